@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use nss_lying::{Config, Uid};
+
+fn passwd_entries(c: &mut Criterion) {
+    let config = Config {
+        ranges: vec![Uid(1000)..=Uid(1_000_999)],
+        home_template: "/home/{name}".into(),
+        ..Config::default()
+    };
+
+    c.bench_function("passwd_entries 1M uids", |b| {
+        b.iter(|| config.passwd_entries())
+    });
+}
+
+fn passwd_entry_chunks(c: &mut Criterion) {
+    let config = Config {
+        ranges: vec![Uid(1000)..=Uid(1_000_999)],
+        home_template: "/home/{name}".into(),
+        enumeration_chunk_size: Some(1024),
+        ..Config::default()
+    };
+
+    c.bench_function("passwd_entry_chunks 1M uids, 1024 per chunk", |b| {
+        b.iter(|| {
+            config
+                .passwd_entry_chunks()
+                .map(|chunk| chunk.len())
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = passwd_entries, passwd_entry_chunks
+}
+criterion_main!(benches);
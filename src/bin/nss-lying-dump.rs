@@ -0,0 +1,422 @@
+//! Print the entries the `lying` NSS module would synthesize, so a config can
+//! be checked before the module is installed.
+//!
+//! ```text
+//! nss-lying-dump [--config PATH] [--stats] [COMMAND]
+//!
+//! Commands:
+//!   all           print every passwd and group entry (default)
+//!   passwd [NAME] print every passwd entry, or the one for NAME
+//!   uid UID       print the passwd entry for UID
+//!   group [NAME]  print every group entry, or the one for NAME
+//!   gid GID       print the group entry for GID
+//!   describe      print a summary of the synthesized users
+//!   check         check that the config loads and the module is installed
+//!   metrics       print the lookup counters in Prometheus text format
+//! ```
+//!
+//! With `--stats`, the lookup counters are printed to stderr afterwards.
+//!
+//! The counters are per-process, and this process doesn't go through the
+//! hooks, so it records its own lookups instead: `--stats` only tells whether
+//! the entries the command asked for were found, and `metrics` shows no
+//! lookups at all. Lookups made by other processes that loaded the module are
+//! not visible; both mainly show the formats a long-lived harness gets from
+//! `Stats` and `Stats::to_prometheus`.
+//!
+//! `check` also prints the problems `Config::lint` finds in the config, looks
+//! at `/etc/nsswitch.conf`, whether `libnss_lying.so.2` is in a library
+//! directory, and whether a synthesized user actually resolves through NSS,
+//! printing a hint for each problem. The lookup is always checked against the
+//! config the installed module loads, even with `--config`.
+
+use std::{
+    ffi::{CStr, CString},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context, Result};
+use nss_lying::{
+    group_line, passwd_line,
+    stats::{self, Lookup},
+    Config, Gid, Severity, Uid,
+};
+
+const USAGE: &str = "usage: nss-lying-dump [--config PATH] [--stats] [all | passwd [NAME] | uid UID | group [NAME] | gid GID | describe | check | metrics]";
+
+/// File name under which glibc loads the module
+const MODULE_NAME: &str = "libnss_lying.so.2";
+
+enum Command {
+    All,
+    Passwd(Option<String>),
+    Uid(Uid),
+    Group(Option<String>),
+    Gid(Gid),
+    Describe,
+    Metrics,
+}
+
+/// What to do once the config is loaded
+enum Action {
+    /// Check the config and the installation, see [check]
+    Check,
+    /// Run a command against the config
+    Run(Command),
+}
+
+struct Args {
+    config: Option<PathBuf>,
+    stats: bool,
+    action: Action,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut config = None;
+    let mut stats = false;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                config = Some(args.next().context("--config requires a path")?.into());
+            }
+            "--stats" => stats = true,
+            "-h" | "--help" => bail!("{}", USAGE),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let action = match positional.next().as_deref() {
+        Some("check") => Action::Check,
+        None | Some("all") => Action::Run(Command::All),
+        Some("describe") => Action::Run(Command::Describe),
+        Some("metrics") => Action::Run(Command::Metrics),
+        Some("passwd") => Action::Run(Command::Passwd(positional.next())),
+        Some("group") => Action::Run(Command::Group(positional.next())),
+        Some("uid") => {
+            let uid = positional.next().context("uid requires a UID")?;
+            Action::Run(Command::Uid(Uid(uid
+                .parse()
+                .with_context(|| format!("invalid UID {:?}", uid))?)))
+        }
+        Some("gid") => {
+            let gid = positional.next().context("gid requires a GID")?;
+            Action::Run(Command::Gid(Gid(gid
+                .parse()
+                .with_context(|| format!("invalid GID {:?}", gid))?)))
+        }
+        Some(other) => bail!("unknown command {:?}\n{}", other, USAGE),
+    };
+    if let Some(extra) = positional.next() {
+        bail!("unexpected argument {:?}\n{}", extra, USAGE);
+    }
+
+    Ok(Args {
+        config,
+        stats,
+        action,
+    })
+}
+
+/// Run a command, returning whether the requested entry was found
+fn run(config: &Config, command: Command) -> bool {
+    match command {
+        Command::All => {
+            print_all_passwd(config);
+            print_all_groups(config);
+            true
+        }
+        Command::Passwd(None) => {
+            print_all_passwd(config);
+            true
+        }
+        Command::Group(None) => {
+            print_all_groups(config);
+            true
+        }
+        Command::Passwd(Some(name)) => print_found(
+            Lookup::PasswdByName,
+            config.passwd_by_name(&name).map(|p| passwd_line(&p)),
+        ),
+        Command::Uid(uid) => print_found(
+            Lookup::PasswdByUid,
+            config.uid_to_passwd(uid).map(|p| passwd_line(&p)),
+        ),
+        Command::Group(Some(name)) => print_found(
+            Lookup::GroupByName,
+            config.group_by_name(&name).map(|g| group_line(&g)),
+        ),
+        Command::Gid(gid) => print_found(
+            Lookup::GroupByGid,
+            config.gid_to_group(gid).map(|g| group_line(&g)),
+        ),
+        Command::Describe => {
+            println!("{}", config.describe());
+            true
+        }
+        Command::Metrics => {
+            print!("{}", stats::stats().to_prometheus());
+            true
+        }
+    }
+}
+
+/// Print the result of a check, with a hint on failure, returning whether
+/// it passed
+fn report(name: &str, result: Result<String>, hint: &str) -> bool {
+    match result {
+        Ok(detail) => {
+            println!("ok   {}: {}", name, detail);
+            true
+        }
+        Err(e) => {
+            println!("FAIL {}: {:#}", name, e);
+            println!("     hint: {}", hint);
+            false
+        }
+    }
+}
+
+/// Check that the module is set up to answer lookups, returning whether
+/// every check passed
+///
+/// The live lookup is only attempted if the config loaded, since it needs a
+/// synthesized name to look up. With `--config`, it is made against the
+/// config the installed module loads instead, since that is what answers it.
+fn check(config: Result<Config>, custom_path: bool) -> bool {
+    let config_ok = report(
+        "config",
+        config
+            .as_ref()
+            .map(|config| config.describe())
+            .map_err(|e| anyhow::anyhow!("{:#}", e)),
+        "fix the config file, or remove it to use the defaults",
+    );
+    let lint_ok = match &config {
+        Ok(config) => lint(config),
+        Err(_) => true,
+    };
+    let nsswitch_ok = report(
+        "nsswitch",
+        check_nsswitch(Path::new("/etc/nsswitch.conf")),
+        "add `lying` to the passwd and group lines of /etc/nsswitch.conf",
+    );
+    let module_ok = report(
+        "module",
+        check_module(),
+        &format!(
+            "install the built library as {} in the system library directory",
+            MODULE_NAME
+        ),
+    );
+    let lookup_hint =
+        "check the results above; processes only load the module once nsswitch.conf lists it";
+    let installed = custom_path.then(nss_lying::load_config);
+    let lookup_ok = match (&installed, &config) {
+        (Some(Ok(config)), _) | (None, Ok(config)) => {
+            report("lookup", check_lookup(config), lookup_hint)
+        }
+        (Some(Err(e)), _) => report(
+            "lookup",
+            Err(anyhow::anyhow!(
+                "the config the module loads is invalid: {:#}",
+                e
+            )),
+            lookup_hint,
+        ),
+        (None, Err(_)) => false,
+    };
+    config_ok && lint_ok && nsswitch_ok && module_ok && lookup_ok
+}
+
+/// Print the problems [Config::lint] finds in a loaded config, returning
+/// whether none of them are errors
+fn lint(config: &Config) -> bool {
+    let mut ok = true;
+    for diagnostic in config.lint() {
+        match diagnostic.severity {
+            Severity::Warning => println!("warn lint: {}", diagnostic.message),
+            Severity::Error => {
+                println!("FAIL lint: {}", diagnostic.message);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Check that the passwd and group databases of an nsswitch.conf list the
+/// module
+fn check_nsswitch(path: &Path) -> Result<String> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let missing: Vec<_> = ["passwd", "group"]
+        .into_iter()
+        .filter(|database| !nsswitch_lists(&contents, database, "lying"))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "{} does not list lying for {}",
+            path.display(),
+            missing.join(" and ")
+        );
+    }
+    Ok(format!(
+        "{} lists lying for passwd and group",
+        path.display()
+    ))
+}
+
+/// Check whether the line of `database` in an nsswitch.conf lists `service`
+fn nsswitch_lists(contents: &str, database: &str, service: &str) -> bool {
+    contents.lines().any(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        let Some((name, services)) = line.split_once(':') else {
+            return false;
+        };
+        name.trim() == database
+            && services
+                .split_whitespace()
+                // skip actions such as `[NOTFOUND=return]`
+                .any(|word| word == service)
+    })
+}
+
+/// Check that the module is installed in one of the usual library
+/// directories
+fn check_module() -> Result<String> {
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    let dirs = [
+        PathBuf::from("/lib"),
+        PathBuf::from("/lib64"),
+        PathBuf::from("/usr/lib"),
+        PathBuf::from("/usr/lib64"),
+        Path::new("/lib").join(&multiarch),
+        Path::new("/usr/lib").join(&multiarch),
+    ];
+    dirs.iter()
+        .map(|dir| dir.join(MODULE_NAME))
+        .find(|path| path.exists())
+        .map(|path| format!("found {}", path.display()))
+        .with_context(|| format!("{} not found in the library directories", MODULE_NAME))
+}
+
+/// Check that the first synthesized user resolves through NSS, which means
+/// the module was loaded by this process
+fn check_lookup(config: &Config) -> Result<String> {
+    let uid = config
+        .uid_range()
+        .next()
+        .context("no UIDs are synthesized")?;
+    let name = config
+        .name_for_uid(uid)
+        .context("no name for the first synthesized UID")?;
+    let c_name = CString::new(name.as_str())?;
+    // SAFETY: `c_name` is a valid C string, and the returned entry is only
+    // read before any other lookup in this single-threaded process
+    let passwd = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if passwd.is_null() {
+        bail!("getpwnam({:?}) found no user", name);
+    }
+    // SAFETY: checked for null above
+    let (found_uid, found_name) = unsafe {
+        (
+            Uid((*passwd).pw_uid),
+            CStr::from_ptr((*passwd).pw_name)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    };
+    if found_uid != uid {
+        bail!(
+            "getpwnam({:?}) returned UID {} instead of {}, so another source answered first",
+            name,
+            found_uid,
+            uid
+        );
+    }
+    Ok(format!(
+        "getpwnam({:?}) returned {} ({})",
+        name, found_name, uid
+    ))
+}
+
+fn print_all_passwd(config: &Config) {
+    stats::record(Lookup::PasswdAll, true);
+    config
+        .passwd_entry_chunks()
+        .flatten()
+        .for_each(|p| println!("{}", passwd_line(&p)));
+}
+
+fn print_all_groups(config: &Config) {
+    stats::record(Lookup::GroupAll, true);
+    config
+        .group_entry_chunks()
+        .flatten()
+        .for_each(|g| println!("{}", group_line(&g)));
+}
+
+/// Print the line for a looked up entry, counting the lookup
+fn print_found(lookup: Lookup, line: Option<String>) -> bool {
+    stats::record(lookup, line.is_some());
+    match line {
+        Some(line) => {
+            println!("{}", line);
+            true
+        }
+        None => {
+            eprintln!("not found");
+            false
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(64);
+        }
+    };
+
+    // load the config the same way the module does, but report errors
+    // instead of quietly answering `Unavail`
+    let config = match &args.config {
+        Some(path) => nss_lying::load_config_from(path).and_then(|config| {
+            config.check_shells()?;
+            Ok(config)
+        }),
+        None => nss_lying::load_config(),
+    };
+    let command = match args.action {
+        Action::Check => {
+            return if check(config, args.config.is_some()) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+        Action::Run(command) => command,
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let found = run(&config, command);
+    if args.stats {
+        eprint!("{}", stats::stats());
+    }
+    if found {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(2)
+    }
+}
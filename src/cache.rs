@@ -0,0 +1,274 @@
+//! Bounded LRU cache of synthesized entries
+//!
+//! Synthesizing an entry is cheap, but allocates the strings of the returned
+//! entry every time; a cache saves that for hot lookups such as `ls` of a
+//! directory owned by many synthesized users. Misses are cached as well.
+//!
+//! Entries are kept behind an [Arc], so that a hit only bumps a reference
+//! count while holding the lock. Callers still copy the entry out, since
+//! libnss takes ownership of the entries it is handed, but they do so without
+//! blocking other lookups.
+//!
+//! The hooks only use the cache when the module is built with the `cache`
+//! feature. Since entries depend on the config, the cache is cleared whenever
+//! the config is reloaded.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use libnss::{group::Group, passwd::Passwd};
+
+/// Key of a cached lookup
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// Lookup by UID or GID
+    Id(u32),
+    /// Lookup by user or group name
+    Name(String),
+}
+
+/// A map that evicts its least recently used entry when full
+struct Lru<V> {
+    capacity: usize,
+    /// Value and last use of each key
+    entries: HashMap<Key, (V, u64)>,
+    /// Keys by last use, oldest first
+    order: BTreeMap<u64, Key>,
+    /// Counter standing in for the time of each use
+    tick: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Get a value, marking it as most recently used
+    fn get(&mut self, key: &Key) -> Option<V> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        let key = self.order.remove(used)?;
+        *used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(value.clone())
+    }
+
+    /// Insert a value, evicting the least recently used one if full
+    fn insert(&mut self, key: Key, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Cache of passwd and group lookups, shared between threads
+///
+/// Each database holds up to `capacity` lookups; a capacity of zero disables
+/// caching.
+pub struct LookupCache {
+    passwd: Mutex<Lru<Option<Arc<Passwd>>>>,
+    group: Mutex<Lru<Option<Arc<Group>>>>,
+    /// Number of resets, so that lookups started before a reset aren't
+    /// cached after it
+    generation: AtomicU64,
+}
+
+impl LookupCache {
+    /// Create an empty cache
+    pub fn new(capacity: usize) -> Self {
+        LookupCache {
+            passwd: Mutex::new(Lru::new(capacity)),
+            group: Mutex::new(Lru::new(capacity)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Drop every cached lookup and set a new capacity
+    pub fn reset(&self, capacity: usize) {
+        let mut passwd = lock(&self.passwd);
+        let mut group = lock(&self.group);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *passwd = Lru::new(capacity);
+        *group = Lru::new(capacity);
+    }
+
+    /// Get the current generation, to be passed to [LookupCache::passwd] or
+    /// [LookupCache::group]
+    ///
+    /// Take it before reading the config a lookup depends on, so that the
+    /// lookup isn't cached if the cache was reset for a new config since.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Get a cached passwd lookup, or do it with `lookup` and cache it unless
+    /// the cache was reset since `generation`
+    pub fn passwd(
+        &self,
+        generation: u64,
+        key: Key,
+        lookup: impl FnOnce() -> Option<Passwd>,
+    ) -> Option<Arc<Passwd>> {
+        self.cached(&self.passwd, generation, key, || lookup().map(Arc::new))
+    }
+
+    /// Get a cached group lookup, or do it with `lookup` and cache it unless
+    /// the cache was reset since `generation`
+    pub fn group(
+        &self,
+        generation: u64,
+        key: Key,
+        lookup: impl FnOnce() -> Option<Group>,
+    ) -> Option<Arc<Group>> {
+        self.cached(&self.group, generation, key, || lookup().map(Arc::new))
+    }
+
+    /// Get a cached lookup, or do it with `lookup` and cache it unless the
+    /// cache was reset since `generation`
+    ///
+    /// The lock is released while `lookup` runs, so concurrent misses may both
+    /// do the lookup; they get the same result either way.
+    fn cached<V: Clone>(
+        &self,
+        lru: &Mutex<Lru<V>>,
+        generation: u64,
+        key: Key,
+        lookup: impl FnOnce() -> V,
+    ) -> V {
+        if let Some(value) = lock(lru).get(&key) {
+            return value;
+        }
+        let value = lookup();
+        // resets hold the lock while moving to the next generation
+        let mut lru = lock(lru);
+        if self.generation() == generation {
+            lru.insert(key, value.clone());
+        }
+        value
+    }
+
+    /// Number of cached passwd and group lookups
+    pub fn len(&self) -> usize {
+        lock(&self.passwd).len() + lock(&self.group).len()
+    }
+
+    /// Check whether no lookups are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Debug for LookupCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LookupCache")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lock a mutex, ignoring poisoning since the cache is never left half
+/// updated by a panic
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Get the cache used by the hooks, creating it with `capacity` on first use
+///
+/// Later calls ignore `capacity`; it is updated by [LookupCache::reset] when
+/// the config is reloaded.
+pub fn global(capacity: usize) -> Arc<LookupCache> {
+    static INSTANCE: OnceLock<Arc<LookupCache>> = OnceLock::new();
+    INSTANCE
+        .get_or_init(|| Arc::new(LookupCache::new(capacity)))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.insert(Key::Id(1), "a");
+        lru.insert(Key::Id(2), "b");
+        assert_eq!(lru.get(&Key::Id(1)), Some("a"));
+        lru.insert(Key::Name("c".into()), "c");
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&Key::Id(2)), None);
+        assert_eq!(lru.get(&Key::Id(1)), Some("a"));
+        assert_eq!(lru.get(&Key::Name("c".into())), Some("c"));
+
+        // replacing a value doesn't evict anything
+        lru.insert(Key::Id(1), "d");
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&Key::Id(1)), Some("d"));
+
+        let mut lru = Lru::new(0);
+        lru.insert(Key::Id(1), "a");
+        assert_eq!(lru.get(&Key::Id(1)), None);
+    }
+
+    #[test]
+    fn cached_lookups() {
+        let cache = LookupCache::new(10);
+        let mut calls = 0;
+        for _ in 0..3 {
+            cache.group(cache.generation(), Key::Id(1000), || {
+                calls += 1;
+                None
+            });
+        }
+        assert_eq!(calls, 1);
+        assert_eq!(cache.len(), 1);
+
+        // hits share the cached entry rather than copying it
+        let group = || {
+            Some(Group {
+                name: "user-1001".into(),
+                passwd: "x".into(),
+                gid: 1001,
+                members: Vec::new(),
+            })
+        };
+        let first = cache.group(cache.generation(), Key::Id(1001), group);
+        let second = cache.group(cache.generation(), Key::Id(1001), || None);
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+
+        cache.reset(10);
+        assert!(cache.is_empty());
+
+        // a lookup started before a reset isn't cached after it
+        let generation = cache.generation();
+        cache.group(generation, Key::Id(1000), || {
+            cache.reset(10);
+            None
+        });
+        assert!(cache.is_empty());
+    }
+}
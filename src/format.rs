@@ -0,0 +1,121 @@
+//! Rendering entries as lines of `/etc/passwd` and `/etc/group`
+//!
+//! Config validation already keeps separators out of synthesized fields, but
+//! entries can also be built by hand, so the renderers drop any separator left
+//! in a field rather than emit a line that parses back differently.
+
+use std::borrow::Cow;
+
+use libnss::{group::Group, passwd::Passwd};
+
+/// Drop the characters in `forbidden` from a field
+fn field<'a>(value: &'a str, forbidden: &[char]) -> Cow<'a, str> {
+    if value.contains(forbidden) {
+        Cow::Owned(value.replace(forbidden, ""))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Characters that end a field of a line
+const FIELD_SEPARATORS: [char; 2] = [':', '\n'];
+
+/// Characters that end a member of a group line
+const MEMBER_SEPARATORS: [char; 3] = [':', '\n', ','];
+
+/// Format a passwd entry as a line of `/etc/passwd`, without the newline
+///
+/// ```
+/// use nss_lying::{passwd_line, Config, Uid};
+///
+/// let passwd = Config::default().uid_to_passwd(Uid(1234)).unwrap();
+/// assert_eq!(
+///     passwd_line(&passwd),
+///     "user-1234:x:1234:1234::/tmp:/bin/bash"
+/// );
+/// ```
+pub fn passwd_line(passwd: &Passwd) -> String {
+    format!(
+        "{}:{}:{}:{}:{}:{}:{}",
+        field(&passwd.name, &FIELD_SEPARATORS),
+        field(&passwd.passwd, &FIELD_SEPARATORS),
+        passwd.uid,
+        passwd.gid,
+        field(&passwd.gecos, &FIELD_SEPARATORS),
+        field(&passwd.dir, &FIELD_SEPARATORS),
+        field(&passwd.shell, &FIELD_SEPARATORS),
+    )
+}
+
+/// Format a group entry as a line of `/etc/group`, without the newline
+///
+/// Members are joined with commas.
+pub fn group_line(group: &Group) -> String {
+    let members: Vec<_> = group
+        .members
+        .iter()
+        .map(|member| field(member, &MEMBER_SEPARATORS))
+        .collect();
+    format!(
+        "{}:{}:{}:{}",
+        field(&group.name, &FIELD_SEPARATORS),
+        field(&group.passwd, &FIELD_SEPARATORS),
+        group.gid,
+        members.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwd_lines() {
+        let passwd = Passwd {
+            name: "user-1000".into(),
+            passwd: "x".into(),
+            uid: 1000,
+            gid: 100,
+            gecos: "Daring Fox,,,".into(),
+            dir: "/home/user-1000".into(),
+            shell: "/bin/zsh".into(),
+        };
+        assert_eq!(
+            passwd_line(&passwd),
+            "user-1000:x:1000:100:Daring Fox,,,:/home/user-1000:/bin/zsh"
+        );
+
+        let passwd = Passwd {
+            gecos: "a:b\nc".into(),
+            ..passwd
+        };
+        assert_eq!(
+            passwd_line(&passwd),
+            "user-1000:x:1000:100:abc:/home/user-1000:/bin/zsh"
+        );
+    }
+
+    #[test]
+    fn group_lines() {
+        let group = Group {
+            name: "users".into(),
+            passwd: "x".into(),
+            gid: 100,
+            members: vec!["user-1000".into(), "user-1001".into()],
+        };
+        assert_eq!(group_line(&group), "users:x:100:user-1000,user-1001");
+
+        let group = Group {
+            members: Vec::new(),
+            ..group
+        };
+        assert_eq!(group_line(&group), "users:x:100:");
+
+        let group = Group {
+            name: "us:ers".into(),
+            members: vec!["a,b".into(), "c:d".into()],
+            ..group
+        };
+        assert_eq!(group_line(&group), "users:x:100:ab,cd");
+    }
+}
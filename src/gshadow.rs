@@ -0,0 +1,194 @@
+//! Group shadow hooks, which libnss doesn't provide
+//!
+//! Every synthesized group gets a read-only gshadow entry, so that tools such
+//! as `newgrp` and PAM modules that consult `getsgnam` see the same groups as
+//! `getgrnam`. The entries have no password and no administrators.
+
+#[cfg(feature = "group")]
+use std::{
+    ffi::CStr,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+
+use libc::c_char;
+#[cfg(feature = "group")]
+use libc::{c_int, size_t};
+#[cfg(feature = "group")]
+use libnss::interop::{Iterator, NssStatus, Response};
+use libnss::{
+    group::Group,
+    interop::{CBuffer, ToC},
+};
+
+use crate::Config;
+#[cfg(feature = "group")]
+use crate::{
+    logged, logged_all, resolver,
+    stats::{counted, Lookup},
+    with_resolver, Resolver,
+};
+
+/// A gshadow entry, as in `/etc/gshadow`
+#[derive(Clone)]
+pub struct GroupShadow {
+    /// Group name, the same as in the group entry
+    pub name: String,
+    /// Group password: `*`, or `!` with `shadow_locked`, since synthesized
+    /// groups have none
+    pub passwd: String,
+    /// Users who may change the group's password and members, always empty
+    pub admins: Vec<String>,
+    /// Members of the group, the same as in the group entry
+    pub members: Vec<String>,
+}
+
+/// `struct sgrp` from glibc's `gshadow.h`
+#[repr(C)]
+pub struct Sgrp {
+    name: *mut c_char,
+    passwd: *mut c_char,
+    admins: *mut *mut c_char,
+    members: *mut *mut c_char,
+}
+
+impl ToC<Sgrp> for GroupShadow {
+    unsafe fn to_c(&self, result: *mut Sgrp, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).name = buffer.write_str(&self.name)?;
+        (*result).passwd = buffer.write_str(&self.passwd)?;
+        (*result).admins = buffer.write_strs(&self.admins)?;
+        (*result).members = buffer.write_strs(&self.members)?;
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Synthesize the gshadow entry of a synthesized group
+    ///
+    /// The password is `!` if `shadow_locked` is set and `*` otherwise, so
+    /// that nobody can join the group with `newgrp` by password.
+    pub fn group_to_gshadow(&self, group: Group) -> GroupShadow {
+        GroupShadow {
+            name: group.name,
+            passwd: if self.shadow_locked { "!" } else { "*" }.into(),
+            admins: Vec::new(),
+            members: group.members,
+        }
+    }
+
+    /// Synthesize the gshadow entry for a group name
+    pub fn gshadow_by_name(&self, name: &str) -> Option<GroupShadow> {
+        self.group_by_name(name)
+            .map(|group| self.group_to_gshadow(group))
+    }
+
+    /// Synthesize every gshadow entry, in the order of [Config::group_entries]
+    pub fn gshadow_entries(&self) -> Vec<GroupShadow> {
+        self.group_entries()
+            .into_iter()
+            .map(|group| self.group_to_gshadow(group))
+            .collect()
+    }
+}
+
+/// Lock the entries being enumerated by [_nss_lying_getsgent_r]
+#[cfg(feature = "group")]
+fn lock_entries() -> MutexGuard<'static, Iterator<GroupShadow>> {
+    static ENTRIES: OnceLock<Mutex<Iterator<GroupShadow>>> = OnceLock::new();
+    ENTRIES
+        .get_or_init(|| Mutex::new(Iterator::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Look up a gshadow entry by group name
+///
+/// # Safety
+///
+/// Called by glibc with a valid name, result, and a buffer of `buflen` bytes.
+#[cfg(feature = "group")]
+#[no_mangle]
+pub unsafe extern "C" fn _nss_lying_getsgnam_r(
+    name: *const c_char,
+    result: *mut Sgrp,
+    buffer: *mut c_char,
+    buflen: size_t,
+    errnop: *mut c_int,
+) -> c_int {
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return NssStatus::NotFound as c_int;
+    };
+    gshadow_by_name(resolver(), name).to_c(result, buffer, buflen, errnop) as c_int
+}
+
+/// Answer [_nss_lying_getsgnam_r] with `resolver`, counting the lookup
+#[cfg(feature = "group")]
+pub(crate) fn gshadow_by_name(resolver: Option<&Resolver>, name: &str) -> Response<GroupShadow> {
+    counted(
+        Lookup::GshadowByName,
+        logged(
+            "gshadow::get_entry_by_name",
+            &name,
+            with_resolver(resolver, |resolver| {
+                resolver
+                    .config()
+                    .name_outcome(name, resolver.gshadow_by_name(name))
+                    .into_response()
+            }),
+        ),
+    )
+}
+
+/// Get the entries for [_nss_lying_setsgent] from `resolver`, counting the
+/// lookup
+#[cfg(feature = "group")]
+pub(crate) fn all_gshadow(resolver: Option<&Resolver>) -> Response<Vec<GroupShadow>> {
+    counted(
+        Lookup::GshadowAll,
+        logged_all(
+            "gshadow::get_all_entries",
+            with_resolver(resolver, |resolver| {
+                Response::Success(resolver.all_gshadow())
+            }),
+        ),
+    )
+}
+
+/// Start enumerating the gshadow entries
+#[cfg(feature = "group")]
+#[no_mangle]
+pub extern "C" fn _nss_lying_setsgent() -> c_int {
+    let status = match all_gshadow(resolver()) {
+        Response::Success(all) => lock_entries().open(all),
+        response => response.to_status(),
+    };
+    status as c_int
+}
+
+/// Get the next gshadow entry enumerated by [_nss_lying_setsgent]
+///
+/// # Safety
+///
+/// Called by glibc with a valid result and a buffer of `buflen` bytes.
+#[cfg(feature = "group")]
+#[no_mangle]
+pub unsafe extern "C" fn _nss_lying_getsgent_r(
+    result: *mut Sgrp,
+    buffer: *mut c_char,
+    buflen: size_t,
+    errnop: *mut c_int,
+) -> c_int {
+    let mut entries = lock_entries();
+    let status = entries.next().to_c(result, buffer, buflen, errnop);
+    // retry the same entry once glibc grows the buffer
+    if status == NssStatus::TryAgain {
+        entries.previous();
+    }
+    status as c_int
+}
+
+/// Stop enumerating the gshadow entries
+#[cfg(feature = "group")]
+#[no_mangle]
+pub extern "C" fn _nss_lying_endsgent() -> c_int {
+    lock_entries().close() as c_int
+}
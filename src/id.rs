@@ -0,0 +1,127 @@
+//! Distinct types for UIDs and GIDs
+//!
+//! `uid_t` and `gid_t` are both `u32`, so passing one where the other is
+//! expected compiles silently. UIDs and GIDs are carried as [Uid] and [Gid]
+//! through [Config](crate::Config) and its public API instead, and only
+//! converted to the libc types where entries are handed to libnss. Turning
+//! one into the other takes [Uid::per_user_gid] or [Gid::owner_uid].
+
+use std::{fmt, ops::RangeInclusive};
+
+use serde::Deserialize;
+
+/// A user ID
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Uid(pub libc::uid_t);
+
+/// A group ID
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Gid(pub libc::gid_t);
+
+impl Uid {
+    /// The largest UID
+    pub const MAX: Uid = Uid(libc::uid_t::MAX);
+
+    /// Get the per-user GID of this UID, `offset` above it
+    ///
+    /// The arithmetic is done in `i64` so that neither the UID nor the offset
+    /// can wrap. Returns [None] if the GID would be out of bounds.
+    pub fn per_user_gid(self, offset: i64) -> Option<Gid> {
+        let gid = i64::from(self.0).checked_add(offset)?;
+        libc::gid_t::try_from(gid).ok().map(Gid)
+    }
+
+    /// Iterate over the UIDs of a range, which `RangeInclusive<Uid>` can't do
+    /// itself
+    pub fn iter(range: &RangeInclusive<Uid>) -> impl DoubleEndedIterator<Item = Uid> {
+        (range.start().0..=range.end().0).map(Uid)
+    }
+}
+
+impl Gid {
+    /// The largest GID
+    pub const MAX: Gid = Gid(libc::gid_t::MAX);
+
+    /// Get the UID owning this per-user GID, the inverse of
+    /// [Uid::per_user_gid]
+    ///
+    /// Returns [None] if the UID would be out of bounds.
+    pub fn owner_uid(self, offset: i64) -> Option<Uid> {
+        let uid = i64::from(self.0).checked_sub(offset)?;
+        libc::uid_t::try_from(uid).ok().map(Uid)
+    }
+}
+
+impl From<libc::uid_t> for Uid {
+    fn from(uid: libc::uid_t) -> Self {
+        Uid(uid)
+    }
+}
+
+impl From<Uid> for libc::uid_t {
+    fn from(uid: Uid) -> Self {
+        uid.0
+    }
+}
+
+impl From<libc::gid_t> for Gid {
+    fn from(gid: libc::gid_t) -> Self {
+        Gid(gid)
+    }
+}
+
+impl From<Gid> for libc::gid_t {
+    fn from(gid: Gid) -> Self {
+        gid.0
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Gid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_user_gid_roundtrip() {
+        assert_eq!(Uid(1000).per_user_gid(0), Some(Gid(1000)));
+        assert_eq!(Uid(1000).per_user_gid(-500), Some(Gid(500)));
+        assert_eq!(Gid(500).owner_uid(-500), Some(Uid(1000)));
+        assert_eq!(Uid(10).per_user_gid(-11), None);
+        assert_eq!(Uid::MAX.per_user_gid(1), None);
+        assert_eq!(Gid::MAX.owner_uid(-1), None);
+        assert_eq!(Gid(0).owner_uid(1), None);
+    }
+
+    #[test]
+    fn iterate_range() {
+        assert_eq!(
+            Uid::iter(&(Uid(5)..=Uid(7))).collect::<Vec<_>>(),
+            [Uid(5), Uid(6), Uid(7)]
+        );
+        assert_eq!(Uid::iter(&(Uid::MAX..=Uid::MAX)).count(), 1);
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = Uid(7)..=Uid(5);
+        assert_eq!(Uid::iter(&empty).count(), 0);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(libc::uid_t::from(Uid::from(1234)), 1234);
+        assert_eq!(libc::gid_t::from(Gid::from(1234)), 1234);
+        assert_eq!(Uid(1234).to_string(), "1234");
+        assert_eq!(Gid(100).to_string(), "100");
+    }
+}
@@ -1,271 +1,8009 @@
+//! NSS module that synthesizes users and groups for a range of UIDs, so that
+//! arbitrary UIDs resolve to a name without being listed anywhere.
+//!
+//! The module itself is loaded by glibc as `libnss_lying.so.2`; the mapping
+//! logic is also usable directly through [Config].
+
 // necessary because libnss macros are badly implemented
+#[cfg(any(feature = "passwd", feature = "group"))]
 #[macro_use]
 extern crate lazy_static;
 
-use std::{ops::RangeBounds, str::FromStr, sync::OnceLock};
+pub mod cache;
+mod format;
+mod gshadow;
+mod id;
+mod lint;
+mod logging;
+mod name_scheme;
+mod netgroup;
+mod resolver;
+#[cfg(test)]
+mod snapshot;
+pub mod stats;
+mod system;
+mod template;
+mod words;
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Read, Seek},
+    ops::RangeInclusive,
+    os::{
+        fd::{BorrowedFd, RawFd},
+        unix::fs::{DirBuilderExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use libnss::{group::Group, interop::Response, passwd::Passwd, shadow::Shadow};
+#[cfg(feature = "group")]
 use libnss::{
-    group::{Group, GroupHooks},
-    interop::Response,
-    libnss_group_hooks, libnss_passwd_hooks,
-    passwd::{Passwd, PasswdHooks},
+    group::GroupHooks, initgroups::InitgroupsHooks, libnss_group_hooks, libnss_initgroups_hooks,
 };
+#[cfg(feature = "passwd")]
+use libnss::{libnss_passwd_hooks, libnss_shadow_hooks, passwd::PasswdHooks, shadow::ShadowHooks};
+use log::{debug, info, warn};
+use serde::Deserialize;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+pub use format::{group_line, passwd_line};
+pub use gshadow::GroupShadow;
+pub use id::{Gid, Uid};
+pub use lint::{Diagnostic, Severity};
+pub use name_scheme::{CustomNameScheme, NamePattern, NameScheme, PrefixScheme};
+pub use resolver::Resolver;
+#[cfg(any(feature = "passwd", feature = "group"))]
+use stats::{counted, Lookup};
+pub use system::{PasswdDb, SystemPasswd};
+
+/// Location of the configuration file
+const CONFIG_PATH: &str = "/etc/nss_lying.toml";
+
+/// Name of the systemd credential holding the config, see [load_config]
+const CREDENTIAL_NAME: &str = "nss_lying.conf";
+
+/// Configuration for which users and groups to synthesize, and how
+///
+/// The methods on [Config] map between UIDs/GIDs, names, and entries. Names
+/// and IDs are in bijection: every synthesized UID has exactly one name, and
+/// [Config::uid_from_name] only accepts names produced by
+/// [Config::name_for_uid] (and likewise for groups), so
+///
+/// ```
+/// # use nss_lying::{Config, Gid, Uid};
+/// let config: Config = "ranges = [{ start = 1000, end = 1999 }]".parse()?;
+///
+/// let name = config.name_for_uid(Uid(1234)).unwrap();
+/// assert_eq!(name, "user-1234");
+/// assert_eq!(config.uid_from_name(&name), Some(Uid(1234)));
+///
+/// let passwd = config.passwd_by_name(&name).unwrap();
+/// assert_eq!(passwd.uid, 1234);
+/// assert_eq!(config.name_for_gid(Gid(passwd.gid)).as_deref(), Some("user-1234"));
+///
+/// assert_eq!(config.name_for_uid(Uid(2000)), None);
+/// assert_eq!(config.uid_from_name("user-01234"), None);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// Some options accept more names than that, so that several names map to
+/// one UID while entries keep the canonical one: [Config::aliases],
+/// `case_insensitive_names`, and `claim_prefix_names_in_gaps`. The mapping
+/// from UIDs to names stays injective, but a lookup by name may then return
+/// an entry under a different name than the one asked for.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
 pub struct Config {
-    /// Minimum UID to synthesize, inclusive
-    pub uid_min: libc::uid_t,
-    /// Maximum UID to synthesize, inclusive
-    pub uid_max: libc::uid_t,
+    /// Inclusive ranges of UIDs to synthesize
+    ///
+    /// Written as `ranges = [{ start = 1000, end = 1999 }]` in the config file.
+    pub ranges: Vec<RangeInclusive<Uid>>,
+
+    /// UIDs within `ranges` that are not synthesized, e.g. because they are
+    /// managed elsewhere
+    #[serde(default)]
+    pub exclude_uids: ExcludedUids,
+
+    /// Inclusive sub-ranges of UIDs within `ranges` that are not synthesized,
+    /// like `exclude_uids`
+    ///
+    /// Written as `exclude_ranges = [{ start = 1500, end = 1999 }]` in the
+    /// config file. Excluded ranges may overlap each other.
+    #[serde(default)]
+    pub exclude_ranges: Vec<RangeInclusive<Uid>>,
+
+    /// If set, synthesize only these UIDs rather than every UID in `ranges`
+    ///
+    /// Each listed UID must still be within `ranges`, and exclusions still
+    /// apply. Users are enumerated in UID order.
+    #[serde(default)]
+    pub allow_uids: Option<AllowedUids>,
+
+    /// If set, never synthesize UIDs below `reserved_below`, even if they are
+    /// within `ranges`, nor the `nobody` user with such a UID
+    ///
+    /// This is a safety floor against misconfigured ranges shadowing system
+    /// accounts such as root, so it is on by default.
+    #[serde(default = "default_forbid_reserved")]
+    pub forbid_reserved: bool,
+
+    /// First UID that is not reserved for system accounts, `1000` by default
+    #[serde(default = "default_reserved_below")]
+    pub reserved_below: Uid,
+
+    /// If set, don't synthesize users whose UID or name already exists in
+    /// the other sources of the passwd database, making the module a fallback
+    /// rather than an override
+    ///
+    /// Lookups by UID and name of users, their shadow entries, and
+    /// `initgroups` answer `NotFound` for such users; a user is also skipped
+    /// if the other sources fail to answer. Checking means an extra lookup
+    /// through NSS for every synthesized user, during which this module
+    /// answers `Unavail` to avoid recursing into itself. Enumeration isn't
+    /// filtered, since that would check every UID in the range; combine with
+    /// `enumerable = false` to avoid listing users twice.
+    #[serde(default)]
+    pub defer_to_real: bool,
+
+    /// Maximum number of UIDs across all of `ranges`, `1000000` by default
+    ///
+    /// Enumerating the users builds every entry in memory inside the calling
+    /// process, so a typo such as a range ending at `4000000000` could make
+    /// e.g. `sshd` run out of memory. Configs with larger ranges are rejected
+    /// when loaded, and enumeration stops after this many UIDs if validation
+    /// is bypassed. `0` disables the limit.
+    #[serde(default = "default_max_range_size")]
+    pub max_range_size: u64,
+
+    /// Whether enumerating the passwd, group, and shadow databases lists the
+    /// synthesized entries, `true` by default
+    ///
+    /// If unset, e.g. `getent passwd` returns no synthesized users, but
+    /// lookups of single users and groups by ID or name still succeed. This
+    /// keeps the synthesized accounts out of listings without breaking
+    /// anything that resolves them one at a time.
+    #[serde(default = "default_enumerable")]
+    pub enumerable: bool,
+
+    /// Order in which enumerating the passwd, group, and shadow databases
+    /// lists the synthesized entries, ascending by ID by default
+    ///
+    /// Written as `enumeration_order = "descending"` in the config file.
+    #[serde(default)]
+    pub enumeration_order: Order,
+
+    /// Number of entries to synthesize at a time when enumerating through
+    /// [Config::passwd_entry_chunks] and [Config::group_entry_chunks], as
+    /// `nss-lying-dump` does, or all of them at once if unset
+    ///
+    /// The module's own enumeration can't be chunked: libnss asks for every
+    /// entry when `setpwent` is called and hands them out of its own buffer
+    /// on each `getpwent_r`, so the whole range is synthesized regardless.
+    #[serde(default)]
+    pub enumeration_chunk_size: Option<usize>,
 
     /// If set, all users have this group as their primary group. If unset,
     /// each user gets their own corresponding primary group of the same name.
-    pub user_group: Option<libc::gid_t>,
+    ///
+    /// The GID must not be one that a synthesized user would get as their
+    /// per-user group, since the same GID would then name different groups
+    /// depending on this setting. Such configs are rejected when loaded; if
+    /// validation is bypassed, the shared group takes precedence.
+    ///
+    /// In the config file, the group may also be given by name, e.g.
+    /// `user_group = "users"`, which is resolved against the system's group
+    /// database when the config is loaded.
+    #[serde(default, deserialize_with = "deserialize_user_group")]
+    pub user_group: Option<Gid>,
+
+    /// Offset added to each UID to get its per-user GID when `user_group` is
+    /// unset, e.g. `1000` to number user-1000's group 2000
+    #[serde(default)]
+    pub gid_offset: i64,
+
+    /// Primary GID of users whose per-user GID is out of bounds after
+    /// applying `gid_offset`
+    ///
+    /// If unset, such users are not synthesized at all. The GID is subject
+    /// to the same collision check as `user_group`.
+    #[serde(default)]
+    pub fallback_gid: Option<Gid>,
+
+    /// Name of the shared group when `user_group` is set, `users` if unset
+    #[serde(default)]
+    pub shared_group_name: Option<String>,
 
-    /// Shell to set for each user
+    /// Template for the name of the shared group when `user_group` is set,
+    /// e.g. `group-{gid}` to mirror the per-user naming scheme
+    ///
+    /// Supports the `{gid}` placeholder. Mutually exclusive with
+    /// `shared_group_name`.
+    #[serde(default)]
+    pub shared_group_template: Option<String>,
+
+    /// If set, also resolve each user's per-user group name to the shared
+    /// group when `user_group` is set, for tools that assume every user has a
+    /// group of their own name
+    ///
+    /// Only the lookup by name is affected: the entry returned, like the one
+    /// for the shared GID, always carries the shared group's name. Has no
+    /// effect when `user_group` is unset.
+    #[serde(default)]
+    pub also_per_user_group_names: bool,
+
+    /// How primary groups are named and numbered, as an alternative to
+    /// `user_group`, `shared_group_name`, and `shared_group_template`
+    ///
+    /// Written as `group_naming = "per_user"`, or as `group_naming = { shared
+    /// = { name = "users", gid = 100 } }` in the config file. Mutually
+    /// exclusive with the other three options; if unset, the policy is
+    /// derived from them, see [Config::group_naming].
+    #[serde(default)]
+    pub group_naming: Option<GroupNaming>,
+
+    /// How users are assigned their primary group, as an alternative to
+    /// `user_group`
+    ///
+    /// Written as `primary_gid_strategy = "per_user"`, `primary_gid_strategy
+    /// = { shared = 100 }`, or `primary_gid_strategy = { round_robin = [100,
+    /// 101] }` in the config file. Mutually exclusive with `user_group` and
+    /// `group_naming`; if unset, the strategy is derived from them, see
+    /// [Config::primary_gid_strategy].
+    #[serde(default)]
+    pub primary_gid_strategy: Option<PrimaryGidStrategy>,
+
+    /// If set, also synthesize each user's per-user group when `user_group`
+    /// is set, as a supplementary group of that user
+    ///
+    /// Users keep the shared group as their primary group, but their
+    /// per-user groups resolve by GID and name, are enumerated, and are
+    /// returned by `initgroups`. Has no effect when `user_group` is unset,
+    /// and can't be combined with `also_per_user_group_names`, which gives
+    /// the per-user group names to the shared group instead.
+    ///
+    /// If a per-user GID equals the shared GID, which is rejected when the
+    /// config is loaded, the shared group wins: the GID resolves to it, and
+    /// that user has no per-user group.
+    #[serde(default)]
+    pub enumerate_per_user_groups: bool,
+
+    /// If set, list every synthesized user as a member of the shared group
+    /// when `user_group` is set
+    ///
+    /// Off by default, since the member list grows with the size of the range.
+    #[serde(default)]
+    pub populate_members: bool,
+
+    /// Maximum number of members listed by `populate_members`, `0` for no
+    /// limit, the default
+    ///
+    /// Also applies to the members [Config::member_map] adds to a group.
+    /// Above this many members, the group is returned with no members at
+    /// all, with a warning, rather than a truncated list that
+    /// would look authoritative. glibc answers `ERANGE` from a module by
+    /// retrying with a doubled buffer, so a member list megabytes long makes
+    /// every lookup of the group synthesize it several times over, and callers
+    /// with a fixed size buffer fail outright.
+    #[serde(default)]
+    pub max_members: usize,
+
+    /// Maximum length of a synthesized passwd line, checked when the config
+    /// is loaded
+    ///
+    /// glibc looks entries up into a buffer of `sysconf(_SC_GETPW_R_SIZE_MAX)`
+    /// bytes, usually 1024, and only retries with a larger one on `ERANGE`,
+    /// which some callers don't do at all. The check renders the entry of the
+    /// largest UID and of each override with the longest configured shell, so
+    /// long home or GECOS templates fail loading instead of lookups. This is a
+    /// heuristic: other fields are assumed to be longest for the largest UID,
+    /// which doesn't hold for e.g. aliases. `0` disables the check.
+    #[serde(default = "default_max_line_len")]
+    pub max_line_len: usize,
+
+    /// If set, list each user as the single member of their own group when
+    /// `user_group` is unset
+    ///
+    /// Users are implicitly members of their primary group either way, but
+    /// some tools only look at the member list.
+    #[serde(default)]
+    pub list_owner_as_member: bool,
+
+    /// Supplementary groups every synthesized user is a member of
+    #[serde(default)]
+    pub supplementary_gids: Vec<Gid>,
+
+    /// Supplementary groups of specific synthesized users, in addition to
+    /// `supplementary_gids`
+    ///
+    /// Written as e.g. `[member_map]` followed by `1005 = [27, 100]` in the
+    /// config file. The groups are returned by `initgroups`, and the users
+    /// are listed as members of those groups that the module synthesizes,
+    /// such as the shared group. Other groups are left to the module that
+    /// owns them. Mapped UIDs must be within `ranges`.
+    #[serde(default)]
+    pub member_map: MemberMap,
+
+    /// Shell to set for each user not matched by `shell_rules` or
+    /// `shell_rollout`
+    #[serde(default = "default_shell")]
     pub shell: String,
-}
 
-impl Config {
-    /// Get a [RangeBounds] representing the UID range
-    fn uid_range(&self) -> impl RangeBounds<libc::uid_t> + Iterator<Item = libc::uid_t> {
-        self.uid_min..=self.uid_max
-    }
+    /// If set, give a percentage of the users not matched by `shell_rules`
+    /// another shell, e.g. to migrate them gradually
+    ///
+    /// Written as `shell_rollout = { percent = 10, shell = "/bin/rbash" }`
+    /// in the config file.
+    #[serde(default)]
+    pub shell_rollout: Option<ShellRollout>,
 
-    /// Get the username for a UID, if it is in range
+    /// Shells for UID sub-ranges, evaluated in order; the first rule whose
+    /// range contains a UID wins
     ///
-    /// Returns [None] when the UID is not within range
-    fn name_for_uid(&self, uid: libc::uid_t) -> Option<String> {
-        self.uid_range()
-            .contains(&uid)
-            .then(|| format!("user-{}", uid))
-    }
+    /// Written as `shell_rules = [[{ start = 60000, end = 65000 },
+    /// "/usr/sbin/nologin"]]` in the config file.
+    #[serde(default)]
+    pub shell_rules: Vec<(RangeInclusive<Uid>, String)>,
 
-    /// Extract a UID from a username
+    /// If set, fail to load the config when a shell doesn't exist or isn't
+    /// executable, instead of only logging a warning
     ///
-    /// Returns [None] when the UID is not synthesized by the module
-    fn uid_from_name(&self, name: &str) -> Option<libc::uid_t> {
-        let suffix = name.strip_prefix("user-")?;
-        let uid = libc::uid_t::from_str(suffix).ok()?;
-        self.uid_range().contains(&uid).then_some(uid)
-    }
+    /// Off by default, since the module may be loaded in e.g. a chroot where
+    /// the shell only exists later.
+    #[serde(default)]
+    pub strict_shell: bool,
 
-    /// Get the primary GID for a UID, if it is in range
+    /// If set, fail to load the config when a string field such as `shell`
+    /// or `name_prefix` has leading or trailing whitespace, instead of
+    /// trimming it with a warning
     ///
-    /// Returns [None] when the UID is not within range
-    fn gid_for_uid(&self, uid: libc::uid_t) -> Option<libc::gid_t> {
-        self.uid_range()
-            .contains(&uid)
-            .then_some(match self.user_group {
-                None => uid,
-                Some(gid) => gid,
-            })
-    }
+    /// Either way, fields containing ':', newlines, or NUL are rejected,
+    /// since they would corrupt the entries.
+    #[serde(default)]
+    pub strict_fields: bool,
 
-    /// Get the name for a GID, if it is synthesized by this module
+    /// Prefix for synthesized usernames, followed by the decimal UID
     ///
-    /// Returns [None] when the GID is not synthesized by this module
-    fn name_for_gid(&self, gid: libc::gid_t) -> Option<String> {
-        match self.user_group {
-            None => {
-                // no `user_group` set, users have their own groups
+    /// An empty prefix makes usernames just the decimal UID.
+    #[serde(default = "default_name_prefix")]
+    pub name_prefix: String,
 
-                // lookup the name for the corresponding UID (will return
-                // `None` if out of range)
-                self.name_for_uid(gid)
-            }
-            Some(user_gid) => {
-                // user group is set, return fixed name
-                (gid == user_gid).then(|| "users".into())
-            }
+    /// Minimum width of the UID in usernames, padded with leading zeros
+    ///
+    /// With a width of 5, UID 1000 is named `user-01000`. Zero disables
+    /// padding.
+    #[serde(default)]
+    pub name_pad_width: usize,
+
+    /// Base of the UID in usernames: 8, 10 (the default), or 16
+    ///
+    /// Hexadecimal digits are lowercase, so with a radix of 16 UID 1000 is
+    /// named `user-3e8`.
+    #[serde(default = "default_name_radix")]
+    pub name_radix: u32,
+
+    /// Custom scheme for generating usernames, replacing `name_prefix`,
+    /// `name_pad_width`, and `name_radix`
+    ///
+    /// Only settable through the API, e.g. to name UIDs after an external
+    /// directory.
+    #[serde(skip)]
+    pub name_scheme: Option<CustomNameScheme>,
+
+    /// If set, a regular expression that a generated username being looked
+    /// up must match as a whole, e.g. `user-[1-9][0-9]{3}`
+    ///
+    /// This is checked before parsing the name, so the module doesn't claim
+    /// names outside of the pattern that another module may own. Generated
+    /// names that don't match it can't be looked up by name. Names set by
+    /// `overrides` are not checked.
+    #[serde(default)]
+    pub name_pattern: Option<NamePattern>,
+
+    /// If set, match usernames and group names case-insensitively (ASCII
+    /// only) in lookups by name, e.g. resolving `USER-1000`
+    ///
+    /// Entries are still returned with their canonical names.
+    #[serde(default)]
+    pub case_insensitive_names: bool,
+
+    /// If set, claim every generated-form name, including those whose UID
+    /// is outside every range, e.g. `user-5000` between the ranges
+    /// `1000..=1999` and `8000..=8999`
+    ///
+    /// Such a name resolves to the entry of the nearest UID in a range,
+    /// under that UID's own name, the lower one on a tie. Nothing is
+    /// returned if that UID is excluded. Off by default, so that only the
+    /// names of UIDs within a range resolve and other modules can answer
+    /// for the rest.
+    ///
+    /// This breaks the bijection between names and UIDs: with the range
+    /// `1000..=1999`, `getpwnam("user-2000")` returns the entry of UID 1999
+    /// with `pw_name` `user-1999`. Callers that compare the returned
+    /// `pw_name` with the name they asked for will see a mismatch.
+    #[serde(default)]
+    pub claim_prefix_names_in_gaps: bool,
+
+    /// Template for each user's home directory
+    ///
+    /// Supports the `{uid}`, `{name}` and `{bucket}` placeholders, e.g.
+    /// `/home/{bucket}/{name}`.
+    #[serde(default = "default_home_template")]
+    pub home_template: String,
+
+    /// Number of buckets to shard home directories into
+    ///
+    /// The `{bucket}` placeholder of [Config::home_template] expands to
+    /// `uid % home_bucket_count`. Zero or one disables bucketing, in which
+    /// case `{bucket}` is always `0`.
+    #[serde(default)]
+    pub home_bucket_count: libc::uid_t,
+
+    /// If set, create a synthesized user's home directory when they are
+    /// looked up by UID or name, owned by them with mode `0700`
+    ///
+    /// Missing parent directories are created as well. Failures are logged
+    /// and don't fail the lookup. Since lookups happen in arbitrary processes,
+    /// this only works from processes allowed to create the directory and
+    /// change its owner, typically root; it is off by default.
+    #[serde(default)]
+    pub create_home: bool,
+
+    /// Template for the full name in each user's GECOS field, the first of
+    /// its comma-separated components
+    ///
+    /// Supports the `{uid}` and `{name}` placeholders, e.g.
+    /// `Synthetic User {uid}`, as do the templates for the other components.
+    /// `{name_titlecase}` is the name split into words at `-`, `_`, and `.`,
+    /// each capitalized, e.g. `User 1000` for `user-1000`. All of them are
+    /// empty by default. Also accepted as `gecos_template`.
+    #[serde(default, alias = "gecos_template")]
+    pub gecos_name: String,
+
+    /// How to generate the full name in each user's GECOS field, instead of
+    /// `gecos_name`
+    ///
+    /// Written as e.g. `gecos_style = "word_list"` or
+    /// `gecos_style = { template = "Synthetic User {uid}" }` in the config
+    /// file. Defaults to `components`, which takes the full name from
+    /// `gecos_name`; the other styles can't be combined with a non-empty
+    /// `gecos_name`.
+    #[serde(default)]
+    pub gecos_style: GecosStyle,
+
+    /// Template for the room number in each user's GECOS field
+    #[serde(default)]
+    pub gecos_room: String,
+
+    /// Template for the work phone number in each user's GECOS field
+    #[serde(default)]
+    pub gecos_work_phone: String,
+
+    /// Template for the home phone number in each user's GECOS field
+    #[serde(default)]
+    pub gecos_home_phone: String,
+
+    /// Template for the last, free-form component of each user's GECOS field
+    #[serde(default)]
+    pub gecos_other: String,
+
+    /// Password field for each passwd entry, and each group entry unless
+    /// `group_passwd_field` is set
+    ///
+    /// Defaults to `x`, deferring to shadow. Set to `*` to explicitly deny
+    /// password authentication on systems without shadow.
+    #[serde(default = "default_passwd_field")]
+    pub passwd_field: String,
+
+    /// Password field for each group entry, if different from `passwd_field`
+    #[serde(default)]
+    pub group_passwd_field: Option<String>,
+
+    /// Password field for each user's shadow entry
+    ///
+    /// Defaults to `*`, which matches no password.
+    #[serde(default = "default_shadow_passwd")]
+    pub shadow_passwd: String,
+
+    /// If set, mark each user's shadow entry as locked by prefixing the
+    /// password field with `!`, as `passwd -l` does
+    #[serde(default)]
+    pub shadow_locked: bool,
+
+    /// If set, reload the config file when the process receives SIGHUP
+    ///
+    /// This installs a signal handler in every process that loads the module.
+    /// Handlers the process installs itself still run, but processes that
+    /// rely on the default action of SIGHUP (terminating) will no longer be
+    /// terminated by it, so this is off by default.
+    #[serde(default)]
+    pub reload_on_sighup: bool,
+
+    /// Number of passwd and of group lookups to cache, `1024` by default
+    ///
+    /// Only used if the module is built with the `cache` feature. Lookups are
+    /// cached by UID, GID, and name, including misses; the cache is cleared
+    /// when the config is reloaded. `0` disables caching.
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: usize,
+
+    /// If set, also synthesize a single fixed "nobody"-style user outside of
+    /// `ranges`
+    #[serde(default)]
+    pub nobody: Option<NobodyConfig>,
+
+    /// If set, expose every synthesized user as a member of a netgroup of
+    /// this name, e.g. `synth-users`
+    #[serde(default)]
+    pub netgroup: Option<String>,
+
+    /// Fields to replace in the generated passwd entries of specific UIDs
+    ///
+    /// Written as e.g. `[overrides.1005]` followed by `shell = "/bin/zsh"` in
+    /// the config file. Overridden UIDs must still be within `ranges`.
+    #[serde(default)]
+    pub overrides: Overrides,
+
+    /// Extra names that resolve to the entries of specific UIDs, e.g. for
+    /// tooling that refers to a synthesized user as `deploy`
+    ///
+    /// Written as e.g. `[aliases]` followed by `deploy = 1005` in the config
+    /// file. Aliases are only resolvable: looking one up returns the entry
+    /// of its UID under the UID's own name, and enumeration doesn't list
+    /// them. Their UIDs must be synthesized, and they must not collide with
+    /// any other name.
+    #[serde(default)]
+    pub aliases: Aliases,
+
+    /// Names of the fields in the config file that this version doesn't
+    /// know, sorted
+    ///
+    /// A config written for a newer version still loads, but the fields are
+    /// logged as a warning and reported by [Config::lint], so that typos
+    /// don't go unnoticed.
+    #[serde(flatten, deserialize_with = "deserialize_unknown_fields")]
+    pub unknown_fields: Vec<String>,
+}
+
+/// How primary groups are named, see [Config::group_naming]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupNaming {
+    /// Each user has a group of their own, named after them, with the GID of
+    /// their UID plus `gid_offset`
+    PerUser,
+    /// Every user has the same primary group
+    Shared {
+        /// Name of the group
+        name: String,
+        /// GID of the group
+        gid: Gid,
+    },
+}
+
+/// How users are assigned their primary group, see
+/// [Config::primary_gid_strategy]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrimaryGidStrategy {
+    /// Each user has a group of their own, with the GID of their UID plus
+    /// `gid_offset`
+    PerUser,
+    /// Every user has the same primary group
+    Shared(Gid),
+    /// Users are spread across existing groups by UID, the user with UID
+    /// `uid` getting the GID at index `uid % gids.len()`
+    ///
+    /// The groups are expected to exist in another source, so none are
+    /// synthesized, neither these nor per-user groups.
+    RoundRobin(Vec<Gid>),
+}
+
+/// Order of enumerated entries, see [Config::enumeration_order]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    /// Lowest ID first
+    #[default]
+    Ascending,
+    /// Highest ID first
+    Descending,
+}
+
+impl Order {
+    /// Check whether ID `a` is listed before ID `b` in this order
+    fn lists_before(self, a: u32, b: u32) -> bool {
+        match self {
+            Order::Ascending => a < b,
+            Order::Descending => a > b,
         }
     }
+}
 
-    /// Extract a GID from a username
+/// How to generate the full name in GECOS fields, see [Config::gecos_style]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GecosStyle {
+    /// Expand `gecos_name` like the templates of the other components, which
+    /// leaves the full name empty unless `gecos_name` is set
+    #[default]
+    Components,
+    /// Expand a template with the same placeholders as `gecos_name`
+    Template(String),
+    /// Pick an adjective and a noun from embedded word lists by UID, e.g.
+    /// `Daring Fox`
     ///
-    /// Returns [None] when the GID is not synthesized by the module
-    fn gid_from_name(&self, name: &str) -> Option<libc::gid_t> {
-        match self.user_group {
-            None => {
-                // no `user_group` set, users have their own groups
-                self.uid_from_name(name)
-            }
-            Some(user_gid) => {
-                // user group is set, return fixed name
-                (name == "users").then_some(user_gid)
-            }
-        }
+    /// The same UID always gets the same name, on every host, but names
+    /// repeat within large ranges.
+    WordList,
+}
+
+/// An alternate shell for a percentage of users, see [Config::shell_rollout]
+///
+/// UIDs are selected by `uid % 100 < percent`, so a UID's shell is the same on
+/// every host and only changes when `percent` crosses it; raising `percent`
+/// only ever moves more users to the new shell.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ShellRollout {
+    /// Percentage of UIDs that get `shell`, from 0 to 100
+    pub percent: u8,
+    /// Shell for the selected UIDs
+    pub shell: String,
+}
+
+impl ShellRollout {
+    /// Check whether a UID is selected for the rollout
+    pub fn selects(&self, uid: Uid) -> bool {
+        uid.0 % 100 < libc::uid_t::from(self.percent)
     }
+}
 
-    fn uid_to_passwd(&self, uid: libc::uid_t) -> Option<Passwd> {
-        Some(Passwd {
-            name: self.name_for_uid(uid)?,
-            passwd: "x".into(),
-            uid,
-            gid: self.gid_for_uid(uid)?,
-            gecos: "".into(),
-            dir: "/tmp".into(), // XXX: maybe something smarter is possible?
-            shell: self.shell.clone(),
-        })
+/// Fields of a generated passwd entry to replace, see [Config::overrides]
+///
+/// Unset fields keep their generated value.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct PasswdOverride {
+    /// Username, which replaces the generated name everywhere, including in
+    /// lookups by name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Primary GID
+    #[serde(default)]
+    pub gid: Option<Gid>,
+    /// GECOS field
+    #[serde(default)]
+    pub gecos: Option<String>,
+    /// Home directory
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Shell
+    #[serde(default)]
+    pub shell: Option<String>,
+}
+
+/// The UIDs of [Config::allow_uids]
+///
+/// Dereferences to the UIDs, which are sorted and deduplicated when built, so
+/// that checking whether a UID is allowed is a binary search.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllowedUids(Vec<Uid>);
+
+impl AllowedUids {
+    /// Check whether a UID is allowed
+    fn contains(&self, uid: Uid) -> bool {
+        self.0.binary_search(&uid).is_ok()
     }
+}
 
-    fn gid_to_group(&self, gid: libc::gid_t) -> Option<Group> {
-        Some(Group {
-            name: self.name_for_gid(gid)?,
-            passwd: "x".into(),
-            gid,
-            members: Vec::new(),
-        })
+impl From<Vec<Uid>> for AllowedUids {
+    fn from(mut uids: Vec<Uid>) -> Self {
+        uids.sort_unstable();
+        uids.dedup();
+        AllowedUids(uids)
     }
 }
 
-/// Utility to turn `Some(foo)` to `Success(foo)` and `None` to `NotFound`
-fn option_to_response<T>(o: Option<T>) -> Response<T> {
-    o.map_or(Response::NotFound, Response::Success)
+impl FromIterator<Uid> for AllowedUids {
+    fn from_iter<I: IntoIterator<Item = Uid>>(iter: I) -> Self {
+        AllowedUids::from(iter.into_iter().collect::<Vec<_>>())
+    }
 }
 
-fn load_config() -> Result<Config> {
-    // FIXME: actual config loading
-    Ok(Config {
-        uid_min: 1000,
-        uid_max: 9999,
-        user_group: None,
-        shell: "/bin/bash".into(),
-    })
+impl std::ops::Deref for AllowedUids {
+    type Target = [Uid];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
 }
 
-fn config() -> &'static Config {
-    static INSTANCE: OnceLock<Config> = OnceLock::new();
-    INSTANCE.get_or_init(|| load_config().unwrap())
+impl<'de> Deserialize<'de> for AllowedUids {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(AllowedUids::from)
+    }
 }
 
-struct FakeDb;
+/// The UIDs of [Config::exclude_uids]
+///
+/// Dereferences to the UIDs, which are sorted and deduplicated when built, so
+/// that checking whether a UID is excluded is a binary search.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExcludedUids(Vec<Uid>);
 
-libnss_passwd_hooks!(lying, FakeDb);
-impl PasswdHooks for FakeDb {
-    fn get_all_entries() -> Response<Vec<Passwd>> {
-        let config = config();
-        Response::Success(
-            config
-                .uid_range()
-                .map(|uid| config.uid_to_passwd(uid).unwrap())
-                .collect(),
-        )
+impl ExcludedUids {
+    /// Check whether a UID is excluded
+    fn contains(&self, uid: Uid) -> bool {
+        self.0.binary_search(&uid).is_ok()
     }
+}
 
-    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
-        let config = config();
-        option_to_response(config.uid_to_passwd(uid))
+impl From<Vec<Uid>> for ExcludedUids {
+    fn from(mut uids: Vec<Uid>) -> Self {
+        uids.sort_unstable();
+        uids.dedup();
+        ExcludedUids(uids)
     }
+}
 
-    fn get_entry_by_name(name: String) -> Response<Passwd> {
-        let config = config();
-        option_to_response(
-            config
-                .uid_from_name(&name)
-                .map(|uid| config.uid_to_passwd(uid).unwrap()),
-        )
+impl FromIterator<Uid> for ExcludedUids {
+    fn from_iter<I: IntoIterator<Item = Uid>>(iter: I) -> Self {
+        ExcludedUids::from(iter.into_iter().collect::<Vec<_>>())
     }
 }
 
-libnss_group_hooks!(lying, FakeDb);
-impl GroupHooks for FakeDb {
-    fn get_all_entries() -> Response<Vec<Group>> {
-        let config = config();
-        match config.user_group {
-            None => {
-                // group per user
-                Response::Success(
-                    config
-                        .uid_range()
-                        .map(|uid| config.gid_to_group(uid).unwrap())
-                        .collect(),
-                )
-            }
-            Some(user_gid) => Response::Success([config.gid_to_group(user_gid).unwrap()].into()),
+impl std::ops::Deref for ExcludedUids {
+    type Target = [Uid];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for ExcludedUids {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(ExcludedUids::from)
+    }
+}
+
+/// The overrides of a config by UID, see [Config::overrides]
+///
+/// Dereferences to the map of overrides by UID. The overridden names are
+/// indexed as well when the overrides are built, so that lookups by name don't
+/// scan every override.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    by_uid: HashMap<Uid, PasswdOverride>,
+    /// UIDs by overridden name
+    by_name: HashMap<String, Uid>,
+    /// UIDs by overridden name in ASCII lowercase, for `case_insensitive_names`
+    by_lowercase_name: HashMap<String, Uid>,
+}
+
+impl Overrides {
+    /// Get the UID whose overridden name is `name`
+    ///
+    /// If several overrides have the same name, which one is returned is
+    /// unspecified; [Config::validate] rejects such configs.
+    fn uid_for_name(&self, name: &str, case_insensitive: bool) -> Option<Uid> {
+        if case_insensitive {
+            self.by_lowercase_name
+                .get(&name.to_ascii_lowercase())
+                .copied()
+        } else {
+            self.by_name.get(name).copied()
         }
     }
+}
 
-    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
-        let config = config();
-        option_to_response(config.gid_to_group(gid))
+impl From<HashMap<Uid, PasswdOverride>> for Overrides {
+    fn from(by_uid: HashMap<Uid, PasswdOverride>) -> Self {
+        let names = || {
+            by_uid
+                .iter()
+                .filter_map(|(&uid, o)| Some((o.name.as_ref()?, uid)))
+        };
+        Overrides {
+            by_name: names().map(|(name, uid)| (name.clone(), uid)).collect(),
+            by_lowercase_name: names()
+                .map(|(name, uid)| (name.to_ascii_lowercase(), uid))
+                .collect(),
+            by_uid,
+        }
     }
+}
 
-    fn get_entry_by_name(name: String) -> Response<Group> {
-        let config = config();
-        option_to_response(
-            config
-                .gid_from_name(&name)
-                .map(|gid| config.gid_to_group(gid).unwrap()),
-        )
+impl FromIterator<(Uid, PasswdOverride)> for Overrides {
+    fn from_iter<I: IntoIterator<Item = (Uid, PasswdOverride)>>(iter: I) -> Self {
+        Overrides::from(iter.into_iter().collect::<HashMap<_, _>>())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl std::ops::Deref for Overrides {
+    type Target = HashMap<Uid, PasswdOverride>;
 
-    fn base_config() -> Config {
-        Config {
-            uid_min: 1000,
-            uid_max: 9999,
-            user_group: None,
-            shell: "/bin/bash".into(),
+    fn deref(&self) -> &Self::Target {
+        &self.by_uid
+    }
+}
+
+impl PartialEq for Overrides {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_uid == other.by_uid
+    }
+}
+
+impl Eq for Overrides {}
+
+impl<'de> Deserialize<'de> for Overrides {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Overrides::from)
+    }
+}
+
+/// The supplementary groups of specific users, see [Config::member_map]
+///
+/// Dereferences to the map of GIDs by UID. The members of each GID are
+/// indexed as well when the map is built, so that synthesizing a group doesn't
+/// scan every user's groups.
+#[derive(Clone, Debug, Default)]
+pub struct MemberMap {
+    by_uid: HashMap<Uid, Vec<Gid>>,
+    /// UIDs by GID, in ascending order
+    by_gid: HashMap<Gid, Vec<Uid>>,
+}
+
+impl MemberMap {
+    /// Get the GIDs a user is mapped into
+    fn gids_of(&self, uid: Uid) -> &[Gid] {
+        self.by_uid.get(&uid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the UIDs mapped into a group, in ascending order
+    fn uids_in(&self, gid: Gid) -> &[Uid] {
+        self.by_gid.get(&gid).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl From<HashMap<Uid, Vec<Gid>>> for MemberMap {
+    fn from(mut by_uid: HashMap<Uid, Vec<Gid>>) -> Self {
+        // keep the first of duplicate GIDs, in the order they were given
+        for gids in by_uid.values_mut() {
+            let mut seen = HashSet::new();
+            gids.retain(|&gid| seen.insert(gid));
+        }
+        let mut by_gid: HashMap<_, Vec<_>> = HashMap::new();
+        for (&uid, gids) in &by_uid {
+            for &gid in gids {
+                by_gid.entry(gid).or_default().push(uid);
+            }
+        }
+        for uids in by_gid.values_mut() {
+            uids.sort_unstable();
+            uids.dedup();
         }
+        MemberMap { by_uid, by_gid }
     }
+}
 
-    #[test]
-    fn parse_valid_name() {
-        let config = base_config();
-        assert_eq!(config.uid_from_name("user-9999"), Some(9999));
+impl FromIterator<(Uid, Vec<Gid>)> for MemberMap {
+    fn from_iter<I: IntoIterator<Item = (Uid, Vec<Gid>)>>(iter: I) -> Self {
+        MemberMap::from(iter.into_iter().collect::<HashMap<_, _>>())
     }
+}
 
-    #[test]
-    fn parse_out_of_range_name() {
-        let config = base_config();
-        assert_eq!(config.uid_from_name("user-99999"), None);
+impl std::ops::Deref for MemberMap {
+    type Target = HashMap<Uid, Vec<Gid>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.by_uid
     }
+}
 
-    #[test]
-    fn parse_group_name_single_group() {
-        let config = Config {
-            user_group: Some(1000),
-            ..base_config()
-        };
-        assert_eq!(config.gid_from_name("users"), Some(1000));
-        assert_eq!(config.gid_from_name("user-1000"), None);
-        assert_eq!(config.gid_from_name("user-9999"), None);
+impl PartialEq for MemberMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_uid == other.by_uid
     }
+}
 
-    #[test]
-    fn parse_group_name_user_groups() {
-        let config = Config {
-            user_group: None,
-            ..base_config()
-        };
-        assert_eq!(config.gid_from_name("users"), None);
-        assert_eq!(config.gid_from_name("user-1000"), Some(1000));
-        assert_eq!(config.gid_from_name("user-9999"), Some(9999));
+impl Eq for MemberMap {}
+
+impl<'de> Deserialize<'de> for MemberMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(MemberMap::from)
     }
+}
 
-    #[test]
-    fn parse_group_name_user_groups_out_of_range() {
-        let config = Config {
-            user_group: None,
-            ..base_config()
-        };
-        assert_eq!(config.gid_from_name("user-99999"), None);
+/// The aliases of a config by name, see [Config::aliases]
+///
+/// Dereferences to the map of UIDs by alias. Like [Overrides], the aliases are
+/// also indexed in lowercase when they are built, for `case_insensitive_names`.
+#[derive(Clone, Debug, Default)]
+pub struct Aliases {
+    by_name: HashMap<String, Uid>,
+    /// UIDs by alias in ASCII lowercase
+    by_lowercase_name: HashMap<String, Uid>,
+}
+
+impl Aliases {
+    /// Get the UID an alias maps to
+    ///
+    /// If several aliases differ only in case, which one is returned for a
+    /// case-insensitive lookup is unspecified; [Config::validate] rejects
+    /// such configs.
+    fn uid_for_name(&self, name: &str, case_insensitive: bool) -> Option<Uid> {
+        if case_insensitive {
+            self.by_lowercase_name
+                .get(&name.to_ascii_lowercase())
+                .copied()
+        } else {
+            self.by_name.get(name).copied()
+        }
     }
+}
 
-    #[test]
-    fn user_name_roundtrip() {
+impl From<HashMap<String, Uid>> for Aliases {
+    fn from(by_name: HashMap<String, Uid>) -> Self {
+        Aliases {
+            by_lowercase_name: by_name
+                .iter()
+                .map(|(name, &uid)| (name.to_ascii_lowercase(), uid))
+                .collect(),
+            by_name,
+        }
+    }
+}
+
+impl FromIterator<(String, Uid)> for Aliases {
+    fn from_iter<I: IntoIterator<Item = (String, Uid)>>(iter: I) -> Self {
+        Aliases::from(iter.into_iter().collect::<HashMap<_, _>>())
+    }
+}
+
+impl std::ops::Deref for Aliases {
+    type Target = HashMap<String, Uid>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.by_name
+    }
+}
+
+impl PartialEq for Aliases {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_name == other.by_name
+    }
+}
+
+impl Eq for Aliases {}
+
+impl<'de> Deserialize<'de> for Aliases {
+    /// Deserialize the aliases from a map, rejecting duplicates
+    ///
+    /// TOML already rejects duplicate keys, but JSON parsers keep the last
+    /// one, which would silently drop an alias.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = HashMap<String, Uid>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of aliases to UIDs")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut aliases = HashMap::new();
+                while let Some((name, uid)) = map.next_entry::<String, Uid>()? {
+                    if aliases.contains_key(&name) {
+                        return Err(serde::de::Error::custom(format!(
+                            "duplicate alias {:?}",
+                            name
+                        )));
+                    }
+                    aliases.insert(name, uid);
+                }
+                Ok(aliases)
+            }
+        }
+
+        deserializer.deserialize_map(Visitor).map(Aliases::from)
+    }
+}
+
+/// A single fixed passwd entry synthesized alongside the range, for software
+/// that expects e.g. `nobody` to resolve
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct NobodyConfig {
+    /// UID of the user, `65534` by default
+    #[serde(default = "default_nobody_id")]
+    pub uid: Uid,
+    /// Primary GID of the user, `65534` by default
+    #[serde(default = "default_nobody_gid")]
+    pub gid: Gid,
+    /// Username, `nobody` by default
+    #[serde(default = "default_nobody_name")]
+    pub name: String,
+    /// Home directory, `/nonexistent` by default
+    #[serde(default = "default_nobody_dir")]
+    pub dir: String,
+    /// Shell, `/usr/sbin/nologin` by default
+    #[serde(default = "default_nobody_shell")]
+    pub shell: String,
+}
+
+fn default_nobody_id() -> Uid {
+    Uid(65534)
+}
+
+fn default_nobody_gid() -> Gid {
+    Gid(65534)
+}
+
+fn default_nobody_name() -> String {
+    "nobody".into()
+}
+
+fn default_nobody_dir() -> String {
+    "/nonexistent".into()
+}
+
+fn default_nobody_shell() -> String {
+    "/usr/sbin/nologin".into()
+}
+
+/// Placeholders supported by [Config::shared_group_template]
+const SHARED_GROUP_PLACEHOLDERS: &[&str] = &["gid"];
+
+/// Placeholders supported by [Config::home_template]
+const HOME_PLACEHOLDERS: &[&str] = &["uid", "name", "bucket"];
+
+/// Placeholders supported by [Config::gecos_name] and the other GECOS
+/// component templates
+const GECOS_PLACEHOLDERS: &[&str] = &["uid", "name", "name_titlecase"];
+
+/// Characters a GECOS component must not contain: the separators of the
+/// components and of the passwd line itself
+const GECOS_FORBIDDEN: &[char] = &[',', ':', '\n'];
+
+/// Deserialize [Config::user_group] from either a GID or a group name
+fn deserialize_user_group<'de, D>(deserializer: D) -> std::result::Result<Option<Gid>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum GroupRef {
+        Id(Gid),
+        Name(String),
+    }
+
+    match GroupRef::deserialize(deserializer)? {
+        GroupRef::Id(gid) => Ok(Some(gid)),
+        GroupRef::Name(name) => system::gid_for_group_name(&name)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("invalid user_group: {:#}", e))),
+    }
+}
+
+fn default_forbid_reserved() -> bool {
+    true
+}
+
+fn default_reserved_below() -> Uid {
+    Uid(1000)
+}
+
+fn default_max_range_size() -> u64 {
+    1_000_000
+}
+
+fn default_max_line_len() -> usize {
+    1024
+}
+
+fn default_enumerable() -> bool {
+    true
+}
+
+/// Merge inclusive ranges into sorted, disjoint ones, so that counting their
+/// members doesn't count any twice
+fn merge_ranges(ranges: impl Iterator<Item = (u64, u64)>) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<_> = ranges.collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Collect the names of the fields left over by the other fields of
+/// [Config], ignoring their values
+fn deserialize_unknown_fields<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let fields: HashMap<String, serde::de::IgnoredAny> = HashMap::deserialize(deserializer)?;
+    let mut names: Vec<_> = fields.into_keys().collect();
+    names.sort();
+    Ok(names)
+}
+
+fn default_cache_capacity() -> usize {
+    1024
+}
+
+fn default_shell() -> String {
+    "/bin/bash".into()
+}
+
+fn default_name_prefix() -> String {
+    "user-".into()
+}
+
+fn default_name_radix() -> u32 {
+    10
+}
+
+fn default_home_template() -> String {
+    "/tmp".into()
+}
+
+fn default_passwd_field() -> String {
+    "x".into()
+}
+
+fn default_shadow_passwd() -> String {
+    "*".into()
+}
+
+impl Config {
+    /// Iterate over every synthesized UID, chaining the configured ranges
+    ///
+    /// If `allow_uids` is set, iterates over the allowed UIDs in order
+    /// instead.
+    pub fn uid_range(&self) -> impl DoubleEndedIterator<Item = Uid> + '_ {
+        let (ranges, allowed) = match &self.allow_uids {
+            Some(allowed) => (&[][..], &allowed[..]),
+            None => (&self.ranges[..], &[][..]),
+        };
+        ranges
+            .iter()
+            .flat_map(Uid::iter)
+            .filter(|uid| !self.is_excluded(*uid) && !self.is_reserved(*uid))
+            .chain(
+                allowed
+                    .iter()
+                    .copied()
+                    .filter(|uid| self.contains_uid(*uid)),
+            )
+    }
+
+    /// Number of UIDs across all the configured ranges, or in `allow_uids` if
+    /// it is set, before exclusions
+    ///
+    /// Saturates rather than overflowing where `usize` is 32 bits wide and a
+    /// range covers every UID up to `uid_t::MAX`.
+    fn uid_range_len(&self) -> usize {
+        if let Some(allowed) = &self.allow_uids {
+            return allowed.len();
+        }
+        self.ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| ((range.end().0 - range.start().0) as usize).saturating_add(1))
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Count the synthesized UIDs, those for which [Config::contains_uid]
+    /// holds, not counting the nobody user
+    ///
+    /// This is computed from the bounds of the ranges and exclusions rather
+    /// than by visiting every UID, so it is cheap even for huge ranges. With
+    /// `allow_uids`, each allowed UID is checked instead.
+    pub fn uid_count(&self) -> u64 {
+        self.count_uids_within(0, u64::from(Uid::MAX.0))
+    }
+
+    /// Count the synthesized groups, those listed when enumerating the group
+    /// database: the per-user groups, and the shared group if any user has it
+    ///
+    /// Like [Config::uid_count], this doesn't visit every UID. Per-user groups
+    /// whose GID would be out of bounds after adding `gid_offset` are left
+    /// out, as they are when synthesizing them.
+    pub fn gid_count(&self) -> u64 {
+        let per_user = if self.has_per_user_groups() {
+            let max = i64::from(libc::gid_t::MAX);
+            // UIDs whose per-user GID is within bounds
+            let low = self.gid_offset.saturating_neg().max(0);
+            let high = max.saturating_sub(self.gid_offset).min(max);
+            if high < low {
+                0
+            } else {
+                self.count_uids_within(low as u64, high as u64)
+            }
+        } else {
+            0
+        };
+        // the shared group takes over a colliding per-user GID
+        let collision = self.shared_gid().is_some_and(|gid| {
+            self.has_per_user_groups()
+                && self
+                    .uid_for_per_user_gid(gid)
+                    .is_some_and(|uid| self.contains_uid(uid))
+        });
+        let shared = self.shared_gid().is_some() && self.uid_count() > 0;
+        per_user - u64::from(collision) + u64::from(shared)
+    }
+
+    /// Count the synthesized UIDs within `low..=high`
+    fn count_uids_within(&self, low: u64, high: u64) -> u64 {
+        if let Some(allowed) = &self.allow_uids {
+            return allowed
+                .iter()
+                .copied()
+                .filter(|&uid| (low..=high).contains(&u64::from(uid.0)) && self.contains_uid(uid))
+                .count() as u64;
+        }
+        let low = if self.forbid_reserved {
+            low.max(u64::from(self.reserved_below.0))
+        } else {
+            low
+        };
+        let included = merge_ranges(self.ranges.iter().filter_map(|range| {
+            let start = u64::from(range.start().0).max(low);
+            let end = u64::from(range.end().0).min(high);
+            (start <= end).then_some((start, end))
+        }));
+        let excluded = merge_ranges(
+            self.exclude_ranges
+                .iter()
+                .map(|range| (u64::from(range.start().0), u64::from(range.end().0)))
+                .chain(
+                    self.exclude_uids
+                        .iter()
+                        .map(|&uid| (u64::from(uid.0), u64::from(uid.0))),
+                )
+                .filter(|(start, end)| start <= end),
+        );
+        included
+            .iter()
+            .map(|&(start, end)| {
+                let overlap: u64 = excluded
+                    .iter()
+                    .map(|&(x_start, x_end)| {
+                        (x_end.min(end) + 1).saturating_sub(x_start.max(start))
+                    })
+                    .sum();
+                end - start + 1 - overlap
+            })
+            .sum()
+    }
+
+    /// Get the lowest synthesized UID that is at least `from`
+    fn next_uid(&self, from: Uid) -> Option<Uid> {
+        if let Some(allowed) = &self.allow_uids {
+            let start = allowed.partition_point(|&uid| uid < from);
+            return allowed[start..]
+                .iter()
+                .copied()
+                .find(|&uid| self.contains_uid(uid));
+        }
+        self.ranges
+            .iter()
+            .filter_map(|range| {
+                Uid::iter(&(from.max(*range.start())..=*range.end()))
+                    .find(|&uid| self.contains_uid(uid))
+            })
+            .min()
+    }
+
+    /// Get the highest synthesized UID
+    fn last_uid(&self) -> Option<Uid> {
+        if let Some(allowed) = &self.allow_uids {
+            return allowed
+                .iter()
+                .rev()
+                .copied()
+                .find(|&uid| self.contains_uid(uid));
+        }
+        self.ranges
+            .iter()
+            .filter_map(|range| Uid::iter(range).rev().find(|&uid| self.contains_uid(uid)))
+            .max()
+    }
+
+    /// Check whether a UID is explicitly excluded from synthesis, by
+    /// `exclude_uids` or `exclude_ranges`
+    fn is_excluded(&self, uid: Uid) -> bool {
+        self.exclude_uids.contains(uid)
+            || self.exclude_ranges.iter().any(|range| range.contains(&uid))
+    }
+
+    /// Check whether a UID is below the reserved floor, if it is enforced
+    fn is_reserved(&self, uid: Uid) -> bool {
+        self.forbid_reserved && uid < self.reserved_below
+    }
+
+    /// Check whether a UID falls within any of the configured ranges, is
+    /// allowed by `allow_uids` if set, and is neither excluded nor reserved
+    pub fn contains_uid(&self, uid: Uid) -> bool {
+        self.ranges.iter().any(|range| range.contains(&uid))
+            && self
+                .allow_uids
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(uid))
+            && !self.is_excluded(uid)
+            && !self.is_reserved(uid)
+    }
+
+    /// Get the override for a UID, if any
+    fn override_for_uid(&self, uid: Uid) -> Option<&PasswdOverride> {
+        self.overrides.get(&uid)
+    }
+
+    /// Get the username for a UID, if it is in range
+    ///
+    /// Returns [None] when the UID is not within range
+    pub fn name_for_uid(&self, uid: Uid) -> Option<String> {
+        if !self.contains_uid(uid) {
+            return None;
+        }
+        if let Some(name) = self.override_for_uid(uid).and_then(|o| o.name.as_ref()) {
+            return Some(name.clone());
+        }
+        self.generated_name(uid)
+    }
+
+    /// Run `f` with the scheme generating usernames: the custom
+    /// `name_scheme` if set, or else the [PrefixScheme] of the `name_*` fields
+    fn with_name_scheme<R>(&self, f: impl FnOnce(&dyn NameScheme) -> R) -> R {
+        match &self.name_scheme {
+            Some(CustomNameScheme(scheme)) => f(scheme.as_ref()),
+            None => f(&PrefixScheme {
+                prefix: Cow::Borrowed(&self.name_prefix),
+                pad_width: self.name_pad_width,
+                radix: self.name_radix,
+                case_insensitive: self.case_insensitive_names,
+            }),
+        }
+    }
+
+    /// Generate the username for a UID with the name scheme, ignoring
+    /// overrides and the range
+    fn generated_name(&self, uid: Uid) -> Option<String> {
+        self.with_name_scheme(|scheme| scheme.name(uid))
+    }
+
+    /// Extract a UID from a username
+    ///
+    /// Only the exact form produced by [Config::name_for_uid] is accepted, so
+    /// that each UID has a single name. This includes names set by
+    /// [Config::overrides], which replace the generated name of their UID,
+    /// and [Config::aliases], which are resolved in addition to it.
+    ///
+    /// Aliases, `case_insensitive_names` and `claim_prefix_names_in_gaps`
+    /// make other names resolve too, so `name_for_uid` of the result is not
+    /// necessarily `name`. With `claim_prefix_names_in_gaps` and the range
+    /// `1000..=1999`, `user-2000` maps to 1999, whose name is `user-1999`.
+    ///
+    /// Returns [None] when the UID is not synthesized by the module
+    pub fn uid_from_name(&self, name: &str) -> Option<Uid> {
+        if let Some(uid) = self
+            .overrides
+            .uid_for_name(name, self.case_insensitive_names)
+            .or_else(|| self.aliases.uid_for_name(name, self.case_insensitive_names))
+        {
+            return self.contains_uid(uid).then_some(uid);
+        }
+        let uid = self.uid_from_generated_name(name)?;
+        // overridden names replace the generated one
+        if self.override_for_uid(uid).is_some_and(|o| o.name.is_some()) {
+            return None;
+        }
+        Some(uid)
+    }
+
+    /// Check whether a name being looked up matches a synthesized name
+    fn name_matches(&self, name: &str, synthesized: &str) -> bool {
+        if self.case_insensitive_names {
+            name.eq_ignore_ascii_case(synthesized)
+        } else {
+            name == synthesized
+        }
+    }
+
+    /// Extract a UID from a name generated by [Config::generated_name], if it
+    /// is in range
+    ///
+    /// With `claim_prefix_names_in_gaps`, UIDs outside every range are
+    /// clamped into the nearest one instead.
+    fn uid_from_generated_name(&self, name: &str) -> Option<Uid> {
+        if self
+            .name_pattern
+            .as_ref()
+            .is_some_and(|pattern| !pattern.is_match(name))
+        {
+            return None;
+        }
+        let mut uid = self.with_name_scheme(|scheme| scheme.uid(name))?;
+        if self.claim_prefix_names_in_gaps {
+            uid = self.nearest_uid_in_range(uid)?;
+        }
+        self.contains_uid(uid).then_some(uid)
+    }
+
+    /// Get the UID within a range nearest to `uid`, which is `uid` itself if
+    /// it is within one
+    ///
+    /// Ties go to the lower UID. Returns [None] if there are no ranges.
+    fn nearest_uid_in_range(&self, uid: Uid) -> Option<Uid> {
+        self.ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| uid.clamp(*range.start(), *range.end()))
+            .min_by_key(|&nearest| (nearest.0.abs_diff(uid.0), nearest))
+    }
+
+    /// Get the primary GID for a UID, if it is in range
+    ///
+    /// Returns [None] when the UID is not within range, or its per-user GID
+    /// would be out of bounds after applying the offset and `fallback_gid`
+    /// is unset
+    pub fn gid_for_uid(&self, uid: Uid) -> Option<Gid> {
+        if !self.contains_uid(uid) {
+            return None;
+        }
+        if let Some(gid) = self.override_for_uid(uid).and_then(|o| o.gid) {
+            return Some(gid);
+        }
+        if let Some(gids) = self.round_robin_gids() {
+            return Some(gids[uid.0 as usize % gids.len()]);
+        }
+        match self.shared_gid() {
+            None => self.per_user_gid(uid).or(self.fallback_gid),
+            Some(gid) => Some(gid),
+        }
+    }
+
+    /// Apply `gid_offset` to a UID to get its per-user GID
+    ///
+    /// All UID to GID arithmetic goes through here and its inverse
+    /// [Config::uid_for_per_user_gid]. Returns [None] on overflow or underflow rather than
+    /// wrapping, and when primary groups are assigned round-robin, since
+    /// there are no per-user groups then.
+    fn per_user_gid(&self, uid: Uid) -> Option<Gid> {
+        if self.round_robin_gids().is_some() {
+            return None;
+        }
+        uid.per_user_gid(self.gid_offset)
+    }
+
+    /// Reverse `gid_offset` to get the UID owning a per-user GID
+    ///
+    /// Returns [None] on overflow or underflow rather than wrapping, and when
+    /// primary groups are assigned round-robin.
+    fn uid_for_per_user_gid(&self, gid: Gid) -> Option<Uid> {
+        if self.round_robin_gids().is_some() {
+            return None;
+        }
+        gid.owner_uid(self.gid_offset)
+    }
+
+    /// Get the GID of a user's per-user group, unless the shared group takes
+    /// it over
+    ///
+    /// The shared group wins a collision with a per-user GID, see
+    /// [Config::enumerate_per_user_groups].
+    fn per_user_group_gid(&self, uid: Uid) -> Option<Gid> {
+        self.per_user_gid(uid)
+            .filter(|&gid| self.shared_gid() != Some(gid))
+    }
+
+    /// Get the strategy for assigning primary groups
+    ///
+    /// This is `primary_gid_strategy` if set. Otherwise, users share the
+    /// group of `group_naming` or `user_group` if set, and have their own
+    /// groups if not.
+    pub fn primary_gid_strategy(&self) -> PrimaryGidStrategy {
+        if let Some(strategy) = &self.primary_gid_strategy {
+            return strategy.clone();
+        }
+        match self.shared_gid() {
+            Some(gid) => PrimaryGidStrategy::Shared(gid),
+            None => PrimaryGidStrategy::PerUser,
+        }
+    }
+
+    /// Get the GIDs primary groups are assigned from, if they are assigned
+    /// round-robin
+    fn round_robin_gids(&self) -> Option<&[Gid]> {
+        match &self.primary_gid_strategy {
+            Some(PrimaryGidStrategy::RoundRobin(gids)) if !gids.is_empty() => Some(gids),
+            _ => None,
+        }
+    }
+
+    /// Get the policy for naming primary groups
+    ///
+    /// This is `group_naming` if set. Otherwise, users share the group of
+    /// `user_group` if set, named by `shared_group_template` or
+    /// `shared_group_name`, and have their own groups if not.
+    pub fn group_naming(&self) -> GroupNaming {
+        match self.shared_gid() {
+            Some(gid) => GroupNaming::Shared {
+                name: self.shared_group_name().into(),
+                gid,
+            },
+            None => GroupNaming::PerUser,
+        }
+    }
+
+    /// Get the GID of the shared group, if users share a primary group
+    fn shared_gid(&self) -> Option<Gid> {
+        match &self.primary_gid_strategy {
+            Some(PrimaryGidStrategy::Shared(gid)) => return Some(*gid),
+            Some(_) => return None,
+            None => {}
+        }
+        match &self.group_naming {
+            Some(GroupNaming::PerUser) => None,
+            Some(GroupNaming::Shared { gid, .. }) => Some(*gid),
+            None => self.user_group,
+        }
+    }
+
+    /// Get the name of the shared group used when users share a primary
+    /// group
+    ///
+    /// This is the name given by `group_naming` if set, or else
+    /// `shared_group_template` expanded with the shared GID if set, or else
+    /// `shared_group_name`.
+    fn shared_group_name(&self) -> Cow<'_, str> {
+        if let Some(GroupNaming::Shared { name, .. }) = &self.group_naming {
+            return name.into();
+        }
+        match (&self.shared_group_template, self.shared_gid()) {
+            (Some(template), Some(gid)) => template::expand(template, &[("gid", &gid)]).into(),
+            _ => self.shared_group_name.as_deref().unwrap_or("users").into(),
+        }
+    }
+
+    /// Get the password field of each group entry
+    ///
+    /// This is `group_passwd_field` if set, or else `passwd_field`.
+    fn group_passwd_field(&self) -> &str {
+        self.group_passwd_field
+            .as_deref()
+            .unwrap_or(&self.passwd_field)
+    }
+
+    /// Get the name for a GID, if it is synthesized by this module
+    ///
+    /// Returns [None] when the GID is not synthesized by this module
+    pub fn name_for_gid(&self, gid: Gid) -> Option<String> {
+        match self.shared_gid() {
+            Some(user_gid) if gid == user_gid => {
+                // user group is set, return fixed name. the shared group takes
+                // precedence over a colliding per-user GID, and the shared
+                // name stays canonical with `also_per_user_group_names`
+                Some(self.shared_group_name().into())
+            }
+            // per-user groups are disabled entirely by a shared group, unless
+            // they are enumerated alongside it
+            Some(_) if !self.enumerate_per_user_groups => None,
+            _ => {
+                // users have their own groups
+
+                // lookup the name for the corresponding UID (will return
+                // `None` if out of range)
+                self.name_for_uid(self.uid_for_per_user_gid(gid)?)
+            }
+        }
+    }
+
+    /// Extract a GID from a username
+    ///
+    /// Returns [None] when the GID is not synthesized by the module
+    pub fn gid_from_name(&self, name: &str) -> Option<Gid> {
+        match self.shared_gid() {
+            Some(user_gid)
+                if self.name_matches(name, &self.shared_group_name())
+                    || (self.also_per_user_group_names && self.uid_from_name(name).is_some()) =>
+            {
+                // user group is set, match fixed name, or the name of a
+                // synthesized user standing in for their per-user group
+                Some(user_gid)
+            }
+            Some(_) if !self.enumerate_per_user_groups => None,
+            _ => {
+                // users have their own groups
+                self.per_user_group_gid(self.uid_from_name(name)?)
+            }
+        }
+    }
+
+    /// Check whether per-user groups are synthesized, either as primary
+    /// groups or alongside the shared group
+    fn has_per_user_groups(&self) -> bool {
+        self.round_robin_gids().is_none()
+            && (self.shared_gid().is_none() || self.enumerate_per_user_groups)
+    }
+
+    /// Get the shell for a UID from the first matching rule, falling back to
+    /// the rollout shell if the UID is selected, and to the global shell
+    /// otherwise
+    fn shell_for_uid(&self, uid: Uid) -> &str {
+        let fallback = match &self.shell_rollout {
+            Some(rollout) if rollout.selects(uid) => &rollout.shell,
+            _ => &self.shell,
+        };
+        self.shell_rules
+            .iter()
+            .find(|(range, _)| range.contains(&uid))
+            .map_or(fallback, |(_, shell)| shell)
+    }
+
+    /// Synthesize the passwd entry for the nobody user, if configured
+    ///
+    /// Like any other user, it is left out if its UID is reserved.
+    fn nobody_passwd(&self) -> Option<Passwd> {
+        let nobody = self
+            .nobody
+            .as_ref()
+            .filter(|nobody| !self.is_reserved(nobody.uid))?;
+        Some(Passwd {
+            name: nobody.name.clone(),
+            passwd: self.passwd_field.clone(),
+            uid: nobody.uid.into(),
+            gid: nobody.gid.into(),
+            gecos: String::new(),
+            dir: nobody.dir.clone(),
+            shell: nobody.shell.clone(),
+        })
+    }
+
+    /// Bucket of a UID's home directory, for the `{bucket}` placeholder
+    fn home_bucket(&self, uid: Uid) -> libc::uid_t {
+        match self.home_bucket_count {
+            0 | 1 => 0,
+            count => uid.0 % count,
+        }
+    }
+
+    /// The GECOS component templates, in field order
+    ///
+    /// The full name comes from `gecos_style` if it is a template. A word
+    /// list name is filled in by [Config::gecos_for_uid] instead.
+    fn gecos_templates(&self) -> [(&'static str, &str); 5] {
+        let name = match &self.gecos_style {
+            GecosStyle::Template(template) => ("gecos_style", template.as_str()),
+            _ => ("gecos_name", self.gecos_name.as_str()),
+        };
+        [
+            name,
+            ("gecos_room", &self.gecos_room),
+            ("gecos_work_phone", &self.gecos_work_phone),
+            ("gecos_home_phone", &self.gecos_home_phone),
+            ("gecos_other", &self.gecos_other),
+        ]
+    }
+
+    /// Expand the GECOS component templates for a UID and join them with
+    /// commas, leaving out trailing empty components
+    ///
+    /// Characters that would start a new component or corrupt the passwd
+    /// line are dropped from the expanded values, since a placeholder such as
+    /// `{name}` isn't checked by [Config::validate].
+    fn gecos_for_uid(&self, uid: Uid, name: &str) -> String {
+        let mut components: Vec<String> = self
+            .gecos_templates()
+            .iter()
+            .map(|(_, template)| {
+                let mut component = template::expand(
+                    template,
+                    &[
+                        ("uid", &uid),
+                        ("name", &name),
+                        ("name_titlecase", &words::TitleCase(name)),
+                    ],
+                );
+                component.retain(|c| !GECOS_FORBIDDEN.contains(&c));
+                component
+            })
+            .collect();
+        if self.gecos_style == GecosStyle::WordList {
+            components[0] = words::display_name(uid);
+        }
+        while components.last().is_some_and(|c| c.is_empty()) {
+            components.pop();
+        }
+        components.join(",")
+    }
+
+    /// Synthesize the passwd entry for a UID, if it is in range or is the
+    /// nobody user
+    pub fn uid_to_passwd(&self, uid: Uid) -> Option<Passwd> {
+        if self.nobody.as_ref().is_some_and(|nobody| nobody.uid == uid) {
+            return self.nobody_passwd();
+        }
+        let name = self.name_for_uid(uid)?;
+        let dir = template::expand(
+            &self.home_template,
+            &[
+                ("uid", &uid),
+                ("name", &name),
+                ("bucket", &self.home_bucket(uid)),
+            ],
+        );
+        let gecos = self.gecos_for_uid(uid, &name);
+        let mut passwd = Passwd {
+            name,
+            passwd: self.passwd_field.clone(),
+            uid: uid.into(),
+            gid: self.gid_for_uid(uid)?.into(),
+            gecos,
+            dir,
+            shell: self.shell_for_uid(uid).into(),
+        };
+        if let Some(o) = self.override_for_uid(uid) {
+            // the name and GID are already applied by `name_for_uid` and
+            // `gid_for_uid`
+            if let Some(gecos) = &o.gecos {
+                passwd.gecos = gecos.clone();
+            }
+            if let Some(dir) = &o.dir {
+                passwd.dir = dir.clone();
+            }
+            if let Some(shell) = &o.shell {
+                passwd.shell = shell.clone();
+            }
+        }
+        Some(passwd)
+    }
+
+    /// Create the home directory of a synthesized user if `create_home` is set
+    /// and it doesn't exist yet, logging any failure
+    fn ensure_home(&self, passwd: &Passwd) {
+        if !self.create_home || !self.contains_uid(Uid(passwd.uid)) {
+            return;
+        }
+        let dir = Path::new(&passwd.dir);
+        if dir.exists() {
+            return;
+        }
+        if let Err(e) = create_home(dir, passwd.uid, passwd.gid) {
+            warn!("failed to create home directory {}: {}", passwd.dir, e);
+        }
+    }
+
+    /// Synthesize the passwd entry for a username
+    pub fn passwd_by_name(&self, name: &str) -> Option<Passwd> {
+        if self
+            .nobody
+            .as_ref()
+            .is_some_and(|nobody| self.name_matches(name, &nobody.name))
+        {
+            return self.nobody_passwd();
+        }
+        self.uid_from_name(name)
+            .and_then(|uid| self.uid_to_passwd(uid))
+    }
+
+    /// Synthesize an entry for every UID in range, in `enumeration_order`
+    ///
+    /// UIDs for which `synthesize` returns [None] are skipped rather than
+    /// treated as an error, since panicking here would unwind across the FFI
+    /// boundary into the calling process.
+    ///
+    /// libnss hands enumeration results to glibc through an iterator that
+    /// takes ownership of a [Vec], so the entries can't be streamed lazily;
+    /// instead the [Vec] is sized up front to avoid reallocating.
+    ///
+    /// At most `max_range_size` UIDs are visited, with a warning if the range
+    /// is cut short, so that a config that bypassed validation can't exhaust
+    /// the memory of the calling process.
+    fn collect_for_uids<T>(&self, synthesize: impl FnMut(Uid) -> Option<T>) -> Vec<T> {
+        let mut entries = Vec::with_capacity(self.uid_range_len().min(self.enumeration_limit()));
+        entries.extend(self.enumerated_uids().filter_map(synthesize));
+        entries
+    }
+
+    /// Get the number of UIDs enumerated at most, from `max_range_size`
+    fn enumeration_limit(&self) -> usize {
+        match self.max_range_size {
+            0 => usize::MAX,
+            max => usize::try_from(max).unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Iterate over the UIDs that are enumerated, in `enumeration_order`: the
+    /// first `max_range_size` ones in range, with a warning if the range is
+    /// cut short
+    ///
+    /// Every enumeration, whether through the module's hooks or in chunks,
+    /// gets its UIDs from here, so that they all list entries in the same
+    /// order.
+    fn enumerated_uids(&self) -> impl Iterator<Item = Uid> + '_ {
+        let limit = self.enumeration_limit();
+        let mut uids: Box<dyn Iterator<Item = Uid>> = match self.enumeration_order {
+            Order::Ascending => Box::new(self.uid_range()),
+            Order::Descending => Box::new(self.uid_range().rev()),
+        };
+        let mut remaining = limit;
+        std::iter::from_fn(move || {
+            if remaining == 0 {
+                if uids.next().is_some() {
+                    warn!(
+                        "synthesized range exceeds max_range_size {}, truncating enumeration",
+                        limit
+                    );
+                }
+                return None;
+            }
+            remaining -= 1;
+            uids.next()
+        })
+        .fuse()
+    }
+
+    /// Split the entries of an enumeration into chunks of
+    /// `enumeration_chunk_size` entries, or a single chunk if it is unset
+    ///
+    /// There are no chunks at all unless `enumerable` is set, like the
+    /// module's own enumeration.
+    fn chunked<T>(&self, entries: impl Iterator<Item = T>) -> impl Iterator<Item = Vec<T>> {
+        let size = self.enumeration_chunk_size.unwrap_or(usize::MAX);
+        let mut entries = entries
+            .take(if self.enumerable { usize::MAX } else { 0 })
+            .fuse();
+        std::iter::from_fn(move || {
+            let chunk: Vec<_> = entries.by_ref().take(size).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        })
+    }
+
+    /// Synthesize every passwd entry, in `enumeration_order`, with the
+    /// nobody user if configured counting as the last UID
+    pub fn passwd_entries(&self) -> Vec<Passwd> {
+        let entries = self.collect_for_uids(|uid| self.uid_to_passwd(uid));
+        self.with_nobody(entries, self.nobody_passwd())
+    }
+
+    /// Add the nobody user's entry, if any, to the entries of an enumeration
+    /// in `enumeration_order`, counting it as the last UID
+    fn with_nobody<T>(&self, mut entries: Vec<T>, nobody: Option<T>) -> Vec<T> {
+        if let Some(nobody) = nobody {
+            match self.enumeration_order {
+                Order::Ascending => entries.push(nobody),
+                Order::Descending => entries.insert(0, nobody),
+            }
+        }
+        entries
+    }
+
+    /// Iterate over every passwd entry, in UID order, followed by the nobody
+    /// user if configured
+    ///
+    /// Unlike [Config::passwd_entries], the entries are synthesized lazily, so
+    /// consumers can stream even a huge range. The same UIDs are skipped as in
+    /// lookups of single entries, but `max_range_size` doesn't apply.
+    pub fn users(&self) -> impl Iterator<Item = Passwd> + '_ {
+        self.users_of(self.uid_range(), Order::Ascending)
+    }
+
+    /// Synthesize [Config::passwd_entries] in chunks of
+    /// `enumeration_chunk_size` entries, which together are the same entries
+    ///
+    /// Like [Config::users], only one chunk is held in memory at a time, but
+    /// `max_range_size` applies. Like the module's enumeration, there are no
+    /// chunks unless `enumerable` is set.
+    pub fn passwd_entry_chunks(&self) -> impl Iterator<Item = Vec<Passwd>> + '_ {
+        self.chunked(self.users_of(self.enumerated_uids(), self.enumeration_order))
+    }
+
+    /// Iterate over the passwd entries of `uids`, which are in `order`, with
+    /// the nobody user if configured counting as the last UID
+    fn users_of<'a>(
+        &'a self,
+        uids: impl Iterator<Item = Uid> + 'a,
+        order: Order,
+    ) -> impl Iterator<Item = Passwd> + 'a {
+        let (first, last) = match order {
+            Order::Ascending => (None, self.nobody_passwd()),
+            Order::Descending => (self.nobody_passwd(), None),
+        };
+        first
+            .into_iter()
+            .chain(uids.filter_map(|uid| self.uid_to_passwd(uid)))
+            .chain(last)
+    }
+
+    /// Synthesize the shadow entry for a UID, if it is in range or is the
+    /// nobody user
+    pub fn uid_to_shadow(&self, uid: Uid) -> Option<Shadow> {
+        if self.nobody.as_ref().is_some_and(|nobody| nobody.uid == uid) {
+            return self.nobody_shadow();
+        }
+        let passwd = if self.shadow_locked && !self.shadow_passwd.starts_with('!') {
+            format!("!{}", self.shadow_passwd)
+        } else {
+            self.shadow_passwd.clone()
+        };
+        Some(self.shadow_entry(self.name_for_uid(uid)?, passwd))
+    }
+
+    /// Synthesize the shadow entry for the nobody user, if configured
+    ///
+    /// Its password is always locked, whatever `shadow_passwd` is, since the
+    /// nobody user is not meant to log in.
+    fn nobody_shadow(&self) -> Option<Shadow> {
+        let nobody = self.nobody_passwd()?;
+        Some(self.shadow_entry(nobody.name, "!".into()))
+    }
+
+    /// Synthesize a shadow entry with the given name and password field
+    fn shadow_entry(&self, name: String, passwd: String) -> Shadow {
+        Shadow {
+            name,
+            passwd,
+            // -1 leaves a field empty, disabling the corresponding check
+            last_change: -1,
+            change_min_days: 0,
+            change_max_days: 99999,
+            change_warn_days: 7,
+            change_inactive_days: -1,
+            expire_date: -1,
+            reserved: usize::MAX,
+        }
+    }
+
+    /// Synthesize the shadow entry for a username
+    pub fn shadow_by_name(&self, name: &str) -> Option<Shadow> {
+        if self
+            .nobody
+            .as_ref()
+            .is_some_and(|nobody| self.name_matches(name, &nobody.name))
+        {
+            return self.nobody_shadow();
+        }
+        self.uid_from_name(name)
+            .and_then(|uid| self.uid_to_shadow(uid))
+    }
+
+    /// Synthesize every shadow entry, in `enumeration_order`, with the
+    /// nobody user if configured counting as the last UID
+    pub fn shadow_entries(&self) -> Vec<Shadow> {
+        let entries = self.collect_for_uids(|uid| self.uid_to_shadow(uid));
+        self.with_nobody(entries, self.nobody_shadow())
+    }
+
+    /// Synthesize the group entry for a GID, if it is synthesized by this
+    /// module
+    pub fn gid_to_group(&self, gid: Gid) -> Option<Group> {
+        let name = self.name_for_gid(gid)?;
+        let members = match self.shared_gid() {
+            Some(user_gid) if self.populate_members && user_gid == gid => {
+                self.shared_group_members()
+            }
+            // per-user groups are named after their owner
+            _ if self.list_owner_as_member && self.shared_gid() != Some(gid) => {
+                Some(vec![name.clone()])
+            }
+            _ => Some(Vec::new()),
+        };
+        let members = members
+            .map(|members| self.add_mapped_members(gid, members))
+            .unwrap_or_default();
+        Some(Group {
+            name,
+            passwd: self.group_passwd_field().into(),
+            gid: gid.into(),
+            members,
+        })
+    }
+
+    /// Add the users [Config::member_map] puts in a group to its members,
+    /// or list none if there are then more than `max_members`
+    fn add_mapped_members(&self, gid: Gid, mut members: Vec<String>) -> Vec<String> {
+        let mapped = self.member_map.uids_in(gid);
+        if mapped.is_empty() {
+            return members;
+        }
+        let mut seen: HashSet<String> = members.iter().cloned().collect();
+        members.extend(
+            mapped
+                .iter()
+                .filter_map(|&uid| self.name_for_uid(uid))
+                .filter(|name| seen.insert(name.clone())),
+        );
+        if self.max_members != 0 && members.len() > self.max_members {
+            warn!(
+                "group {} has more than max_members {} members, omitting them",
+                gid, self.max_members
+            );
+            return Vec::new();
+        }
+        members
+    }
+
+    /// List the members of the shared group for `populate_members`, or
+    /// [None] if there are more than `max_members`
+    fn shared_group_members(&self) -> Option<Vec<String>> {
+        // every synthesized user has the shared group as their primary
+        let max = self.max_members;
+        if max == 0 {
+            return Some(self.collect_for_uids(|uid| self.name_for_uid(uid)));
+        }
+        let members: Vec<_> = self
+            .uid_range()
+            .filter_map(|uid| self.name_for_uid(uid))
+            .take(max.saturating_add(1))
+            .collect();
+        if members.len() > max {
+            warn!(
+                "shared group has more than max_members {} members, omitting them",
+                max
+            );
+            return None;
+        }
+        Some(members)
+    }
+
+    /// Synthesize the group entry for a group name
+    pub fn group_by_name(&self, name: &str) -> Option<Group> {
+        self.gid_from_name(name)
+            .and_then(|gid| self.gid_to_group(gid))
+    }
+
+    /// Get the supplementary groups for a username, for `initgroups`
+    ///
+    /// Returns no groups when the name is not synthesized by this module.
+    ///
+    /// With `enumerate_per_user_groups`, this includes the user's own
+    /// per-user group. The groups of `member_map` follow those of
+    /// `supplementary_gids`, leaving out any listed there already.
+    pub fn supplementary_groups(&self, name: &str) -> Vec<Group> {
+        let Some(uid) = self.uid_from_name(name) else {
+            return Vec::new();
+        };
+        let per_user_gid = self
+            .shared_gid()
+            .filter(|_| self.enumerate_per_user_groups)
+            .and_then(|_| self.per_user_group_gid(uid));
+        per_user_gid
+            .into_iter()
+            .chain(self.supplementary_gids.iter().copied())
+            .chain(
+                self.member_map
+                    .gids_of(uid)
+                    .iter()
+                    .copied()
+                    .filter(|gid| !self.supplementary_gids.contains(gid)),
+            )
+            .map(|gid| Group {
+                // only the GID is used by `initgroups`, and the group itself
+                // may not be one we synthesize
+                name: String::new(),
+                passwd: self.passwd_field.clone(),
+                gid: gid.into(),
+                members: Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Synthesize every group entry, in `enumeration_order`
+    pub fn group_entries(&self) -> Vec<Group> {
+        // groups per user, derived from the same set of synthesized users as
+        // the passwd entries so the two lists can't drift apart
+        let mut entries = if self.has_per_user_groups() {
+            self.collect_for_uids(|uid| self.gid_to_group(self.per_user_group_gid(uid)?))
+        } else {
+            Vec::new()
+        };
+        if let Some(shared) = self.shared_group_entry() {
+            let index = entries.partition_point(|group| {
+                self.enumeration_order.lists_before(group.gid, shared.gid)
+            });
+            entries.insert(index, shared);
+        }
+        entries
+    }
+
+    /// Synthesize the shared group's entry for enumeration, if `user_group`
+    /// is set
+    ///
+    /// The shared group is only listed while it has users, matching the
+    /// per-user groups of an empty range.
+    ///
+    /// If the shared GID doesn't resolve, which would take a bug in
+    /// [Config::name_for_gid], it is left out with a warning rather than
+    /// panicking inside the calling process.
+    fn shared_group_entry(&self) -> Option<Group> {
+        self.shared_group_entry_with(|gid| self.gid_to_group(gid))
+    }
+
+    /// Synthesize the shared group's entry like [Config::shared_group_entry],
+    /// looking it up with `lookup`
+    fn shared_group_entry_with(&self, lookup: impl FnOnce(Gid) -> Option<Group>) -> Option<Group> {
+        let gid = self.shared_gid()?;
+        self.uid_range().next()?;
+        let group = lookup(gid);
+        if group.is_none() {
+            warn!("shared group {} does not resolve, leaving it out", gid);
+        }
+        group
+    }
+
+    /// Iterate over every group entry, in GID order
+    ///
+    /// The lazy counterpart of [Config::group_entries], see [Config::users].
+    pub fn groups(&self) -> impl Iterator<Item = Group> + '_ {
+        self.groups_of(self.uid_range(), Order::Ascending)
+    }
+
+    /// Synthesize [Config::group_entries] in chunks of
+    /// `enumeration_chunk_size` entries, which together are the same entries
+    ///
+    /// See [Config::passwd_entry_chunks].
+    pub fn group_entry_chunks(&self) -> impl Iterator<Item = Vec<Group>> + '_ {
+        self.chunked(self.groups_of(self.enumerated_uids(), self.enumeration_order))
+    }
+
+    /// Iterate over the per-user groups of `uids`, which are in `order`, if
+    /// any, with the shared group merged in at its place in that order
+    fn groups_of<'a>(
+        &'a self,
+        uids: impl Iterator<Item = Uid> + 'a,
+        order: Order,
+    ) -> impl Iterator<Item = Group> + 'a {
+        let mut per_user = self
+            .has_per_user_groups()
+            .then(|| uids.filter_map(|uid| self.gid_to_group(self.per_user_group_gid(uid)?)))
+            .into_iter()
+            .flatten()
+            .peekable();
+        let mut shared = self.shared_gid();
+        std::iter::from_fn(move || {
+            // merge the shared group in at its place in the order
+            if let Some(gid) = shared {
+                if per_user
+                    .peek()
+                    .is_none_or(|group| order.lists_before(gid.0, group.gid))
+                {
+                    shared = None;
+                    if let Some(group) = self.shared_group_entry() {
+                        return Some(group);
+                    }
+                }
+            }
+            per_user.next()
+        })
+    }
+
+    /// Check that every shell handed out by the config exists and is
+    /// executable
+    ///
+    /// Problems are logged as warnings, or returned as an error if
+    /// `strict_shell` is set. Unlike [Config::validate], this looks at the
+    /// filesystem, so it is only done when loading the config.
+    pub fn check_shells(&self) -> Result<()> {
+        for problem in self.shell_problems() {
+            if self.strict_shell {
+                bail!(problem);
+            }
+            warn!("{}", problem);
+        }
+        Ok(())
+    }
+
+    /// Describe the problem with each shell handed out by the config that
+    /// doesn't exist or isn't executable
+    fn shell_problems(&self) -> Vec<String> {
+        let shells = std::iter::once(&self.shell)
+            .chain(self.shell_rules.iter().map(|(_, shell)| shell))
+            .chain(self.shell_rollout.iter().map(|rollout| &rollout.shell))
+            .chain(self.overrides.values().filter_map(|o| o.shell.as_ref()))
+            .chain(self.nobody.iter().map(|nobody| &nobody.shell));
+        shells
+            .filter_map(|shell| match std::fs::metadata(shell) {
+                Err(e) => Some(format!("shell {} is unusable: {}", shell, e)),
+                Ok(metadata) if !metadata.is_file() => {
+                    Some(format!("shell {} is not a file", shell))
+                }
+                Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => {
+                    Some(format!("shell {} is not executable", shell))
+                }
+                Ok(_) => None,
+            })
+            .collect()
+    }
+
+    /// Summarize the synthesized users in a human-readable line
+    ///
+    /// Includes the number of UIDs, the ranges, the first and last username,
+    /// and how primary groups are assigned, e.g. `9000 UIDs in 1000..=9999,
+    /// named user-1000 to user-9999, each with their own primary group`.
+    pub fn describe(&self) -> String {
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|range| format!("{}..={}", range.start(), range.end()))
+            .collect::<Vec<_>>()
+            .join(" and ");
+        let (Some(first), Some(last)) = (self.next_uid(Uid(0)), self.last_uid()) else {
+            return format!(
+                "no UIDs in {}",
+                if ranges.is_empty() {
+                    "no ranges"
+                } else {
+                    &ranges
+                }
+            );
+        };
+        let names = format!(
+            "named {} to {}",
+            self.name_for_uid(first).unwrap_or_default(),
+            self.name_for_uid(last).unwrap_or_default()
+        );
+        let groups = match (self.round_robin_gids(), self.shared_gid(), self.gid_offset) {
+            (Some(gids), _, _) => {
+                format!("with primary groups assigned round-robin from {:?}", gids)
+            }
+            (None, Some(gid), _) => format!(
+                "all with primary group {} ({})",
+                self.shared_group_name(),
+                gid
+            ),
+            (None, None, 0) => "each with their own primary group".into(),
+            (None, None, offset) if offset > 0 => {
+                format!("each with their own primary group (GID = UID + {})", offset)
+            }
+            (None, None, offset) => format!(
+                "each with their own primary group (GID = UID - {})",
+                offset.unsigned_abs()
+            ),
+        };
+        format!(
+            "{} UIDs in {}, {}, {}",
+            self.uid_range().count(),
+            ranges,
+            names,
+            groups
+        )
+    }
+
+    /// The string fields copied into entries, by name
+    fn string_fields(&mut self) -> Vec<(String, &mut String)> {
+        let mut fields = vec![
+            ("shell".to_owned(), &mut self.shell),
+            ("name_prefix".to_owned(), &mut self.name_prefix),
+            ("home_template".to_owned(), &mut self.home_template),
+            ("gecos_name".to_owned(), &mut self.gecos_name),
+            ("gecos_room".to_owned(), &mut self.gecos_room),
+            ("gecos_work_phone".to_owned(), &mut self.gecos_work_phone),
+            ("gecos_home_phone".to_owned(), &mut self.gecos_home_phone),
+            ("gecos_other".to_owned(), &mut self.gecos_other),
+            ("passwd_field".to_owned(), &mut self.passwd_field),
+            ("shadow_passwd".to_owned(), &mut self.shadow_passwd),
+        ];
+        if let Some(field) = &mut self.group_passwd_field {
+            fields.push(("group_passwd_field".to_owned(), field));
+        }
+        if let Some(name) = &mut self.shared_group_name {
+            fields.push(("shared_group_name".to_owned(), name));
+        }
+        if let Some(template) = &mut self.shared_group_template {
+            fields.push(("shared_group_template".to_owned(), template));
+        }
+        if let Some(rollout) = &mut self.shell_rollout {
+            fields.push(("shell_rollout shell".to_owned(), &mut rollout.shell));
+        }
+        for (range, shell) in &mut self.shell_rules {
+            fields.push((
+                format!("shell for rule {}..={}", range.start(), range.end()),
+                shell,
+            ));
+        }
+        fields
+    }
+
+    /// Reject string fields that contain ':', newlines, or NUL, and trim
+    /// leading and trailing whitespace from the others, or reject it if
+    /// `strict_fields` is set
+    ///
+    /// Done after parsing and before [Config::validate], since e.g. a shell
+    /// with a trailing space is easy to miss in the config file.
+    fn normalize_fields(&mut self) -> Result<()> {
+        let strict = self.strict_fields;
+        for (name, field) in self.string_fields() {
+            if field.contains([':', '\n', '\0']) {
+                bail!(
+                    "invalid {} {:?}: must not contain ':', newlines, or NUL",
+                    name,
+                    field
+                );
+            }
+            let trimmed = field.trim();
+            if trimmed.len() == field.len() {
+                continue;
+            }
+            if strict {
+                bail!(
+                    "invalid {} {:?}: has leading or trailing whitespace",
+                    name,
+                    field
+                );
+            }
+            warn!("trimming whitespace around {} {:?}", name, field);
+            *field = trimmed.to_owned();
+        }
+        Ok(())
+    }
+
+    /// Describe every name of an override, alias, or `nobody` that collides
+    /// with another synthesized name, so that one of them can't be looked up
+    ///
+    /// [Config::validate] fails with the first of these, [Config::lint]
+    /// reports each of them.
+    fn name_collisions(&self) -> Vec<String> {
+        let mut collisions = Vec::new();
+        let mut override_uids: Vec<_> = self.overrides.keys().collect();
+        override_uids.sort();
+        for &uid in override_uids {
+            let Some(name) = &self.overrides[&uid].name else {
+                continue;
+            };
+            if self
+                .uid_from_generated_name(name)
+                .is_some_and(|other| other != uid)
+            {
+                collisions.push(format!(
+                    "name {:?} in override for UID {} collides with a synthesized username",
+                    name, uid
+                ));
+            } else if self.uid_from_name(name) != Some(uid) {
+                collisions.push(format!(
+                    "name {:?} in override for UID {} is used by another override",
+                    name, uid
+                ));
+            }
+        }
+
+        let mut aliases: Vec<_> = self.aliases.iter().collect();
+        aliases.sort();
+        for (name, _) in aliases {
+            if self
+                .overrides
+                .uid_for_name(name, self.case_insensitive_names)
+                .is_some()
+                || self.uid_from_generated_name(name).is_some()
+            {
+                collisions.push(format!(
+                    "alias {:?} collides with a synthesized username",
+                    name
+                ));
+            }
+        }
+
+        if let Some(nobody) = &self.nobody {
+            if self.uid_from_name(&nobody.name).is_some() {
+                collisions.push(format!(
+                    "nobody name {:?} collides with a synthesized username",
+                    nobody.name
+                ));
+            }
+        }
+        collisions
+    }
+
+    /// Check the config for values that would make the module misbehave
+    pub fn validate(&self) -> Result<()> {
+        for range in &self.ranges {
+            if range.start() > range.end() {
+                bail!(
+                    "invalid range {}..={}: start is greater than end",
+                    range.start(),
+                    range.end()
+                );
+            }
+        }
+        for range in &self.exclude_ranges {
+            if range.start() > range.end() {
+                bail!(
+                    "invalid excluded range {}..={}: start is greater than end",
+                    range.start(),
+                    range.end()
+                );
+            }
+        }
+        let mut ranges: Vec<_> = self.ranges.iter().collect();
+        ranges.sort_by_key(|range| range.start());
+        for pair in ranges.windows(2) {
+            if pair[1].start() <= pair[0].end() {
+                bail!(
+                    "ranges {}..={} and {}..={} overlap",
+                    pair[0].start(),
+                    pair[0].end(),
+                    pair[1].start(),
+                    pair[1].end()
+                );
+            }
+        }
+
+        if let Some(uid) = self
+            .allow_uids
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|uid| !self.ranges.iter().any(|range| range.contains(uid)))
+        {
+            bail!("allowed UID {} is not within any range", uid);
+        }
+
+        let size = self.uid_range_len() as u64;
+        if self.max_range_size != 0 && size > self.max_range_size {
+            bail!(
+                "ranges contain {} UIDs, more than max_range_size {}",
+                size,
+                self.max_range_size
+            );
+        }
+
+        if self.enumeration_chunk_size == Some(0) {
+            bail!("enumeration_chunk_size must not be 0");
+        }
+
+        if self.shell.is_empty() {
+            bail!("shell must not be empty");
+        }
+        for (range, shell) in &self.shell_rules {
+            if shell.is_empty() {
+                bail!(
+                    "shell for rule {}..={} must not be empty",
+                    range.start(),
+                    range.end()
+                );
+            }
+        }
+        if let Some(rollout) = &self.shell_rollout {
+            if rollout.percent > 100 {
+                bail!("shell_rollout percent {} is more than 100", rollout.percent);
+            }
+            if rollout.shell.is_empty() {
+                bail!("shell_rollout shell must not be empty");
+            }
+        }
+
+        if self.enumerate_per_user_groups && self.also_per_user_group_names {
+            bail!("enumerate_per_user_groups and also_per_user_group_names are mutually exclusive");
+        }
+
+        if self.group_naming.is_some()
+            && (self.user_group.is_some()
+                || self.shared_group_name.is_some()
+                || self.shared_group_template.is_some())
+        {
+            bail!("group_naming and user_group, shared_group_name, or shared_group_template are mutually exclusive");
+        }
+        if self.primary_gid_strategy.is_some()
+            && (self.user_group.is_some() || self.group_naming.is_some())
+        {
+            bail!("primary_gid_strategy and user_group or group_naming are mutually exclusive");
+        }
+        if matches!(&self.primary_gid_strategy, Some(PrimaryGidStrategy::RoundRobin(gids)) if gids.is_empty())
+        {
+            bail!("primary_gid_strategy round_robin must list at least one GID");
+        }
+        if let Some(GroupNaming::Shared { name, .. }) = &self.group_naming {
+            if name.is_empty() || name.contains([':', '\n']) {
+                bail!(
+                    "invalid group_naming name {:?}: must not be empty or contain ':' or newlines",
+                    name
+                );
+            }
+        }
+        if let Some(gid) = self.fallback_gid {
+            if let Some(uid) = self
+                .uid_for_per_user_gid(gid)
+                .filter(|&uid| self.contains_uid(uid))
+            {
+                bail!(
+                    "fallback_gid {} collides with the per-user group of synthesized UID {}",
+                    gid,
+                    uid
+                );
+            }
+        }
+        if let Some(gid) = self.shared_gid() {
+            // this GID would also belong to a synthesized user if per-user
+            // groups were enabled, which is almost certainly a mistake
+            if let Some(uid) = self
+                .uid_for_per_user_gid(gid)
+                .filter(|&uid| self.contains_uid(uid))
+            {
+                bail!(
+                    "shared group GID {} collides with the per-user group of synthesized UID {}",
+                    gid,
+                    uid
+                );
+            }
+        }
+
+        let mut override_uids: Vec<_> = self.overrides.keys().collect();
+        override_uids.sort();
+        for &uid in override_uids {
+            if !self.contains_uid(uid) {
+                bail!("override for UID {} is outside the synthesized range", uid);
+            }
+            let Some(name) = &self.overrides[&uid].name else {
+                continue;
+            };
+            if name.is_empty() || name.contains([':', '\n']) {
+                bail!(
+                    "invalid name {:?} in override for UID {}: must not be empty or contain ':' or newlines",
+                    name,
+                    uid
+                );
+            }
+        }
+
+        let mut member_uids: Vec<_> = self.member_map.keys().collect();
+        member_uids.sort();
+        if let Some(uid) = member_uids
+            .into_iter()
+            .find(|&&uid| !self.contains_uid(uid))
+        {
+            bail!(
+                "member_map entry for UID {} is outside the synthesized range",
+                uid
+            );
+        }
+
+        let mut aliases: Vec<_> = self.aliases.iter().collect();
+        aliases.sort();
+        for (name, &uid) in aliases {
+            if name.is_empty() || name.contains([':', '\n']) {
+                bail!(
+                    "invalid alias {:?}: must not be empty or contain ':' or newlines",
+                    name
+                );
+            }
+            if !self.contains_uid(uid) {
+                bail!(
+                    "alias {:?} maps to UID {}, which is not synthesized",
+                    name,
+                    uid
+                );
+            }
+        }
+        if self.case_insensitive_names
+            && self.aliases.by_lowercase_name.len() < self.aliases.by_name.len()
+        {
+            bail!("aliases must not differ only in case with case_insensitive_names");
+        }
+
+        if let Some(nobody) = &self.nobody {
+            if self.contains_uid(nobody.uid) {
+                bail!("nobody UID {} is inside the synthesized range", nobody.uid);
+            }
+            if self.is_reserved(nobody.uid) {
+                bail!(
+                    "nobody UID {} is below reserved_below {}",
+                    nobody.uid,
+                    self.reserved_below
+                );
+            }
+        }
+        if let Some(collision) = self.name_collisions().into_iter().next() {
+            bail!(collision);
+        }
+
+        if let Some(template) = &self.shared_group_template {
+            if self.shared_group_name.is_some() {
+                bail!("shared_group_name and shared_group_template are mutually exclusive");
+            }
+            template::validate(template, SHARED_GROUP_PLACEHOLDERS)
+                .context("invalid shared_group_template")?;
+            if template.is_empty() || template.contains([':', '\n']) {
+                bail!(
+                    "invalid shared_group_template {:?}: must not be empty or contain ':' or newlines",
+                    template
+                );
+            }
+        }
+        template::validate(&self.home_template, HOME_PLACEHOLDERS)
+            .context("invalid home_template")?;
+        if self.gecos_style != GecosStyle::Components && !self.gecos_name.is_empty() {
+            bail!("gecos_style and gecos_name are mutually exclusive");
+        }
+        for (field, template) in self.gecos_templates() {
+            template::validate(template, GECOS_PLACEHOLDERS)
+                .with_context(|| format!("invalid {}", field))?;
+            if template.contains(GECOS_FORBIDDEN) {
+                bail!(
+                    "invalid {} {:?}: must not contain ',', ':' or newlines",
+                    field,
+                    template
+                );
+            }
+        }
+        // the rest of a generated name is ASCII digits, so this covers every
+        // generated username
+        if let Some(c) = self
+            .name_prefix
+            .chars()
+            .find(|&c| !c.is_ascii() || c == ':' || c.is_whitespace() || c == '\0')
+        {
+            bail!(
+                "invalid name_prefix {:?}: contains {:?}, but must be ASCII without ':', whitespace, or NUL",
+                self.name_prefix,
+                c
+            );
+        }
+        if ![8, 10, 16].contains(&self.name_radix) {
+            bail!(
+                "invalid name_radix {}: must be 8, 10, or 16",
+                self.name_radix
+            );
+        }
+        if self.passwd_field.contains([':', '\n']) {
+            bail!(
+                "invalid passwd_field {:?}: must not contain ':' or newlines",
+                self.passwd_field
+            );
+        }
+        if let Some(field) = &self.group_passwd_field {
+            if field.contains([':', '\n']) {
+                bail!(
+                    "invalid group_passwd_field {:?}: must not contain ':' or newlines",
+                    field
+                );
+            }
+        }
+
+        if self.max_line_len != 0 {
+            if let Some((uid, len)) = self.longest_passwd_line() {
+                if len > self.max_line_len {
+                    bail!(
+                        "passwd line of UID {} could be {} bytes long, more than max_line_len {}",
+                        uid,
+                        len,
+                        self.max_line_len
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the longest passwd line that may be synthesized, and its UID
+    ///
+    /// This is a heuristic rather than a bound: the longest generated names
+    /// and fields are assumed to be those of the largest UID, which holds for
+    /// numbered names but not e.g. for aliases or every name scheme. Any user
+    /// is assumed to possibly get the longest configured shell and, with
+    /// [GecosStyle::WordList], the longest display name. Overridden users are
+    /// checked individually.
+    fn longest_passwd_line(&self) -> Option<(Uid, usize)> {
+        let longest_shell = std::iter::once(&self.shell)
+            .chain(self.shell_rules.iter().map(|(_, shell)| shell))
+            .chain(self.shell_rollout.iter().map(|rollout| &rollout.shell))
+            .map(String::len)
+            .max()
+            .unwrap_or_default();
+        self.last_uid()
+            .into_iter()
+            .chain(self.overrides.keys().copied())
+            .filter_map(|uid| {
+                let passwd = self.uid_to_passwd(uid)?;
+                let shell = passwd.shell.len().max(longest_shell);
+                // display names vary in length between UIDs
+                let gecos_overridden = self
+                    .override_for_uid(uid)
+                    .is_some_and(|o| o.gecos.is_some());
+                let display_name_slack = match self.gecos_style {
+                    GecosStyle::WordList if !gecos_overridden => {
+                        words::longest_display_name_len() - words::display_name(uid).len()
+                    }
+                    _ => 0,
+                };
+                let len = passwd_line(&passwd).len() - passwd.shell.len() + shell;
+                Some((uid, len + display_name_slack))
+            })
+            .max_by_key(|&(_, len)| len)
+    }
+}
+
+/// Create a home directory owned by `uid` and `gid` with mode `0700`
+///
+/// Succeeds if the directory already exists, e.g. because another process
+/// created it first; its owner and mode are then left alone.
+fn create_home(dir: &Path, uid: libc::uid_t, gid: libc::gid_t) -> io::Result<()> {
+    if let Some(parent) = dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        result => result?,
+    }
+    std::os::unix::fs::chown(dir, Some(uid), Some(gid))
+}
+
+/// Result of a lookup of a single entry, keeping apart the ways it can miss
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "passwd", feature = "group")), allow(dead_code))]
+enum Outcome<T> {
+    /// The entry is synthesized
+    Found(T),
+    /// The key isn't one this module would synthesize, e.g. `alice`
+    Foreign,
+    /// The key has the form of a synthesized one, but isn't synthesized, e.g.
+    /// `user-99999` outside of the ranges or an excluded UID
+    Invalid,
+}
+
+#[cfg_attr(not(any(feature = "passwd", feature = "group")), allow(dead_code))]
+impl<T> Outcome<T> {
+    /// Classify the result of a lookup, where `ours` tells whether a missing
+    /// key still has the form of a synthesized one
+    fn new(entry: Option<T>, ours: impl FnOnce() -> bool) -> Self {
+        match entry {
+            Some(entry) => Outcome::Found(entry),
+            None if ours() => Outcome::Invalid,
+            None => Outcome::Foreign,
+        }
+    }
+
+    /// Turn the outcome into the response for NSS
+    fn into_response(self) -> Response<T> {
+        match self {
+            Outcome::Found(entry) => Response::Success(entry),
+            // a foreign key is left to the other modules either way, so
+            // `NotFound` is the right answer there. an invalid key is also
+            // `NotFound` for now, but may warrant e.g. stopping the search
+            Outcome::Foreign => Response::NotFound,
+            Outcome::Invalid => Response::NotFound,
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "passwd", feature = "group")), allow(dead_code))]
+impl Config {
+    /// Classify the result of a lookup by UID
+    ///
+    /// A missing UID within `ranges` is [Outcome::Invalid], e.g. because it
+    /// is excluded or reserved.
+    #[cfg_attr(not(feature = "passwd"), allow(dead_code))]
+    fn uid_outcome<T>(&self, uid: Uid, entry: Option<T>) -> Outcome<T> {
+        Outcome::new(entry, || {
+            self.ranges.iter().any(|range| range.contains(&uid))
+        })
+    }
+
+    /// Classify the result of a lookup by GID
+    ///
+    /// A missing GID that is the per-user GID of a UID within `ranges` is
+    /// [Outcome::Invalid].
+    #[cfg_attr(not(feature = "group"), allow(dead_code))]
+    fn gid_outcome<T>(&self, gid: Gid, entry: Option<T>) -> Outcome<T> {
+        Outcome::new(entry, || {
+            self.has_per_user_groups()
+                && self
+                    .uid_for_per_user_gid(gid)
+                    .is_some_and(|uid| self.ranges.iter().any(|range| range.contains(&uid)))
+        })
+    }
+
+    /// Classify the result of a lookup by user or group name
+    ///
+    /// A missing name that has the form of a generated one is
+    /// [Outcome::Invalid], e.g. one out of range or with extra leading zeros.
+    fn name_outcome<T>(&self, name: &str, entry: Option<T>) -> Outcome<T> {
+        Outcome::new(entry, || {
+            self.with_name_scheme(|scheme| scheme.is_name_form(name))
+        })
+    }
+}
+
+/// Format of a config file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// TOML, the format of `/etc/nss_lying.toml`
+    #[default]
+    Toml,
+    /// JSON, with the same structure as the TOML format
+    Json,
+}
+
+impl ConfigFormat {
+    /// Pick the format of a file from its extension
+    ///
+    /// Files ending in `.json` are JSON; anything else is TOML.
+    pub fn from_path(path: &Path) -> Self {
+        if path.extension().is_some_and(|ext| ext == "json") {
+            ConfigFormat::Json
+        } else {
+            ConfigFormat::Toml
+        }
+    }
+
+    /// Parse the contents of a file in this format into a JSON object, so
+    /// that fragments of either format can be merged
+    ///
+    /// JSON rather than TOML, since TOML can't represent a JSON `null`.
+    fn parse_object(self, contents: &str) -> Result<JsonObject> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(contents)?,
+            ConfigFormat::Json => serde_json::from_str(contents)?,
+        })
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = anyhow::Error;
+
+    /// Parse a format hint, `toml` or `json`
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "toml" => Ok(ConfigFormat::Toml),
+            "json" => Ok(ConfigFormat::Json),
+            _ => bail!("unknown config format {:?}: expected toml or json", s),
+        }
+    }
+}
+
+/// Parse a [Config] from the contents of a TOML file
+fn parse_config(contents: &str) -> Result<Config> {
+    parse_config_as(contents, ConfigFormat::Toml)
+}
+
+/// Parse a [Config] from the contents of a file in the given format
+fn parse_config_as(contents: &str, format: ConfigFormat) -> Result<Config> {
+    let mut config: Config = match format {
+        ConfigFormat::Toml => toml::from_str(contents)?,
+        ConfigFormat::Json => serde_json::from_str(contents)?,
+    };
+    if !config.unknown_fields.is_empty() {
+        warn!(
+            "ignoring unknown config fields: {}",
+            config.unknown_fields.join(", ")
+        );
+    }
+    config.normalize_fields()?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    /// Parse and validate a [Config] from the contents of a TOML file
+    fn from_str(s: &str) -> Result<Self> {
+        parse_config(s)
+    }
+}
+
+/// The configuration used when no config file exists, synthesizing UIDs
+/// 1000 to 9999 with per-user groups
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ranges: vec![Uid(1000)..=Uid(9999)],
+            exclude_uids: ExcludedUids::default(),
+            exclude_ranges: Vec::new(),
+            allow_uids: None,
+            forbid_reserved: default_forbid_reserved(),
+            reserved_below: default_reserved_below(),
+            defer_to_real: false,
+            max_range_size: default_max_range_size(),
+            enumerable: default_enumerable(),
+            enumeration_order: Order::default(),
+            enumeration_chunk_size: None,
+            user_group: None,
+            gid_offset: 0,
+            fallback_gid: None,
+            shared_group_name: None,
+            shared_group_template: None,
+            also_per_user_group_names: false,
+            group_naming: None,
+            primary_gid_strategy: None,
+            enumerate_per_user_groups: false,
+            populate_members: false,
+            max_members: 0,
+            max_line_len: default_max_line_len(),
+            list_owner_as_member: false,
+            supplementary_gids: Vec::new(),
+            member_map: MemberMap::default(),
+            shell: default_shell(),
+            shell_rollout: None,
+            shell_rules: Vec::new(),
+            strict_shell: false,
+            strict_fields: false,
+            name_prefix: default_name_prefix(),
+            name_pad_width: 0,
+            name_radix: default_name_radix(),
+            name_scheme: None,
+            name_pattern: None,
+            case_insensitive_names: false,
+            claim_prefix_names_in_gaps: false,
+            home_template: default_home_template(),
+            home_bucket_count: 0,
+            create_home: false,
+            gecos_name: String::new(),
+            gecos_style: GecosStyle::Components,
+            gecos_room: String::new(),
+            gecos_work_phone: String::new(),
+            gecos_home_phone: String::new(),
+            gecos_other: String::new(),
+            passwd_field: default_passwd_field(),
+            group_passwd_field: None,
+            shadow_passwd: default_shadow_passwd(),
+            shadow_locked: false,
+            reload_on_sighup: false,
+            cache_capacity: default_cache_capacity(),
+            nobody: None,
+            netgroup: None,
+            overrides: Overrides::default(),
+            aliases: Aliases::default(),
+            unknown_fields: Vec::new(),
+        }
+    }
+}
+
+/// A config fragment parsed from either format, see
+/// [ConfigFormat::parse_object]
+type JsonObject = serde_json::Map<String, serde_json::Value>;
+
+/// Merge a config fragment into `base`
+///
+/// Arrays are concatenated and tables are merged recursively; any other value
+/// in `fragment` overwrites the one in `base`, including a JSON `null`, which
+/// unsets an optional field.
+fn merge_fragment(base: &mut JsonObject, fragment: JsonObject) {
+    for (key, value) in fragment {
+        match (base.get_mut(&key), value) {
+            (Some(serde_json::Value::Array(base)), serde_json::Value::Array(fragment)) => {
+                base.extend(fragment)
+            }
+            (Some(serde_json::Value::Object(base)), serde_json::Value::Object(fragment)) => {
+                merge_fragment(base, fragment)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parse and merge config fragments in order, then validate the result
+///
+/// Each fragment is given as its origin, used in error messages and to pick
+/// its [ConfigFormat], and its contents. See [load_config_from] for the merge
+/// semantics.
+fn parse_config_fragments<'a>(
+    fragments: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Config> {
+    let mut merged = JsonObject::new();
+    for (origin, contents) in fragments {
+        let fragment = ConfigFormat::from_path(Path::new(origin))
+            .parse_object(contents)
+            .with_context(|| format!("failed to parse {}", origin))?;
+        merge_fragment(&mut merged, fragment);
+    }
+    // deserializing from the object directly can't parse the UID keys of
+    // `overrides`, so go through the JSON text instead
+    parse_config_as(&serde_json::to_string(&merged)?, ConfigFormat::Json)
+}
+
+/// Read a file, returning [None] if it does not exist
+fn read_if_exists(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// List the `*.toml` and `*.json` files in a drop-in directory, in lexical
+/// order
+///
+/// A missing directory has no drop-ins.
+fn drop_in_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read {}", dir.display()))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == "toml" || ext == "json")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load the [Config] from the file at `path`, merged with any drop-ins
+///
+/// The file and each drop-in are parsed as JSON if their name ends in
+/// `.json`, and as TOML otherwise, see [ConfigFormat::from_path].
+///
+/// Drop-ins are the `*.toml` and `*.json` files in the directory next to
+/// `path` with its extension replaced by `.d`, e.g. `/etc/nss_lying.d` for
+/// `/etc/nss_lying.toml`. They are merged over the file in lexical order:
+/// scalar fields and tables such as `nobody` are overwritten field by field,
+/// while list fields such as `ranges` and `exclude_uids` are appended to. A
+/// JSON drop-in may set an optional field to `null` to unset it. The merged
+/// result is validated as a whole, so a fragment may e.g. only add a range.
+///
+/// Falls back to [Config::default] if neither the file nor any drop-ins
+/// exist. Any other failure to read or parse them is returned as an error.
+pub fn load_config_from(path: &Path) -> Result<Config> {
+    let mut fragments = Vec::new();
+    if let Some(contents) = read_if_exists(path)? {
+        fragments.push((path.display().to_string(), contents));
+    }
+    for drop_in in drop_in_paths(&path.with_extension("d"))? {
+        if let Some(contents) = read_if_exists(&drop_in)? {
+            fragments.push((drop_in.display().to_string(), contents));
+        }
+    }
+    if fragments.is_empty() {
+        return Ok(Config::default());
+    }
+
+    parse_config_fragments(
+        fragments
+            .iter()
+            .map(|(origin, contents)| (origin.as_str(), contents.as_str())),
+    )
+    .with_context(|| format!("failed to load {}", path.display()))
+}
+
+/// Parse an environment variable override with [FromStr]
+fn parse_env<T: FromStr>(key: &str, value: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse()
+        .with_context(|| format!("invalid value {:?} for {}", value, key))
+}
+
+impl Config {
+    /// Read, parse, and validate a [Config] in TOML format
+    pub fn from_reader(mut reader: impl io::Read) -> Result<Config> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        parse_config(&contents)
+    }
+
+    /// Read, parse, and validate a [Config] in the given format
+    pub fn from_reader_as(mut reader: impl io::Read, format: ConfigFormat) -> Result<Config> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        parse_config_as(&contents, format)
+    }
+
+    /// Override fields from `NSS_LYING_*` environment variables
+    ///
+    /// `lookup` returns the value of a variable, or [None] if it is unset.
+    /// Unset variables leave the corresponding field untouched.
+    ///
+    /// - `NSS_LYING_UID_MIN`/`NSS_LYING_UID_MAX` replace `ranges` with a
+    ///   single range. If only one is set, the other bound is taken from the
+    ///   lowest start or highest end of the existing ranges.
+    /// - `NSS_LYING_SHELL` sets `shell`.
+    /// - `NSS_LYING_USER_GROUP` sets `user_group`, or unsets it if empty.
+    fn apply_env_overrides(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let uid_min = lookup("NSS_LYING_UID_MIN")
+            .map(|value| parse_env("NSS_LYING_UID_MIN", &value).map(Uid))
+            .transpose()?;
+        let uid_max = lookup("NSS_LYING_UID_MAX")
+            .map(|value| parse_env("NSS_LYING_UID_MAX", &value).map(Uid))
+            .transpose()?;
+        if uid_min.is_some() || uid_max.is_some() {
+            let start = uid_min
+                .or_else(|| self.ranges.iter().map(|range| *range.start()).min())
+                .context("NSS_LYING_UID_MAX is set without NSS_LYING_UID_MIN or any ranges")?;
+            let end = uid_max
+                .or_else(|| self.ranges.iter().map(|range| *range.end()).max())
+                .context("NSS_LYING_UID_MIN is set without NSS_LYING_UID_MAX or any ranges")?;
+            self.ranges = vec![start..=end];
+        }
+
+        if let Some(shell) = lookup("NSS_LYING_SHELL") {
+            self.shell = shell;
+        }
+
+        if let Some(user_group) = lookup("NSS_LYING_USER_GROUP") {
+            self.user_group = match user_group.as_str() {
+                "" => None,
+                value => Some(Gid(parse_env("NSS_LYING_USER_GROUP", value)?)),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+/// Load the [Config] from `/etc/nss_lying.toml` and `/etc/nss_lying.d`,
+/// applying any overrides from `NSS_LYING_*` environment variables
+///
+/// If `NSS_LYING_CONFIG_FD` is set, the config is instead read from that file
+/// descriptor, in the format named by `NSS_LYING_CONFIG_FORMAT` (`toml` by
+/// default), so that e.g. a secret can be handed to a process without writing
+/// it to disk. Drop-ins are not read in that case.
+///
+/// Otherwise, if `CREDENTIALS_DIRECTORY` is set and contains an
+/// `nss_lying.conf` credential, e.g. from systemd's `LoadCredential=` or
+/// `SetCredential=`, the config is read from that file in TOML format, again
+/// without drop-ins.
+///
+/// In secure-execution mode, e.g. when glibc loads the module into a setuid
+/// binary, the environment belongs to a less privileged user, so the
+/// `NSS_LYING_*` variables and `CREDENTIALS_DIRECTORY` are ignored and the
+/// config file is always read.
+pub fn load_config() -> Result<Config> {
+    load_config_with(env_lookup(secure_execution()))
+}
+
+/// Load the [Config] like [load_config], taking a `lookup` function for
+/// environment variables, like [Config::apply_env_overrides]
+fn load_config_with(lookup: impl Fn(&str) -> Option<String>) -> Result<Config> {
+    let mut config = match lookup("NSS_LYING_CONFIG_FD") {
+        Some(fd) => {
+            let fd = parse_env("NSS_LYING_CONFIG_FD", &fd)?;
+            let format = match lookup("NSS_LYING_CONFIG_FORMAT") {
+                Some(format) => format
+                    .parse()
+                    .context("invalid value for NSS_LYING_CONFIG_FORMAT")?,
+                None => ConfigFormat::default(),
+            };
+            load_config_from_fd(fd, format)?
+        }
+        None => match load_config_from_credentials(&lookup)? {
+            Some(config) => config,
+            None => load_config_from(Path::new(CONFIG_PATH))?,
+        },
+    };
+    config.apply_env_overrides(&lookup)?;
+    config.normalize_fields()?;
+    config.validate()?;
+    config.check_shells()?;
+    info!("loaded config: {}", config.describe());
+    Ok(config)
+}
+
+/// Check whether the process runs in secure-execution mode, as for setuid
+/// and setgid binaries, see `getauxval(3)`
+fn secure_execution() -> bool {
+    // SAFETY: `getauxval` only reads the auxiliary vector
+    unsafe { libc::getauxval(libc::AT_SECURE) != 0 }
+}
+
+/// Get a `lookup` function for environment variables, which sees none of
+/// them if `secure`, like glibc's `secure_getenv`
+fn env_lookup(secure: bool) -> impl Fn(&str) -> Option<String> {
+    move |key| {
+        if secure {
+            None
+        } else {
+            std::env::var(key).ok()
+        }
+    }
+}
+
+/// Read, parse, and validate a [Config] from the systemd credential, if
+/// `CREDENTIALS_DIRECTORY` is set and the credential exists
+///
+/// Takes a `lookup` function for environment variables, like
+/// [Config::apply_env_overrides].
+fn load_config_from_credentials(lookup: impl Fn(&str) -> Option<String>) -> Result<Option<Config>> {
+    let Some(dir) = lookup("CREDENTIALS_DIRECTORY") else {
+        return Ok(None);
+    };
+    let path = Path::new(&dir).join(CREDENTIAL_NAME);
+    let Some(contents) = read_if_exists(&path)? else {
+        return Ok(None);
+    };
+    parse_config(&contents)
+        .map(Some)
+        .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Read, parse, and validate a [Config] from a file descriptor
+///
+/// The descriptor is left open, and read from its start if it is seekable,
+/// so that a file or memfd can be read again when the config is reloaded. A
+/// pipe can only be read once, so it comes up empty on a reload, which is
+/// rejected rather than taken as an empty config.
+fn load_config_from_fd(fd: RawFd, format: ConfigFormat) -> Result<Config> {
+    // SAFETY: `F_GETFD` only checks whether the descriptor is open
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("config fd {} is not open", fd));
+    }
+    // SAFETY: the descriptor was just checked to be open, and is only
+    // borrowed to duplicate it
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    let mut file = std::fs::File::from(
+        fd.try_clone_to_owned()
+            .with_context(|| format!("failed to duplicate config fd {:?}", fd))?,
+    );
+    // not seekable if e.g. a pipe, which is read from its current position
+    let _ = file.seek(io::SeekFrom::Start(0));
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .with_context(|| format!("failed to read config fd {:?}", fd))?;
+    if contents.trim().is_empty() {
+        bail!("config fd {:?} is empty", fd);
+    }
+    parse_config_as(&contents, format)
+        .with_context(|| format!("failed to parse config fd {:?}", fd))
+}
+
+/// Set by the SIGHUP handler to ask for the config to be reloaded on the next
+/// lookup
+fn reload_requested() -> &'static Arc<AtomicBool> {
+    static INSTANCE: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    INSTANCE.get_or_init(|| Arc::new(AtomicBool::new(false)))
+}
+
+/// Set while a reload is loading the new config, during which lookups on
+/// other threads still see the previous one
+fn reloading() -> &'static AtomicBool {
+    static INSTANCE: AtomicBool = AtomicBool::new(false);
+    &INSTANCE
+}
+
+/// Get the [Resolver] for the global [Config], loading it on first use
+///
+/// Returns [None] if the config failed to load. The failure is cached, since
+/// there's nowhere to report it from inside an NSS call.
+///
+/// If `reload_on_sighup` is set, a SIGHUP makes the next call reload the
+/// config with [Resolver::reload], keeping the previous one if the reload
+/// fails.
+///
+/// Also returns [None] while the config is resolving a group name on this
+/// thread, so that lookups made by that resolution don't recurse into loading
+/// the config.
+fn resolver() -> Option<&'static Resolver> {
+    // resolving a group name while loading the config may look up groups
+    // through this module again
+    if system::in_lookup() {
+        return None;
+    }
+    static INSTANCE: OnceLock<Option<Resolver>> = OnceLock::new();
+    let resolver = INSTANCE
+        .get_or_init(|| {
+            logging::init(env_lookup(secure_execution()));
+            let config = load_config()
+                .inspect_err(|e| warn!("failed to load config: {:#}", e))
+                .ok()?;
+            if config.reload_on_sighup {
+                // the handler only sets a flag, and chains to any handler the
+                // process already installed. Failing to install it just means
+                // reloading doesn't work, which isn't worth failing lookups
+                // over.
+                let _ = signal_hook::flag::register(
+                    signal_hook::consts::SIGHUP,
+                    reload_requested().clone(),
+                );
+            }
+            #[cfg(feature = "cache")]
+            let cache = cache::global(config.cache_capacity);
+            let resolver = Resolver::from(config);
+            #[cfg(feature = "cache")]
+            let resolver = resolver.with_cache(cache);
+            Some(resolver)
+        })
+        .as_ref()?;
+    if reload_requested().swap(false, Ordering::Relaxed) {
+        reloading().store(true, Ordering::Relaxed);
+        match load_config() {
+            Ok(config) => {
+                resolver.reload(config);
+            }
+            Err(e) => warn!("failed to reload config, keeping the previous one: {:#}", e),
+        }
+        reloading().store(false, Ordering::Relaxed);
+    }
+    Some(resolver)
+}
+
+/// Get the global [Config], loading it on first use
+///
+/// Returns [None] if the config failed to load. Like the hooks, this reloads
+/// the config on SIGHUP if `reload_on_sighup` is set.
+pub fn config() -> Option<Arc<Config>> {
+    resolver().map(Resolver::current)
+}
+
+/// Run a hook against a [Resolver] for the config, if it loaded
+///
+/// A missing config gives `Unavail` rather than `NotFound`, so that NSS falls
+/// through to the next module instead of treating the miss as authoritative.
+/// See [resolve] for lookups made while the config is reloading.
+#[cfg_attr(
+    not(any(feature = "passwd", feature = "group", feature = "netgroup")),
+    allow(dead_code)
+)]
+fn with_resolver<T>(
+    resolver: Option<&Resolver>,
+    hook: impl FnOnce(&Resolver) -> Response<T>,
+) -> Response<T> {
+    let Some(resolver) = resolver else {
+        return Response::Unavail;
+    };
+    resolve(
+        &resolver
+            .clone()
+            .with_reloading(reloading().load(Ordering::Relaxed)),
+        hook,
+    )
+}
+
+/// Run a hook against a [Resolver]
+///
+/// While the resolver's config is reloading, a miss gives `TryAgain` rather
+/// than `NotFound`, since the new config may synthesize the entry, and errno
+/// is set to `EAGAIN` so that glibc doesn't mistake it for a buffer that is
+/// too small.
+#[cfg_attr(
+    not(any(feature = "passwd", feature = "group", feature = "netgroup")),
+    allow(dead_code)
+)]
+fn resolve<T>(resolver: &Resolver, hook: impl FnOnce(&Resolver) -> Response<T>) -> Response<T> {
+    match hook(resolver) {
+        Response::NotFound if resolver.is_reloading() => {
+            // SAFETY: errno is thread-local, and NSS passes it as `errnop`
+            unsafe { *libc::__errno_location() = libc::EAGAIN };
+            Response::TryAgain
+        }
+        response => response,
+    }
+}
+
+/// Log the result of a hook
+///
+/// The arguments are only formatted if debug logging is enabled.
+#[cfg_attr(
+    not(any(feature = "passwd", feature = "group", feature = "netgroup")),
+    allow(dead_code)
+)]
+fn logged<T>(hook: &str, arg: &dyn fmt::Display, response: Response<T>) -> Response<T> {
+    debug!("{}({}) -> {:?}", hook, arg, response.to_status());
+    response
+}
+
+/// Log the result of an enumeration hook, including the number of entries
+#[cfg_attr(not(any(feature = "passwd", feature = "group")), allow(dead_code))]
+fn logged_all<T>(hook: &str, response: Response<Vec<T>>) -> Response<Vec<T>> {
+    match &response {
+        Response::Success(entries) => {
+            debug!("{}() -> Success, {} entries", hook, entries.len())
+        }
+        response => debug!("{}() -> {:?}", hook, response.to_status()),
+    }
+    response
+}
+
+#[cfg(any(feature = "passwd", feature = "group"))]
+struct FakeDb;
+
+/// The counted hooks, taking the resolver to answer with so that they can be
+/// tested against any config rather than the global one
+#[cfg(feature = "passwd")]
+impl FakeDb {
+    fn all_passwd(resolver: Option<&Resolver>) -> Response<Vec<Passwd>> {
+        counted(
+            Lookup::PasswdAll,
+            logged_all(
+                "passwd::get_all_entries",
+                with_resolver(resolver, |resolver| {
+                    Response::Success(resolver.all_passwd())
+                }),
+            ),
+        )
+    }
+
+    fn passwd_by_uid(resolver: Option<&Resolver>, uid: Uid) -> Response<Passwd> {
+        counted(
+            Lookup::PasswdByUid,
+            logged(
+                "passwd::get_entry_by_uid",
+                &uid,
+                with_resolver(resolver, |resolver| {
+                    resolver
+                        .config()
+                        .uid_outcome(uid, resolver.passwd_by_uid(uid))
+                        .into_response()
+                }),
+            ),
+        )
+    }
+
+    fn passwd_by_name(resolver: Option<&Resolver>, name: &str) -> Response<Passwd> {
+        counted(
+            Lookup::PasswdByName,
+            logged(
+                "passwd::get_entry_by_name",
+                &name,
+                with_resolver(resolver, |resolver| {
+                    resolver
+                        .config()
+                        .name_outcome(name, resolver.passwd_by_name(name))
+                        .into_response()
+                }),
+            ),
+        )
+    }
+}
+
+#[cfg(feature = "passwd")]
+libnss_passwd_hooks!(lying, FakeDb);
+#[cfg(feature = "passwd")]
+impl PasswdHooks for FakeDb {
+    fn get_all_entries() -> Response<Vec<Passwd>> {
+        FakeDb::all_passwd(resolver())
+    }
+
+    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+        FakeDb::passwd_by_uid(resolver(), Uid(uid))
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Passwd> {
+        FakeDb::passwd_by_name(resolver(), &name)
+    }
+}
+
+#[cfg(feature = "group")]
+impl FakeDb {
+    fn all_group(resolver: Option<&Resolver>) -> Response<Vec<Group>> {
+        counted(
+            Lookup::GroupAll,
+            logged_all(
+                "group::get_all_entries",
+                with_resolver(resolver, |resolver| Response::Success(resolver.all_group())),
+            ),
+        )
+    }
+
+    fn group_by_gid(resolver: Option<&Resolver>, gid: Gid) -> Response<Group> {
+        counted(
+            Lookup::GroupByGid,
+            logged(
+                "group::get_entry_by_gid",
+                &gid,
+                with_resolver(resolver, |resolver| {
+                    resolver
+                        .config()
+                        .gid_outcome(gid, resolver.group_by_gid(gid))
+                        .into_response()
+                }),
+            ),
+        )
+    }
+
+    fn group_by_name(resolver: Option<&Resolver>, name: &str) -> Response<Group> {
+        counted(
+            Lookup::GroupByName,
+            logged(
+                "group::get_entry_by_name",
+                &name,
+                with_resolver(resolver, |resolver| {
+                    resolver
+                        .config()
+                        .name_outcome(name, resolver.group_by_name(name))
+                        .into_response()
+                }),
+            ),
+        )
+    }
+}
+
+#[cfg(feature = "group")]
+libnss_group_hooks!(lying, FakeDb);
+#[cfg(feature = "group")]
+impl GroupHooks for FakeDb {
+    fn get_all_entries() -> Response<Vec<Group>> {
+        FakeDb::all_group(resolver())
+    }
+
+    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
+        FakeDb::group_by_gid(resolver(), Gid(gid))
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Group> {
+        FakeDb::group_by_name(resolver(), &name)
+    }
+}
+
+#[cfg(feature = "group")]
+impl FakeDb {
+    fn groups_for_user(resolver: Option<&Resolver>, user: &str) -> Response<Vec<Group>> {
+        counted(
+            Lookup::InitgroupsByUser,
+            logged(
+                "initgroups::get_entries_by_user",
+                &user,
+                with_resolver(resolver, |resolver| {
+                    Response::Success(resolver.groups_for_user(user))
+                }),
+            ),
+        )
+    }
+}
+
+#[cfg(feature = "group")]
+libnss_initgroups_hooks!(lying, FakeDb);
+#[cfg(feature = "group")]
+impl InitgroupsHooks for FakeDb {
+    fn get_entries_by_user(user: String) -> Response<Vec<Group>> {
+        FakeDb::groups_for_user(resolver(), &user)
+    }
+}
+
+#[cfg(feature = "passwd")]
+impl FakeDb {
+    fn all_shadow(resolver: Option<&Resolver>) -> Response<Vec<Shadow>> {
+        counted(
+            Lookup::ShadowAll,
+            logged_all(
+                "shadow::get_all_entries",
+                with_resolver(resolver, |resolver| {
+                    Response::Success(resolver.all_shadow())
+                }),
+            ),
+        )
+    }
+
+    fn shadow_by_name(resolver: Option<&Resolver>, name: &str) -> Response<Shadow> {
+        counted(
+            Lookup::ShadowByName,
+            logged(
+                "shadow::get_entry_by_name",
+                &name,
+                with_resolver(resolver, |resolver| {
+                    resolver
+                        .config()
+                        .name_outcome(name, resolver.shadow_by_name(name))
+                        .into_response()
+                }),
+            ),
+        )
+    }
+}
+
+#[cfg(feature = "passwd")]
+libnss_shadow_hooks!(lying, FakeDb);
+#[cfg(feature = "passwd")]
+impl ShadowHooks for FakeDb {
+    fn get_all_entries() -> Response<Vec<Shadow>> {
+        FakeDb::all_shadow(resolver())
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Shadow> {
+        FakeDb::shadow_by_name(resolver(), &name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, os::fd::AsRawFd};
+
+    use super::*;
+    use crate::snapshot::{GroupSnapshot, PasswdSnapshot};
+
+    /// Turn `Some(foo)` to `Success(foo)` and `None` to `NotFound`
+    fn option_to_response<T>(o: Option<T>) -> Response<T> {
+        o.map_or(Response::NotFound, Response::Success)
+    }
+
+    fn base_config() -> Config {
+        Config::default()
+    }
+
+    /// Turn a range of raw UIDs into a range of [Uid]s
+    fn uids(range: RangeInclusive<libc::uid_t>) -> RangeInclusive<Uid> {
+        Uid(*range.start())..=Uid(*range.end())
+    }
+
+    #[test]
+    fn parse_valid_name() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("user-9999"), Some(Uid(9999)));
+    }
+
+    #[test]
+    fn parse_out_of_range_name() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("user-99999"), None);
+    }
+
+    #[test]
+    fn parse_name_leading_zero() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("user-01000"), None);
+        assert_eq!(config.uid_from_name("user-+1000"), None);
+        assert_eq!(config.uid_from_name("user-0"), None);
+
+        let config = Config {
+            ranges: vec![uids(0..=10)],
+            forbid_reserved: false,
+            ..base_config()
+        };
+        assert_eq!(config.uid_from_name("user-0"), Some(Uid(0)));
+        assert_eq!(config.uid_from_name("user-00"), None);
+        assert_eq!(config.uid_from_name("user-010"), None);
+        assert_eq!(config.uid_from_name("user-10"), Some(Uid(10)));
+    }
+
+    #[test]
+    fn parse_name_overflow() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("user-99999999999999999999"), None);
+        assert_eq!(
+            config.uid_from_name(&format!("user-{}", u64::from(libc::uid_t::MAX) + 1)),
+            None
+        );
+        // the maximum UID parses, but is out of range
+        assert_eq!(
+            config.uid_from_name(&format!("user-{}", libc::uid_t::MAX)),
+            None
+        );
+
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 10..=libc::uid_t::MAX)],
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_from_name(&format!("user-{}", libc::uid_t::MAX)),
+            Some(Uid(libc::uid_t::MAX))
+        );
+        assert_eq!(
+            config.uid_from_name(&format!("user-{}", u64::from(libc::uid_t::MAX) + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_name_empty_suffix() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("user-"), None);
+        assert_eq!(config.uid_from_name(""), None);
+
+        let config = Config {
+            name_prefix: String::new(),
+            ..base_config()
+        };
+        assert_eq!(config.uid_from_name(""), None);
+    }
+
+    #[test]
+    fn parse_name_non_digit_suffix() {
+        let config = base_config();
+        for name in [
+            "user-abc",
+            "user-1000abc",
+            "user- 10",
+            "user-1000 ",
+            " user-1000",
+            "user-1000\n",
+            "user-1000\0",
+            "user--1000",
+            "user-1_000",
+            "user-1.0",
+            "user-\u{0661}\u{0660}\u{0660}\u{0660}",
+        ] {
+            assert_eq!(config.uid_from_name(name), None, "{:?}", name);
+            assert_eq!(config.gid_from_name(name), None, "{:?}", name);
+            assert!(config.passwd_by_name(name).is_none(), "{:?}", name);
+        }
+
+        // hexadecimal names are just as strict
+        let config = Config {
+            name_radix: 16,
+            ..base_config()
+        };
+        for name in ["user-3e8\n", "user-0x3e8", "user-3g8"] {
+            assert_eq!(config.uid_from_name(name), None, "{:?}", name);
+        }
+        assert_eq!(config.uid_from_name("user-3e8"), Some(Uid(1000)));
+    }
+
+    /// Names UIDs as the English words for their digits, e.g. `one-zero`
+    struct WordScheme;
+
+    const DIGIT_WORDS: [&str; 10] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+
+    impl NameScheme for WordScheme {
+        fn name(&self, uid: Uid) -> Option<String> {
+            let words: Vec<_> = uid
+                .0
+                .to_string()
+                .bytes()
+                .map(|b| DIGIT_WORDS[usize::from(b - b'0')])
+                .collect();
+            Some(words.join("-"))
+        }
+
+        fn uid(&self, name: &str) -> Option<Uid> {
+            let digits = name
+                .split('-')
+                .map(|word| {
+                    let digit = DIGIT_WORDS.iter().position(|&w| w == word)?;
+                    char::from_digit(digit as u32, 10)
+                })
+                .collect::<Option<String>>()?;
+            let uid = Uid(digits.parse().ok()?);
+            (self.name(uid)? == name).then_some(uid)
+        }
+    }
+
+    #[test]
+    fn default_name_scheme() {
+        let config = Config {
+            name_pad_width: 6,
+            name_radix: 16,
+            ..base_config()
+        };
+        let scheme = PrefixScheme {
+            pad_width: 6,
+            radix: 16,
+            ..PrefixScheme::new("user-")
+        };
+        for uid in [1000, 1234, 9999].map(Uid) {
+            assert_eq!(config.name_for_uid(uid), scheme.name(uid));
+            let name = scheme.name(uid).unwrap();
+            assert_eq!(config.uid_from_name(&name), scheme.uid(&name));
+        }
+    }
+
+    #[test]
+    fn custom_name_scheme() {
+        let config = Config {
+            name_scheme: Some(CustomNameScheme::new(WordScheme)),
+            ..base_config()
+        };
+        assert_eq!(
+            config.name_for_uid(Uid(1024)).as_deref(),
+            Some("one-zero-two-four")
+        );
+        assert_eq!(config.uid_from_name("one-zero-two-four"), Some(Uid(1024)));
+        assert_eq!(config.uid_from_name("user-1024"), None);
+        // the range still applies
+        assert_eq!(config.name_for_uid(Uid(10000)), None);
+        assert_eq!(config.uid_from_name("one-zero-zero-zero-zero"), None);
+        assert_eq!(config.uid_from_name("zero-one-zero-two-four"), None);
+
+        let passwd = config.passwd_by_name("nine-nine-nine-nine").unwrap();
+        assert_eq!(passwd.uid, 9999);
+        assert_eq!(
+            config.gid_to_group(Gid(9999)).unwrap().name,
+            "nine-nine-nine-nine"
+        );
+        assert!(matches!(
+            config.name_outcome("one-two", config.passwd_by_name("one-two")),
+            Outcome::Invalid
+        ));
+        assert!(config.clone() == config);
+    }
+
+    #[test]
+    fn name_pattern() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            name_pattern = "user-[0-9]{4}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.uid_from_name("user-1000"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-1000x"), None);
+        assert!(config.passwd_by_name("user-9999").is_some());
+        assert!(config.group_by_name("user-1000x").is_none());
+
+        let config = Config {
+            name_pattern: Some(NamePattern::new("user-1[0-9]{3}").unwrap()),
+            ..base_config()
+        };
+        assert_eq!(config.uid_from_name("user-1999"), Some(Uid(1999)));
+        assert_eq!(config.uid_from_name("user-2000"), None);
+        // only lookups by name are restricted
+        assert_eq!(config.name_for_uid(Uid(2000)).as_deref(), Some("user-2000"));
+    }
+
+    #[test]
+    fn parse_config_invalid_name_pattern() {
+        let err = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            name_pattern = "user-("
+            "#,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("name_pattern"), "{:#}", err);
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        let config = base_config();
+        assert_eq!(config.uid_from_name("USER-1000"), None);
+        assert_eq!(config.gid_from_name("User-1000"), None);
+
+        let config = Config {
+            case_insensitive_names: true,
+            ..base_config()
+        };
+        assert_eq!(config.uid_from_name("USER-1000"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("User-1000"), Some(Uid(1000)));
+        assert_eq!(config.gid_from_name("USER-1000"), Some(Gid(1000)));
+        // lookups still return the canonical name
+        assert_eq!(
+            config.passwd_by_name("USER-1000").unwrap().name,
+            "user-1000"
+        );
+        assert_eq!(config.group_by_name("User-1000").unwrap().name, "user-1000");
+        assert_eq!(config.uid_from_name("USER-0999"), None);
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..config
+        };
+        assert_eq!(config.gid_from_name("USERS"), Some(Gid(100)));
+        assert_eq!(config.group_by_name("USERS").unwrap().name, "users");
+    }
+
+    #[test]
+    fn hex_names() {
+        let config = Config {
+            name_radix: 16,
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(1000)).as_deref(), Some("user-3e8"));
+        assert_eq!(config.uid_from_name("user-3e8"), Some(Uid(1000)));
+        assert_eq!(config.name_for_uid(Uid(9999)).as_deref(), Some("user-270f"));
+        // only the canonical form is accepted
+        assert_eq!(config.uid_from_name("user-3E8"), None);
+        assert_eq!(config.uid_from_name("user-03e8"), None);
+        assert_eq!(config.uid_from_name("user-+3e8"), None);
+        assert_eq!(config.uid_from_name("user-1000"), Some(Uid(0x1000)));
+
+        let config = Config {
+            name_pad_width: 5,
+            ..config
+        };
+        assert_eq!(
+            config.name_for_uid(Uid(1000)).as_deref(),
+            Some("user-003e8")
+        );
+        assert_eq!(config.uid_from_name("user-003e8"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-3e8"), None);
+
+        let config = Config {
+            name_radix: 8,
+            name_pad_width: 0,
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(1000)).as_deref(), Some("user-1750"));
+        assert_eq!(config.uid_from_name("user-1750"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-1758"), None);
+    }
+
+    #[test]
+    fn hex_names_roundtrip() {
+        let config = Config {
+            name_radix: 16,
+            ranges: vec![
+                uids(1000..=9999),
+                uids(libc::uid_t::MAX - 100..=libc::uid_t::MAX),
+            ],
+            ..base_config()
+        };
+        for uid in config.uid_range() {
+            assert_eq!(
+                config.uid_from_name(&config.name_for_uid(uid).unwrap()),
+                Some(uid)
+            );
+        }
+    }
+
+    #[test]
+    fn validate_name_radix() {
+        for name_radix in [8, 10, 16] {
+            let config = Config {
+                name_radix,
+                ..base_config()
+            };
+            assert!(config.validate().is_ok());
+        }
+        for name_radix in [0, 2, 36] {
+            let config = Config {
+                name_radix,
+                ..base_config()
+            };
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn padded_names() {
+        let config = Config {
+            name_pad_width: 5,
+            ranges: vec![uids(0..=200000)],
+            forbid_reserved: false,
+            ..base_config()
+        };
+        assert_eq!(
+            config.name_for_uid(Uid(1000)).as_deref(),
+            Some("user-01000")
+        );
+        assert_eq!(config.name_for_uid(Uid(0)).as_deref(), Some("user-00000"));
+        assert_eq!(
+            config.name_for_uid(Uid(123456)).as_deref(),
+            Some("user-123456")
+        );
+        assert_eq!(config.uid_from_name("user-01000"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-00000"), Some(Uid(0)));
+        assert_eq!(config.uid_from_name("user-123456"), Some(Uid(123456)));
+        assert_eq!(config.gid_from_name("user-01000"), Some(Gid(1000)));
+
+        // widths other than the configured one are rejected
+        assert_eq!(config.uid_from_name("user-1000"), None);
+        assert_eq!(config.uid_from_name("user-001000"), None);
+        assert_eq!(config.uid_from_name("user-0123456"), None);
+        assert_eq!(config.uid_from_name("user-0"), None);
+    }
+
+    #[test]
+    fn parse_group_name_single_group() {
+        let config = Config {
+            user_group: Some(Gid(1000)),
+            ..base_config()
+        };
+        assert_eq!(config.gid_from_name("users"), Some(Gid(1000)));
+        assert_eq!(config.gid_from_name("user-1000"), None);
+        assert_eq!(config.gid_from_name("user-9999"), None);
+
+        let config = Config {
+            shared_group_name: Some("staff".into()),
+            ..config
+        };
+        assert_eq!(config.gid_from_name("staff"), Some(Gid(1000)));
+        assert_eq!(config.gid_from_name("users"), None);
+        assert_eq!(config.name_for_gid(Gid(1000)).as_deref(), Some("staff"));
+        assert_eq!(
+            config.gid_from_name(&config.name_for_gid(Gid(1000)).unwrap()),
+            Some(Gid(1000))
+        );
+    }
+
+    #[test]
+    fn parse_group_name_template() {
+        let config = Config {
+            user_group: Some(Gid(500)),
+            shared_group_template: Some("group-{gid}".into()),
+            ..base_config()
+        };
+        assert_eq!(config.name_for_gid(Gid(500)).as_deref(), Some("group-500"));
+        assert_eq!(config.gid_from_name("group-500"), Some(Gid(500)));
+        assert_eq!(config.gid_from_name("group-501"), None);
+        assert_eq!(config.gid_from_name("group-0500"), None);
+        assert_eq!(config.gid_from_name("users"), None);
+        assert_eq!(config.gid_to_group(Gid(500)).unwrap().name, "group-500");
+        assert!(config.describe().contains("group-500 (500)"));
+
+        // only applies to the shared group
+        let config = Config {
+            user_group: None,
+            ..config
+        };
+        assert_eq!(config.name_for_gid(Gid(1000)).as_deref(), Some("user-1000"));
+        assert_eq!(config.gid_from_name("group-500"), None);
+    }
+
+    #[test]
+    fn parse_config_shared_group_template() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 500
+            shared_group_template = "group-{gid}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.name_for_gid(Gid(500)).as_deref(), Some("group-500"));
+
+        for extra in [
+            r#"shared_group_template = "group-{uid}""#,
+            r#"shared_group_template = "group:{gid}""#,
+            r#"shared_group_template = """#,
+            "shared_group_template = \"group-{gid}\"\nshared_group_name = \"staff\"",
+        ] {
+            assert!(
+                parse_config(&format!(
+                    "ranges = [{{ start = 1000, end = 9999 }}]\nuser_group = 500\n{}",
+                    extra
+                ))
+                .is_err(),
+                "{}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn group_naming() {
+        // derived from the legacy options
+        assert_eq!(base_config().group_naming(), GroupNaming::PerUser);
+        let legacy = Config {
+            user_group: Some(Gid(100)),
+            shared_group_name: Some("staff".into()),
+            ..base_config()
+        };
+        let shared = GroupNaming::Shared {
+            name: "staff".into(),
+            gid: Gid(100),
+        };
+        assert_eq!(legacy.group_naming(), shared);
+
+        let config = Config {
+            group_naming: Some(shared.clone()),
+            ..base_config()
+        };
+        assert_eq!(config.group_naming(), shared);
+        for config in [&config, &legacy] {
+            assert_eq!(config.gid_for_uid(Uid(1234)), Some(Gid(100)));
+            assert_eq!(config.name_for_gid(Gid(100)).as_deref(), Some("staff"));
+            assert_eq!(config.name_for_gid(Gid(1234)), None);
+            assert_eq!(config.gid_from_name("staff"), Some(Gid(100)));
+            assert_eq!(config.gid_from_name("user-1234"), None);
+        }
+
+        // an explicit per-user policy ignores the legacy options
+        let config = Config {
+            group_naming: Some(GroupNaming::PerUser),
+            ..legacy
+        };
+        assert_eq!(config.group_naming(), GroupNaming::PerUser);
+        assert_eq!(config.gid_for_uid(Uid(1234)), Some(Gid(1234)));
+        assert_eq!(config.name_for_gid(Gid(1234)).as_deref(), Some("user-1234"));
+        assert_eq!(config.name_for_gid(Gid(100)), None);
+        assert_eq!(config.gid_from_name("user-1234"), Some(Gid(1234)));
+        assert_eq!(config.gid_from_name("staff"), None);
+    }
+
+    #[test]
+    fn primary_gid_round_robin() {
+        let config = Config {
+            primary_gid_strategy: Some(PrimaryGidStrategy::RoundRobin(vec![
+                Gid(100),
+                Gid(101),
+                Gid(102),
+            ])),
+            ..base_config()
+        };
+        assert!(config.validate().is_ok());
+        for (uid, gid) in [(1000, 101), (1001, 102), (1002, 100), (1003, 101)] {
+            assert_eq!(config.gid_for_uid(Uid(uid)), Some(Gid(gid)), "{}", uid);
+            assert_eq!(config.uid_to_passwd(Uid(uid)).unwrap().gid, gid);
+        }
+        // the groups are real, and there are no per-user groups
+        for gid in [100, 1000, 1001].map(Gid) {
+            assert_eq!(config.name_for_gid(gid), None, "{}", gid);
+            assert!(config.gid_to_group(gid).is_none());
+        }
+        assert_eq!(config.gid_from_name("user-1000"), None);
+        assert!(config.group_entries().is_empty());
+        assert_eq!(config.groups().count(), 0);
+
+        // the other strategies, derived from the legacy options
+        assert_eq!(
+            base_config().primary_gid_strategy(),
+            PrimaryGidStrategy::PerUser
+        );
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..base_config()
+        };
+        assert_eq!(
+            config.primary_gid_strategy(),
+            PrimaryGidStrategy::Shared(Gid(100))
+        );
+        let config = Config {
+            primary_gid_strategy: Some(PrimaryGidStrategy::Shared(Gid(100))),
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(1234)), Some(Gid(100)));
+        assert_eq!(config.name_for_gid(Gid(100)).as_deref(), Some("users"));
+    }
+
+    #[test]
+    fn parse_config_primary_gid_strategy() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            primary_gid_strategy = { round_robin = [100, 101] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.primary_gid_strategy,
+            Some(PrimaryGidStrategy::RoundRobin(vec![Gid(100), Gid(101)]))
+        );
+
+        for extra in [
+            "primary_gid_strategy = { round_robin = [] }",
+            "primary_gid_strategy = { shared = 100 }\nuser_group = 100",
+            "primary_gid_strategy = \"per_user\"\ngroup_naming = \"per_user\"",
+            "primary_gid_strategy = { shared = 1000 }",
+        ] {
+            assert!(
+                parse_config(&format!(
+                    "ranges = [{{ start = 1000, end = 9999 }}]\n{}",
+                    extra
+                ))
+                .is_err(),
+                "{}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn parse_config_group_naming() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            group_naming = { shared = { name = "staff", gid = 100 } }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.group_naming,
+            Some(GroupNaming::Shared {
+                name: "staff".into(),
+                gid: Gid(100)
+            })
+        );
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            group_naming = "per_user"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.group_naming, Some(GroupNaming::PerUser));
+
+        for extra in [
+            "group_naming = \"per_user\"\nuser_group = 100",
+            "group_naming = \"per_user\"\nshared_group_name = \"staff\"",
+            r#"group_naming = { shared = { name = "st:aff", gid = 100 } }"#,
+            r#"group_naming = { shared = { name = "staff", gid = 1000 } }"#,
+            r#"group_naming = "shared""#,
+        ] {
+            assert!(
+                parse_config(&format!(
+                    "ranges = [{{ start = 1000, end = 9999 }}]\n{}",
+                    extra
+                ))
+                .is_err(),
+                "{}",
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn shared_group_enumerated_once() {
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..base_config()
+        };
+        let groups = config.group_entries();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].gid, 100);
+        assert_eq!(groups[0].name, "users");
+        assert_eq!(config.groups().count(), 1);
+
+        // bypasses validation, which rejects a shared GID that is also a
+        // per-user GID
+        let config = Config {
+            user_group: Some(Gid(1000)),
+            ..base_config()
+        };
+        let groups = config.group_entries();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].gid, 1000);
+        assert_eq!(groups[0].name, "users");
+
+        // a shared GID that fails to resolve is left out with a warning
+        let mut group = None;
+        let logs = capture_logs(|| group = config.shared_group_entry_with(|_| None));
+        assert!(group.is_none());
+        assert_eq!(logs, ["shared group 1000 does not resolve, leaving it out"]);
+    }
+
+    #[test]
+    fn hybrid_groups_by_gid() {
+        let config = Config {
+            ranges: vec![uids(1000..=1004)],
+            user_group: Some(Gid(100)),
+            enumerate_per_user_groups: true,
+            list_owner_as_member: true,
+            ..base_config()
+        };
+        let group = config.gid_to_group(Gid(100)).unwrap();
+        assert_eq!((group.name.as_str(), group.gid), ("users", 100));
+        let group = config.gid_to_group(Gid(1003)).unwrap();
+        assert_eq!((group.name.as_str(), group.gid), ("user-1003", 1003));
+        assert_eq!(group.members, ["user-1003".to_owned()]);
+
+        // rejected when loaded, but the shared group wins if validation is
+        // bypassed
+        let config = Config {
+            user_group: Some(Gid(1002)),
+            ..config
+        };
+        assert!(config.validate().is_err());
+        assert_eq!(
+            GroupSnapshot::from(&config.gid_to_group(Gid(1002)).unwrap()),
+            GroupSnapshot {
+                name: "users".into(),
+                passwd: "x".into(),
+                gid: 1002,
+                members: Vec::new(),
+            }
+        );
+        assert_eq!(config.gid_from_name("users"), Some(Gid(1002)));
+        assert_eq!(config.gid_from_name("user-1002"), None);
+        assert!(config.group_by_name("user-1002").is_none());
+        assert_eq!(config.gid_from_name("user-1003"), Some(Gid(1003)));
+        let gids: Vec<_> = config
+            .supplementary_groups("user-1002")
+            .iter()
+            .map(|g| g.gid)
+            .collect();
+        assert!(gids.is_empty());
+
+        let entries: Vec<_> = config
+            .group_entries()
+            .iter()
+            .map(|g| (g.gid, g.name.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (1000, "user-1000".to_owned()),
+                (1001, "user-1001".to_owned()),
+                (1002, "users".to_owned()),
+                (1003, "user-1003".to_owned()),
+                (1004, "user-1004".to_owned()),
+            ]
+        );
+        let lazy: Vec<_> = config.groups().map(|g| (g.gid, g.name)).collect();
+        assert_eq!(lazy, entries);
+        assert_eq!(config.gid_count(), 5);
+    }
+
+    #[test]
+    fn enumerate_per_user_groups() {
+        let config = Config {
+            ranges: vec![uids(1000..=1004)],
+            user_group: Some(Gid(100)),
+            enumerate_per_user_groups: true,
+            list_owner_as_member: true,
+            supplementary_gids: vec![Gid(27)],
+            ..base_config()
+        };
+        // passwd entries keep the shared primary group
+        assert_eq!(config.gid_for_uid(Uid(1000)), Some(Gid(100)));
+        assert_eq!(config.uid_to_passwd(Uid(1003)).unwrap().gid, 100);
+
+        // both the shared group and the per-user groups resolve
+        assert_eq!(config.name_for_gid(Gid(100)).as_deref(), Some("users"));
+        assert_eq!(config.gid_from_name("users"), Some(Gid(100)));
+        assert_eq!(config.name_for_gid(Gid(1002)).as_deref(), Some("user-1002"));
+        assert_eq!(config.gid_from_name("user-1002"), Some(Gid(1002)));
+        assert_eq!(config.name_for_gid(Gid(1005)), None);
+        let group = config.group_by_name("user-1002").unwrap();
+        assert_eq!(group.gid, 1002);
+        assert_eq!(group.members, vec!["user-1002".to_string()]);
+        assert!(config.gid_to_group(Gid(100)).unwrap().members.is_empty());
+
+        let gids: Vec<_> = config.group_entries().iter().map(|g| g.gid).collect();
+        assert_eq!(gids, vec![100, 1000, 1001, 1002, 1003, 1004]);
+        let lazy: Vec<_> = config.groups().map(|g| g.gid).collect();
+        assert_eq!(lazy, gids);
+
+        // the per-user group is a supplementary group of its owner
+        let gids: Vec<_> = config
+            .supplementary_groups("user-1002")
+            .iter()
+            .map(|g| g.gid)
+            .collect();
+        assert_eq!(gids, vec![1002, 27]);
+
+        // the shared group is merged in GID order
+        let config = Config {
+            ranges: vec![uids(1000..=1001), uids(1003..=1004)],
+            user_group: Some(Gid(1002)),
+            ..config
+        };
+        let gids: Vec<_> = config.group_entries().iter().map(|g| g.gid).collect();
+        assert_eq!(gids, vec![1000, 1001, 1002, 1003, 1004]);
+        let lazy: Vec<_> = config.groups().map(|g| g.gid).collect();
+        assert_eq!(lazy, gids);
+        let config = Config {
+            user_group: Some(Gid(5000)),
+            ..config
+        };
+        let gids: Vec<_> = config.groups().map(|g| g.gid).collect();
+        assert_eq!(gids, vec![1000, 1001, 1003, 1004, 5000]);
+        assert_eq!(config.group_entries().len(), 5);
+
+        // per-user groups stay hidden without the option
+        let config = Config {
+            enumerate_per_user_groups: false,
+            ..config
+        };
+        assert_eq!(config.name_for_gid(Gid(1000)), None);
+        assert_eq!(config.gid_from_name("user-1003"), None);
+        assert_eq!(config.group_entries().len(), 1);
+        assert_eq!(config.supplementary_groups("user-1003").len(), 1);
+    }
+
+    #[test]
+    fn parse_config_user_group_by_gid_or_name() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.user_group, Some(Gid(100)));
+        assert_eq!(
+            parse_config_as(
+                r#"{ "ranges": [{ "start": 1000, "end": 9999 }], "user_group": 100 }"#,
+                ConfigFormat::Json
+            )
+            .unwrap()
+            .user_group,
+            Some(Gid(100))
+        );
+
+        let err = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = "nss-lying-no-such-group"
+            "#,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("does not exist"), "{:#}", err);
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = -1
+            "#,
+        )
+        .is_err());
+
+        // only meaningful where the system has a root group
+        if std::fs::read_to_string("/etc/group").is_ok_and(|group| group.starts_with("root:")) {
+            let config = parse_config(
+                r#"
+                ranges = [{ start = 1000, end = 9999 }]
+                user_group = "root"
+                "#,
+            )
+            .unwrap();
+            assert_eq!(config.user_group, Some(Gid(0)));
+        }
+    }
+
+    #[test]
+    fn parse_config_enumerate_per_user_groups_exclusive() {
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 100
+            enumerate_per_user_groups = true
+            also_per_user_group_names = true
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_group_name_also_per_user() {
+        let config = Config {
+            user_group: Some(Gid(100)),
+            shared_group_name: Some("staff".into()),
+            also_per_user_group_names: true,
+            ..base_config()
+        };
+        assert_eq!(config.gid_from_name("staff"), Some(Gid(100)));
+        assert_eq!(config.gid_from_name("user-1000"), Some(Gid(100)));
+        assert_eq!(config.gid_from_name("user-9999"), Some(Gid(100)));
+        assert_eq!(config.gid_from_name("user-99999"), None);
+        assert_eq!(config.name_for_gid(Gid(100)).as_deref(), Some("staff"));
+        assert_eq!(config.name_for_gid(Gid(1000)), None);
+
+        // the shared group is returned under its canonical name either way
+        let shared = config.group_by_name("staff").unwrap();
+        let per_user = config.group_by_name("user-1000").unwrap();
+        assert_eq!(per_user.gid, 100);
+        assert_eq!(per_user.name, "staff");
+        assert_eq!(per_user.members, shared.members);
+        assert_eq!(config.gid_to_group(Gid(100)).unwrap().name, "staff");
+        assert_eq!(config.group_entries().len(), 1);
+
+        // no effect without a shared group
+        let config = Config {
+            user_group: None,
+            ..config
+        };
+        assert_eq!(config.gid_from_name("user-1000"), Some(Gid(1000)));
+        assert_eq!(config.gid_from_name("staff"), None);
+    }
+
+    #[test]
+    fn parse_group_name_user_groups() {
+        let config = Config {
+            user_group: None,
+            ..base_config()
+        };
+        assert_eq!(config.gid_from_name("users"), None);
+        assert_eq!(config.gid_from_name("user-1000"), Some(Gid(1000)));
+        assert_eq!(config.gid_from_name("user-9999"), Some(Gid(9999)));
+    }
+
+    #[test]
+    fn parse_group_name_user_groups_out_of_range() {
+        let config = Config {
+            user_group: None,
+            ..base_config()
+        };
+        assert_eq!(config.gid_from_name("user-99999"), None);
+    }
+
+    #[test]
+    fn parse_full_config() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 2000, end = 2999 }, { start = 60000, end = 65000 }]
+            exclude_uids = [2000, 2500]
+            forbid_reserved = false
+            reserved_below = 500
+            user_group = 100
+            gid_offset = 1000
+            shared_group_name = "staff"
+            supplementary_gids = [27, 100]
+            shell = "/bin/sh"
+            shell_rules = [[{ start = 60000, end = 65000 }, "/usr/sbin/nologin"]]
+            name_prefix = "svc-"
+            home_template = "/home/{bucket}/{name}"
+            home_bucket_count = 16
+            gecos_template = "Synthetic User {uid}"
+            passwd_field = "*"
+            shadow_passwd = "!"
+            shadow_locked = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.ranges, vec![uids(2000..=2999), uids(60000..=65000)]);
+        assert_eq!(*config.exclude_uids, [Uid(2000), Uid(2500)]);
+        assert!(!config.forbid_reserved);
+        assert_eq!(config.reserved_below, Uid(500));
+        assert_eq!(config.user_group, Some(Gid(100)));
+        assert_eq!(config.gid_offset, 1000);
+        assert_eq!(config.shared_group_name.as_deref(), Some("staff"));
+        assert_eq!(config.supplementary_gids, vec![Gid(27), Gid(100)]);
+        assert_eq!(config.shell, "/bin/sh");
+        assert_eq!(
+            config.shell_rules,
+            vec![(uids(60000..=65000), "/usr/sbin/nologin".to_string())]
+        );
+        assert_eq!(config.name_prefix, "svc-");
+        assert_eq!(config.home_template, "/home/{bucket}/{name}");
+        assert_eq!(config.home_bucket_count, 16);
+        assert_eq!(config.gecos_name, "Synthetic User {uid}");
+        assert_eq!(config.passwd_field, "*");
+        assert_eq!(config.shadow_passwd, "!");
+        assert!(config.shadow_locked);
+    }
+
+    #[test]
+    fn default_config() {
+        assert_eq!(
+            Config::default(),
+            Config {
+                ranges: vec![uids(1000..=9999)],
+                exclude_uids: ExcludedUids::default(),
+                exclude_ranges: Vec::new(),
+                allow_uids: None,
+                forbid_reserved: true,
+                reserved_below: Uid(1000),
+                defer_to_real: false,
+                max_range_size: 1_000_000,
+                enumerable: true,
+                enumeration_order: Order::Ascending,
+                enumeration_chunk_size: None,
+                user_group: None,
+                gid_offset: 0,
+                fallback_gid: None,
+                shared_group_name: None,
+                shared_group_template: None,
+                also_per_user_group_names: false,
+                group_naming: None,
+                primary_gid_strategy: None,
+                enumerate_per_user_groups: false,
+                populate_members: false,
+                max_members: 0,
+                max_line_len: 1024,
+                list_owner_as_member: false,
+                supplementary_gids: Vec::new(),
+                member_map: MemberMap::default(),
+                shell: "/bin/bash".into(),
+                shell_rollout: None,
+                shell_rules: Vec::new(),
+                strict_shell: false,
+                strict_fields: false,
+                name_prefix: "user-".into(),
+                name_pad_width: 0,
+                name_radix: 10,
+                name_scheme: None,
+                name_pattern: None,
+                case_insensitive_names: false,
+                claim_prefix_names_in_gaps: false,
+                home_template: "/tmp".into(),
+                home_bucket_count: 0,
+                create_home: false,
+                gecos_name: "".into(),
+                gecos_style: GecosStyle::Components,
+                gecos_room: "".into(),
+                gecos_work_phone: "".into(),
+                gecos_home_phone: "".into(),
+                gecos_other: "".into(),
+                passwd_field: "x".into(),
+                group_passwd_field: None,
+                shadow_passwd: "*".into(),
+                shadow_locked: false,
+                reload_on_sighup: false,
+                cache_capacity: 1024,
+                nobody: None,
+                netgroup: None,
+                overrides: Overrides::default(),
+                aliases: Aliases::default(),
+                unknown_fields: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_minimal_config() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config, base_config());
+    }
+
+    #[test]
+    fn merge_config_fragments() {
+        let config = parse_config_fragments([
+            (
+                "base",
+                r#"
+                ranges = [{ start = 1000, end = 1999 }]
+                exclude_uids = [1001]
+                shell = "/bin/sh"
+                populate_members = true
+                [nobody]
+                name = "nobody"
+                "#,
+            ),
+            (
+                "10-ci.toml",
+                r#"
+                ranges = [{ start = 60000, end = 60999 }]
+                exclude_uids = [60000]
+                shell = "/usr/sbin/nologin"
+                [nobody]
+                uid = 65000
+                "#,
+            ),
+            (
+                "20-extra.toml",
+                r#"
+                ranges = [{ start = 70000, end = 70009 }]
+                name_prefix = "svc-"
+                "#,
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.ranges,
+            vec![uids(1000..=1999), uids(60000..=60999), uids(70000..=70009)]
+        );
+        assert_eq!(*config.exclude_uids, [Uid(1001), Uid(60000)]);
+        assert_eq!(config.shell, "/usr/sbin/nologin");
+        assert_eq!(config.name_prefix, "svc-");
+        assert!(config.populate_members);
+        let nobody = config.nobody.unwrap();
+        assert_eq!(nobody.name, "nobody");
+        assert_eq!(nobody.uid, Uid(65000));
+    }
+
+    #[test]
+    fn merge_config_fragments_validates_result() {
+        // a fragment alone doesn't have to be a complete config
+        assert!(parse_config_fragments([("10-shell.toml", r#"shell = "/bin/sh""#)]).is_err());
+
+        let err = parse_config_fragments([
+            ("base", "ranges = [{ start = 1000, end = 1999 }]"),
+            ("10-overlap.toml", "ranges = [{ start = 1500, end = 2999 }]"),
+        ])
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("overlap"), "{:#}", err);
+
+        let err = parse_config_fragments([
+            ("base", "ranges = [{ start = 1000, end = 1999 }]"),
+            ("10-broken.toml", "ranges = "),
+        ])
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("10-broken.toml"), "{:#}", err);
+    }
+
+    #[test]
+    fn merge_config_fragments_with_null() {
+        let json = r#"{ "user_group": 100, "shared_group_name": null, "nobody": null }"#;
+        let config = parse_config_fragments([
+            (
+                "base",
+                r#"
+                ranges = [{ start = 1000, end = 1999 }]
+                shared_group_name = "staff"
+                [nobody]
+                name = "nobody"
+                "#,
+            ),
+            ("10.json", json),
+        ])
+        .unwrap();
+        assert_eq!(config.user_group, Some(Gid(100)));
+        assert_eq!(config.shared_group_name, None);
+        assert_eq!(config.nobody, None);
+
+        // the same as a whole JSON config
+        let json = r#"{
+            "ranges": [{ "start": 1000, "end": 1999 }],
+            "user_group": 100,
+            "shared_group_name": null,
+            "nobody": null
+        }"#;
+        assert_eq!(
+            Config::from_reader_as(json.as_bytes(), ConfigFormat::Json).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn load_config_with_drop_ins() {
+        let dir = std::env::temp_dir().join(format!("nss_lying-drop-ins-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nss_lying.d")).unwrap();
+        let path = dir.join("nss_lying.toml");
+        std::fs::write(&path, "ranges = [{ start = 1000, end = 1999 }]").unwrap();
+        std::fs::write(
+            dir.join("nss_lying.d/20-b.toml"),
+            "ranges = [{ start = 3000, end = 3999 }]",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("nss_lying.d/10-a.toml"),
+            "ranges = [{ start = 2000, end = 2999 }]",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("nss_lying.d/30-c.json"),
+            r#"{ "ranges": [{ "start": 4000, "end": 4999 }] }"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("nss_lying.d/README"), "not a fragment").unwrap();
+
+        let config = load_config_from(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            config.unwrap().ranges,
+            vec![
+                uids(1000..=1999),
+                uids(2000..=2999),
+                uids(3000..=3999),
+                uids(4000..=4999)
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_malformed_config() {
+        assert!(parse_config("ranges = ").is_err());
+        assert!(parse_config("ranges = [{ start = \"1000\", end = 9999 }]").is_err());
+        assert!(parse_config("ranges = [{ end = 9999 }]").is_err());
+        assert!(parse_config("shell = \"/bin/sh\"").is_err());
+    }
+
+    #[test]
+    fn parse_config_unknown_home_placeholder() {
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            home_template = "/home/{username}"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_unknown_gecos_placeholder() {
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            gecos_template = "User {gid}"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_name_prefix() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            name_prefix = "svc_x.-"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.name_for_uid(Uid(1000)).as_deref(),
+            Some("svc_x.-1000")
+        );
+
+        for prefix in ["svc:", "svc ", "svc\t", "svc\n", "svc\0", "usér-", "用户"] {
+            let config = Config {
+                name_prefix: prefix.into(),
+                ..base_config()
+            };
+            let err = config.validate().unwrap_err();
+            assert!(format!("{:#}", err).contains("name_prefix"), "{:#}", err);
+        }
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            name_prefix = "usér-"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_gecos_separators() {
+        for template in ["Room 1:2", "Room 1,2", "Room\n12"] {
+            assert!(parse_config(&format!(
+                "ranges = [{{ start = 1000, end = 9999 }}]\ngecos_room = {:?}",
+                template
+            ))
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn lookup_outcomes() {
+        let config = Config {
+            exclude_uids: vec![Uid(1005)].into(),
+            ..base_config()
+        };
+        assert!(matches!(
+            config.name_outcome("user-1000", config.passwd_by_name("user-1000")),
+            Outcome::Found(_)
+        ));
+        for name in ["alice", "user-", "user-abc", "svc-1000"] {
+            assert!(
+                matches!(
+                    config.name_outcome(name, config.passwd_by_name(name)),
+                    Outcome::Foreign
+                ),
+                "{}",
+                name
+            );
+        }
+        for name in ["user-99999", "user-01000", "user-1005", "user-999"] {
+            assert!(
+                matches!(
+                    config.name_outcome(name, config.passwd_by_name(name)),
+                    Outcome::Invalid
+                ),
+                "{}",
+                name
+            );
+        }
+
+        assert!(matches!(
+            config.uid_outcome(Uid(1000), config.uid_to_passwd(Uid(1000))),
+            Outcome::Found(_)
+        ));
+        assert!(matches!(
+            config.uid_outcome(Uid(1005), config.uid_to_passwd(Uid(1005))),
+            Outcome::Invalid
+        ));
+        assert!(matches!(
+            config.uid_outcome(Uid(0), config.uid_to_passwd(Uid(0))),
+            Outcome::Foreign
+        ));
+
+        assert!(matches!(
+            config.gid_outcome(Gid(1000), config.gid_to_group(Gid(1000))),
+            Outcome::Found(_)
+        ));
+        assert!(matches!(
+            config.gid_outcome(Gid(1005), config.gid_to_group(Gid(1005))),
+            Outcome::Invalid
+        ));
+        assert!(matches!(
+            config.gid_outcome(Gid(100), config.gid_to_group(Gid(100))),
+            Outcome::Foreign
+        ));
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..config
+        };
+        assert!(matches!(
+            config.gid_outcome(Gid(1000), config.gid_to_group(Gid(1000))),
+            Outcome::Foreign
+        ));
+    }
+
+    #[test]
+    fn lookup_outcome_responses() {
+        assert!(matches!(
+            Outcome::Found(1).into_response(),
+            Response::Success(1)
+        ));
+        assert!(matches!(
+            Outcome::<()>::Foreign.into_response(),
+            Response::NotFound
+        ));
+        assert!(matches!(
+            Outcome::<()>::Invalid.into_response(),
+            Response::NotFound
+        ));
+    }
+
+    #[test]
+    fn failed_config_is_unavail() {
+        assert!(matches!(
+            with_resolver(None, |resolver| option_to_response(
+                resolver.passwd_by_uid(Uid(1000))
+            )),
+            Response::Unavail
+        ));
+        assert!(matches!(
+            with_resolver(None, |resolver| Response::Success(resolver.all_group())),
+            Response::Unavail
+        ));
+
+        let resolver = Resolver::from(base_config());
+        assert!(matches!(
+            with_resolver(Some(&resolver), |resolver| option_to_response(
+                resolver.passwd_by_uid(Uid(1000))
+            )),
+            Response::Success(_)
+        ));
+        assert!(matches!(
+            with_resolver(Some(&resolver), |resolver| option_to_response(
+                resolver.passwd_by_uid(Uid(1))
+            )),
+            Response::NotFound
+        ));
+    }
+
+    #[test]
+    #[cfg(all(feature = "passwd", feature = "group"))]
+    fn hooks_are_counted() {
+        let resolver = Resolver::from(base_config());
+        let before = stats::stats();
+        let _ = FakeDb::passwd_by_uid(Some(&resolver), Uid(1000));
+        let _ = FakeDb::passwd_by_uid(Some(&resolver), Uid(1));
+        let _ = FakeDb::passwd_by_name(Some(&resolver), "nobody-at-all");
+        let _ = FakeDb::group_by_gid(Some(&resolver), Gid(1000));
+        let _ = FakeDb::group_by_name(Some(&resolver), "user-1000");
+        let _ = FakeDb::all_group(Some(&resolver));
+        let _ = gshadow::gshadow_by_name(Some(&resolver), "user-1000");
+        let _ = gshadow::gshadow_by_name(Some(&resolver), "nobody-at-all");
+        let _ = gshadow::all_gshadow(Some(&resolver));
+        let _ = FakeDb::groups_for_user(Some(&resolver), "user-1000");
+        let _ = FakeDb::shadow_by_name(Some(&resolver), "user-1000");
+        let _ = FakeDb::shadow_by_name(Some(&resolver), "nobody-at-all");
+        let _ = FakeDb::all_shadow(Some(&resolver));
+        // every lookup is a miss without a config
+        let _ = FakeDb::passwd_by_uid(None, Uid(1000));
+        let _ = FakeDb::all_group(None);
+        let after = stats::stats();
+
+        let advanced = |lookup: Lookup| {
+            let (before, after) = (before.get(lookup), after.get(lookup));
+            (after.hits - before.hits, after.misses - before.misses)
+        };
+        assert_eq!(advanced(Lookup::PasswdByUid), (1, 2));
+        assert_eq!(advanced(Lookup::PasswdByName), (0, 1));
+        assert_eq!(advanced(Lookup::GroupByGid), (1, 0));
+        assert_eq!(advanced(Lookup::GroupByName), (1, 0));
+        assert_eq!(advanced(Lookup::GroupAll), (1, 1));
+        assert_eq!(advanced(Lookup::GshadowByName), (1, 1));
+        assert_eq!(advanced(Lookup::GshadowAll), (1, 0));
+        assert_eq!(advanced(Lookup::InitgroupsByUser), (1, 0));
+        assert_eq!(advanced(Lookup::ShadowByName), (1, 1));
+        assert_eq!(advanced(Lookup::ShadowAll), (1, 0));
+    }
+
+    #[test]
+    fn record_lookups() {
+        // not looked up by any other test
+        let lookup = stats::Lookup::PasswdAll;
+        let before = stats::stats().get(lookup);
+        stats::record(lookup, true);
+        stats::record(lookup, false);
+        stats::record(lookup, false);
+        let after = stats::stats().get(lookup);
+        assert_eq!(after.hits - before.hits, 1);
+        assert_eq!(after.misses - before.misses, 2);
+    }
+
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// Logger capturing the records of the current thread
+    struct CaptureLogger;
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED.with(|logged| logged.borrow_mut().push(record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Run `f`, returning the messages it logged
+    fn capture_logs(f: impl FnOnce()) -> Vec<String> {
+        static LOGGER: std::sync::Once = std::sync::Once::new();
+        LOGGER.call_once(|| {
+            log::set_logger(&CaptureLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        LOGGED.with(|logged| logged.borrow_mut().clear());
+        f();
+        LOGGED.with(|logged| logged.take())
+    }
+
+    #[test]
+    fn unknown_fields_warned() {
+        let mut config = None;
+        let logs = capture_logs(|| {
+            config = Some(
+                parse_config(
+                    r#"
+                    ranges = [{ start = 1000, end = 9999 }]
+                    shel = "/bin/zsh"
+                    future_option = { enabled = true }
+                    "#,
+                )
+                .unwrap(),
+            );
+        });
+        let config = config.unwrap();
+        assert_eq!(config.unknown_fields, ["future_option", "shel"]);
+        assert_eq!(config.shell, default_shell());
+        assert_eq!(
+            logs,
+            ["ignoring unknown config fields: future_option, shel"]
+        );
+
+        let config = parse_config_as(
+            r#"{ "ranges": [{ "start": 1000, "end": 9999 }], "shel": "/bin/zsh" }"#,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        assert_eq!(config.unknown_fields, ["shel"]);
+        assert!(capture_logs(|| {
+            parse_config("ranges = [{ start = 1000, end = 9999 }]").unwrap();
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn lookups_are_logged() {
+        let resolver = Resolver::from(base_config());
+        let logs = capture_logs(|| {
+            logged(
+                "passwd::get_entry_by_uid",
+                &1000,
+                with_resolver(Some(&resolver), |resolver| {
+                    option_to_response(resolver.passwd_by_uid(Uid(1000)))
+                }),
+            );
+            logged(
+                "passwd::get_entry_by_name",
+                &"root",
+                with_resolver(Some(&resolver), |resolver| {
+                    option_to_response(resolver.passwd_by_name("root"))
+                }),
+            );
+            logged_all(
+                "group::get_all_entries",
+                with_resolver(Some(&resolver), |resolver| {
+                    Response::Success(resolver.all_group())
+                }),
+            );
+            logged(
+                "passwd::get_entry_by_uid",
+                &1000,
+                with_resolver(None, |_| Response::<()>::NotFound),
+            );
+        });
+        assert_eq!(
+            logs,
+            [
+                "passwd::get_entry_by_uid(1000) -> Success",
+                "passwd::get_entry_by_name(root) -> NotFound",
+                "group::get_all_entries() -> Success, 9000 entries",
+                "passwd::get_entry_by_uid(1000) -> Unavail",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_config_invalid_passwd_field() {
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            passwd_field = "x:y"
+            "#,
+        )
+        .is_err());
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            group_passwd_field = "x:y"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_whitespace_around_fields() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            shell = "/bin/sh "
+            shell_rules = [[{ start = 1000, end = 1999 }, " /bin/bash"]]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.shell, "/bin/sh");
+        assert_eq!(config.shell_rules[0].1, "/bin/bash");
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().shell, "/bin/bash");
+
+        let err = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            shell = "/bin/sh "
+            strict_fields = true
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("whitespace"), "{}", err);
+
+        // rejected regardless of strict_fields
+        for field in ["name_prefix = \"us\\u0000er\"", "gecos_other = \"a\\nb\""] {
+            let err = parse_config(&format!(
+                "ranges = [{{ start = 1000, end = 9999 }}]\n{}",
+                field
+            ))
+            .unwrap_err()
+            .to_string();
+            assert!(err.contains("must not contain"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn validate_base_config() {
+        assert!(base_config().validate().is_ok());
+        assert!(multi_range_config().validate().is_ok());
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn validate_reversed_range() {
+        let config = Config {
+            ranges: vec![uids(9999..=1000)],
+            ..base_config()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("9999..=1000"), "{}", err);
+    }
+
+    #[test]
+    fn validate_overlapping_ranges() {
+        let config = Config {
+            ranges: vec![uids(5000..=6000), uids(1000..=5000)],
+            ..base_config()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_empty_shell() {
+        let config = Config {
+            shell: "".into(),
+            ..base_config()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("shell"), "{}", err);
+    }
+
+    #[test]
+    fn validate_user_group_collision() {
+        let config = Config {
+            user_group: Some(Gid(1500)),
+            ..base_config()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("1500"), "{}", err);
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..base_config()
+        };
+        assert!(config.validate().is_ok());
+
+        // with an offset, the per-user GIDs no longer overlap the UIDs
+        let config = Config {
+            user_group: Some(Gid(1500)),
+            gid_offset: 10000,
+            ..base_config()
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn user_group_collision_resolution() {
+        // bypassing validation, the shared group wins over the per-user group
+        let config = Config {
+            user_group: Some(Gid(1500)),
+            ..base_config()
+        };
+        assert_eq!(config.name_for_gid(Gid(1500)).as_deref(), Some("users"));
+        assert_eq!(config.gid_to_group(Gid(1500)).unwrap().name, "users");
+        assert_eq!(config.gid_from_name("users"), Some(Gid(1500)));
+        assert_eq!(config.gid_from_name("user-1500"), None);
+        assert_eq!(config.uid_to_passwd(Uid(1500)).unwrap().gid, 1500);
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().gid, 1500);
+
+        let err = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 1500
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("collides"), "{}", err);
+
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.name_for_gid(Gid(100)).as_deref(), Some("users"));
+        assert_eq!(config.name_for_gid(Gid(1500)), None);
+    }
+
+    #[test]
+    fn parse_config_validates() {
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 9999, end = 1000 }]
+            "#,
+        )
+        .is_err());
+    }
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn env_overrides_unset() {
+        let mut config = multi_range_config();
+        config.apply_env_overrides(env(&[])).unwrap();
+        assert_eq!(config, multi_range_config());
+    }
+
+    #[test]
+    fn env_overrides_all() {
+        let mut config = base_config();
+        config
+            .apply_env_overrides(env(&[
+                ("NSS_LYING_UID_MIN", "2000"),
+                ("NSS_LYING_UID_MAX", "2999"),
+                ("NSS_LYING_SHELL", "/bin/zsh"),
+                ("NSS_LYING_USER_GROUP", "100"),
+            ]))
+            .unwrap();
+        assert_eq!(config.ranges, vec![uids(2000..=2999)]);
+        assert_eq!(config.shell, "/bin/zsh");
+        assert_eq!(config.user_group, Some(Gid(100)));
+
+        config
+            .apply_env_overrides(env(&[("NSS_LYING_USER_GROUP", "")]))
+            .unwrap();
+        assert_eq!(config.user_group, None);
+    }
+
+    #[test]
+    fn env_overrides_one_bound() {
+        let mut config = multi_range_config();
+        config
+            .apply_env_overrides(env(&[("NSS_LYING_UID_MAX", "50000")]))
+            .unwrap();
+        assert_eq!(config.ranges, vec![uids(1000..=50000)]);
+
+        let mut config = multi_range_config();
+        config
+            .apply_env_overrides(env(&[("NSS_LYING_UID_MIN", "1500")]))
+            .unwrap();
+        assert_eq!(config.ranges, vec![uids(1500..=65000)]);
+    }
+
+    #[test]
+    fn env_overrides_unparseable() {
+        for (key, value) in [
+            ("NSS_LYING_UID_MIN", "abc"),
+            ("NSS_LYING_UID_MAX", "-1"),
+            ("NSS_LYING_USER_GROUP", "users"),
+        ] {
+            let mut config = base_config();
+            let err = config
+                .apply_env_overrides(env(&[(key, value)]))
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains(key), "{}", err);
+        }
+    }
+
+    #[test]
+    fn reload_swaps_config() {
+        let resolver = Resolver::from(base_config());
+        let lookup = |resolver: &Resolver, uid| {
+            with_resolver(Some(resolver), |resolver| {
+                resolver
+                    .config()
+                    .uid_outcome(uid, resolver.passwd_by_uid(uid))
+                    .into_response()
+            })
+        };
+        assert!(matches!(lookup(&resolver, Uid(1000)), Response::Success(_)));
+        assert!(matches!(lookup(&resolver, Uid(20000)), Response::NotFound));
+
+        let previous = resolver.reload(Config {
+            ranges: vec![uids(20000..=29999)],
+            ..base_config()
+        });
+        assert_eq!(*previous, base_config());
+        assert!(matches!(lookup(&resolver, Uid(1000)), Response::NotFound));
+        assert!(matches!(
+            lookup(&resolver, Uid(20000)),
+            Response::Success(_)
+        ));
+        assert!(matches!(
+            with_resolver(None, |_| Response::Success(())),
+            Response::Unavail
+        ));
+    }
+
+    #[test]
+    fn reloading_misses_try_again() {
+        let lookup = |resolver: &Resolver, uid| {
+            resolve(resolver, |resolver| {
+                resolver
+                    .config()
+                    .uid_outcome(uid, resolver.passwd_by_uid(uid))
+                    .into_response()
+            })
+        };
+        let resolver = Resolver::from(base_config());
+        assert!(matches!(lookup(&resolver, Uid(1000)), Response::Success(_)));
+        assert!(matches!(lookup(&resolver, Uid(20000)), Response::NotFound));
+
+        let resolver = resolver.with_reloading(true);
+        assert!(matches!(lookup(&resolver, Uid(1000)), Response::Success(_)));
+        assert!(matches!(lookup(&resolver, Uid(20000)), Response::TryAgain));
+        assert_eq!(
+            io::Error::last_os_error().raw_os_error(),
+            Some(libc::EAGAIN)
+        );
+    }
+
+    #[test]
+    fn parse_config_from_str_and_reader() {
+        let contents = "ranges = [{ start = 1000, end = 9999 }]";
+        assert_eq!(contents.parse::<Config>().unwrap(), base_config());
+        assert_eq!(
+            Config::from_reader(contents.as_bytes()).unwrap(),
+            base_config()
+        );
+        assert!(Config::from_reader(&b"ranges = "[..]).is_err());
+    }
+
+    #[test]
+    fn load_config_from_credentials_directory() {
+        let dir =
+            std::env::temp_dir().join(format!("nss_lying-credentials-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let env = |dir: &Path| {
+            let dir = dir.to_str().unwrap().to_owned();
+            move |key: &str| (key == "CREDENTIALS_DIRECTORY").then(|| dir.clone())
+        };
+
+        // missing credentials fall back to the config file
+        assert_eq!(load_config_from_credentials(|_| None).unwrap(), None);
+        assert_eq!(load_config_from_credentials(env(&dir)).unwrap(), None);
+
+        let path = dir.join(CREDENTIAL_NAME);
+        std::fs::write(&path, "ranges = [{ start = 1000, end = 9999 }]").unwrap();
+        assert_eq!(
+            load_config_from_credentials(env(&dir)).unwrap(),
+            Some(base_config())
+        );
+
+        std::fs::write(&path, "ranges = [{ start = 9999, end = 1000 }]").unwrap();
+        let err = load_config_from_credentials(env(&dir)).unwrap_err();
+        assert!(format!("{:#}", err).contains(CREDENTIAL_NAME), "{:#}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn env_ignored_in_secure_mode() {
+        assert!(!secure_execution());
+        let (key, value) = std::env::vars().next().expect("environment is empty");
+        assert_eq!(env_lookup(false)(&key), Some(value));
+        assert_eq!(env_lookup(true)(&key), None);
+
+        // the fd is only read through the lookup, and a closed one fails
+        let err = load_config_with(|key| (key == "NSS_LYING_CONFIG_FD").then(|| "-1".into()))
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("fd -1"), "{:#}", err);
+    }
+
+    #[test]
+    fn load_config_from_fd_in_both_formats() {
+        let path = std::env::temp_dir().join(format!("nss_lying-config-fd-{}", std::process::id()));
+        let mut file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let fd = file.as_raw_fd();
+
+        io::Write::write_all(&mut file, b"ranges = [{ start = 1000, end = 9999 }]").unwrap();
+        assert_eq!(
+            load_config_from_fd(fd, ConfigFormat::Toml).unwrap(),
+            base_config()
+        );
+        // read again from the start, as on a reload
+        assert_eq!(
+            load_config_from_fd(fd, ConfigFormat::Toml).unwrap(),
+            base_config()
+        );
+
+        file.set_len(0).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        assert!(load_config_from_fd(fd, ConfigFormat::Toml).is_err());
+        io::Write::write_all(
+            &mut file,
+            br#"{ "ranges": [{ "start": 1000, "end": 9999 }] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            load_config_from_fd(fd, ConfigFormat::Json).unwrap(),
+            base_config()
+        );
+        assert!(load_config_from_fd(fd, ConfigFormat::Toml).is_err());
+
+        assert!(load_config_from_fd(-1, ConfigFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn parse_json_config_matches_toml() {
+        let toml = r#"
+            ranges = [{ start = 2000, end = 2999 }]
+            exclude_uids = [2500]
+            user_group = 100
+            shell_rules = [[{ start = 2900, end = 2999 }, "/usr/sbin/nologin"]]
+            home_template = "/home/{name}"
+            [nobody]
+            name = "nobody"
+            [overrides.2005]
+            shell = "/bin/zsh"
+        "#;
+        let json = r#"{
+            "ranges": [{ "start": 2000, "end": 2999 }],
+            "exclude_uids": [2500],
+            "user_group": 100,
+            "shell_rules": [[{ "start": 2900, "end": 2999 }, "/usr/sbin/nologin"]],
+            "home_template": "/home/{name}",
+            "nobody": { "name": "nobody" },
+            "overrides": { "2005": { "shell": "/bin/zsh" } }
+        }"#;
+        let config = parse_config_as(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(parse_config_as(json, ConfigFormat::Json).unwrap(), config);
+        assert_eq!(
+            Config::from_reader_as(json.as_bytes(), ConfigFormat::Json).unwrap(),
+            config
+        );
+        assert_eq!(
+            parse_config_fragments([("nss_lying.json", json)]).unwrap(),
+            config
+        );
+
+        // JSON goes through the same validation
+        assert!(parse_config_as(
+            r#"{ "ranges": [{ "start": 2000, "end": 1000 }] }"#,
+            ConfigFormat::Json
+        )
+        .is_err());
+        assert!(parse_config_as(toml, ConfigFormat::Json).is_err());
+    }
+
+    #[test]
+    fn config_format_selection() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/etc/nss_lying.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/etc/nss_lying.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/etc/nss_lying")),
+            ConfigFormat::Toml
+        );
+        assert_eq!("json".parse::<ConfigFormat>().unwrap(), ConfigFormat::Json);
+        assert_eq!("toml".parse::<ConfigFormat>().unwrap(), ConfigFormat::Toml);
+        assert!("yaml".parse::<ConfigFormat>().is_err());
+    }
+
+    #[test]
+    fn load_missing_config() {
+        let config = load_config_from(Path::new("/nonexistent/nss_lying.toml")).unwrap();
+        assert_eq!(config, base_config());
+    }
+
+    #[test]
+    fn custom_name_prefix() {
+        let config = Config {
+            name_prefix: "svc-".into(),
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(1000)).as_deref(), Some("svc-1000"));
+        assert_eq!(config.uid_from_name("svc-1000"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-1000"), None);
+    }
+
+    #[test]
+    fn empty_name_prefix() {
+        let config = Config {
+            name_prefix: "".into(),
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(1000)).as_deref(), Some("1000"));
+        assert_eq!(config.uid_from_name("1000"), Some(Uid(1000)));
+        assert_eq!(config.uid_from_name("user-1000"), None);
+    }
+
+    fn multi_range_config() -> Config {
+        Config {
+            ranges: vec![uids(1000..=1999), uids(60000..=65000)],
+            ..base_config()
+        }
+    }
+
+    #[test]
+    fn uid_in_second_range() {
+        let config = multi_range_config();
+        assert_eq!(
+            config.name_for_uid(Uid(60000)).as_deref(),
+            Some("user-60000")
+        );
+        assert_eq!(config.uid_from_name("user-65000"), Some(Uid(65000)));
+        assert_eq!(config.gid_for_uid(Uid(60001)), Some(Gid(60001)));
+    }
+
+    #[test]
+    fn uid_in_range_gap() {
+        let config = multi_range_config();
+        assert_eq!(config.name_for_uid(Uid(2000)), None);
+        assert_eq!(config.uid_from_name("user-59999"), None);
+        assert_eq!(config.gid_for_uid(Uid(30000)), None);
+    }
+
+    #[test]
+    fn claim_prefix_names_in_gaps() {
+        let config = Config {
+            claim_prefix_names_in_gaps: true,
+            exclude_uids: vec![Uid(65000)].into(),
+            ..multi_range_config()
+        };
+        assert_eq!(config.uid_from_name("user-2000"), Some(Uid(1999)));
+        assert_eq!(config.uid_from_name("user-59999"), Some(Uid(60000)));
+        // halfway between the ranges goes to the lower one
+        assert_eq!(config.uid_from_name("user-30999"), Some(Uid(1999)));
+        assert_eq!(config.uid_from_name("user-31000"), Some(Uid(60000)));
+        assert_eq!(config.uid_from_name("user-0"), Some(Uid(1000)));
+        // clamped onto an excluded UID, or excluded within a range
+        assert_eq!(config.uid_from_name("user-70000"), None);
+        assert_eq!(config.uid_from_name("user-65000"), None);
+        assert_eq!(
+            config.passwd_by_name("user-2000").unwrap().name,
+            "user-1999"
+        );
+        // lookups by UID are unaffected
+        assert_eq!(config.name_for_uid(Uid(2000)), None);
+        assert!(config.uid_to_passwd(Uid(2000)).is_none());
+
+        let config = Config {
+            claim_prefix_names_in_gaps: false,
+            ..config
+        };
+        assert_eq!(config.uid_from_name("user-2000"), None);
+        assert_eq!(config.uid_from_name("user-59999"), None);
+        assert_eq!(config.uid_from_name("user-60000"), Some(Uid(60000)));
+    }
+
+    #[test]
+    fn users_match_passwd_entries() {
+        let config = Config {
+            exclude_uids: vec![Uid(1001), Uid(60000)].into(),
+            nobody: Some(NobodyConfig {
+                uid: Uid(65534),
+                gid: Gid(65534),
+                name: "nobody".into(),
+                dir: "/nonexistent".into(),
+                shell: "/usr/sbin/nologin".into(),
+            }),
+            ..multi_range_config()
+        };
+        let users: Vec<_> = config.users().map(|p| (p.uid, p.name)).collect();
+        let entries: Vec<_> = config
+            .passwd_entries()
+            .into_iter()
+            .map(|p| (p.uid, p.name))
+            .collect();
+        assert_eq!(users, entries);
+        assert!(users.iter().all(|(uid, _)| *uid != 1001 && *uid != 60000));
+        assert_eq!(users.last(), Some(&(65534, "nobody".to_string())));
+
+        let group_keys = |groups: Vec<Group>| -> Vec<_> {
+            groups
+                .into_iter()
+                .map(|g| (g.gid, g.name, g.members))
+                .collect()
+        };
+        assert_eq!(
+            group_keys(config.groups().collect()),
+            group_keys(config.group_entries())
+        );
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            populate_members: true,
+            ..config
+        };
+        assert_eq!(
+            group_keys(config.groups().collect()),
+            group_keys(config.group_entries())
+        );
+        let config = Config {
+            ranges: Vec::new(),
+            ..config
+        };
+        assert_eq!(config.groups().count(), 0);
+    }
+
+    #[test]
+    fn users_lazy_over_huge_range() {
+        // bypasses validation, which would reject the range
+        let config = Config {
+            ranges: vec![uids(1000..=4_000_000_000)],
+            exclude_uids: vec![Uid(1002)].into(),
+            ..base_config()
+        };
+        let uids: Vec<_> = config.users().take(3).map(|p| p.uid).collect();
+        assert_eq!(uids, vec![1000, 1001, 1003]);
+        let gids: Vec<_> = config.groups().take(3).map(|g| g.gid).collect();
+        assert_eq!(gids, vec![1000, 1001, 1003]);
+    }
+
+    #[test]
+    fn range_boundaries() {
+        let config = multi_range_config();
+        for uid in [1000, 1999, 60000, 65000] {
+            assert!(config.contains_uid(Uid(uid)), "{}", uid);
+            let name = format!("user-{}", uid);
+            assert_eq!(config.name_for_uid(Uid(uid)), Some(name.clone()));
+            assert_eq!(config.uid_from_name(&name), Some(Uid(uid)));
+            assert_eq!(config.gid_for_uid(Uid(uid)), Some(Gid(uid)));
+            assert_eq!(config.name_for_gid(Gid(uid)), Some(name));
+        }
+        for uid in [999, 2000, 59999, 65001] {
+            assert!(!config.contains_uid(Uid(uid)), "{}", uid);
+            assert_eq!(config.name_for_uid(Uid(uid)), None);
+            assert_eq!(config.uid_from_name(&format!("user-{}", uid)), None);
+            assert_eq!(config.gid_for_uid(Uid(uid)), None);
+            assert_eq!(config.name_for_gid(Gid(uid)), None);
+        }
+    }
+
+    #[test]
+    fn uid_range_chains_ranges() {
+        let config = multi_range_config();
+        let uids: Vec<_> = config.uid_range().collect();
+        assert_eq!(uids.len(), 1000 + 5001);
+        assert_eq!(uids.first(), Some(&Uid(1000)));
+        assert_eq!(uids[999], Uid(1999));
+        assert_eq!(uids[1000], Uid(60000));
+        assert_eq!(uids.last(), Some(&Uid(65000)));
+    }
+
+    #[test]
+    fn uid_count() {
+        assert_eq!(base_config().uid_count(), 9000);
+        assert_eq!(multi_range_config().uid_count(), 1000 + 5001);
+
+        let config = Config {
+            ranges: vec![uids(1000..=1999), uids(60000..=65000)],
+            exclude_uids: vec![Uid(1500), Uid(1500), Uid(1955), Uid(60000), Uid(5)].into(),
+            exclude_ranges: vec![uids(1900..=2100), uids(1950..=1960), uids(64000..=70000)],
+            ..base_config()
+        };
+        // sorted and deduplicated when built
+        assert_eq!(*config.exclude_uids, [5, 1500, 1955, 60000].map(Uid));
+        let brute_force = |config: &Config| {
+            Uid::iter(&uids(0..=70000))
+                .filter(|&uid| config.contains_uid(uid))
+                .count() as u64
+        };
+        assert_eq!(config.uid_count(), 1000 - 1 - 100 + 5001 - 1 - 1001);
+        assert_eq!(config.uid_count(), brute_force(&config));
+
+        let config = Config {
+            ranges: vec![uids(0..=1999)],
+            ..base_config()
+        };
+        assert_eq!(config.uid_count(), 1000);
+        let config = Config {
+            forbid_reserved: false,
+            ..config
+        };
+        assert_eq!(config.uid_count(), 2000);
+
+        let config = Config {
+            ranges: vec![uids(0..=libc::uid_t::MAX)],
+            forbid_reserved: false,
+            ..base_config()
+        };
+        assert_eq!(config.uid_count(), 1 << 32);
+
+        let config = Config {
+            ranges: vec![uids(1000..=1999), uids(5000..=5999)],
+            allow_uids: Some(
+                vec![
+                    Uid(5003),
+                    Uid(1010),
+                    Uid(1500),
+                    Uid(1010),
+                    Uid(1200),
+                    Uid(7000),
+                ]
+                .into(),
+            ),
+            exclude_uids: vec![Uid(1500)].into(),
+            ..base_config()
+        };
+        assert_eq!(config.uid_count(), 3);
+        assert_eq!(config.uid_count(), brute_force(&config));
+    }
+
+    #[test]
+    fn gid_count() {
+        let count_entries = |config: &Config| config.group_entries().len() as u64;
+        let config = Config {
+            exclude_uids: vec![Uid(1005)].into(),
+            ..base_config()
+        };
+        assert_eq!(config.gid_count(), 8999);
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..config
+        };
+        assert_eq!(config.gid_count(), 1);
+        let config = Config {
+            enumerate_per_user_groups: true,
+            ..config
+        };
+        assert_eq!(config.gid_count(), 9000);
+        assert_eq!(config.gid_count(), count_entries(&config));
+
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            gid_offset: -1500,
+            ..base_config()
+        };
+        assert_eq!(config.gid_count(), 500);
+        assert_eq!(config.gid_count(), count_entries(&config));
+        let config = Config {
+            gid_offset: i64::from(libc::gid_t::MAX) - 1500,
+            ..config
+        };
+        assert_eq!(config.gid_count(), 501);
+        assert_eq!(config.gid_count(), count_entries(&config));
+
+        let config = Config {
+            primary_gid_strategy: Some(PrimaryGidStrategy::RoundRobin(vec![Gid(100), Gid(101)])),
+            ..base_config()
+        };
+        assert_eq!(config.gid_count(), 0);
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            allow_uids: Some(AllowedUids::default()),
+            ..base_config()
+        };
+        assert_eq!(config.gid_count(), 0);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn uid_range_len_matches_iterator() {
+        let config = multi_range_config();
+        assert_eq!(config.uid_range_len(), config.uid_range().count());
+
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 1..=libc::uid_t::MAX), uids(10..=9)],
+            ..base_config()
+        };
+        assert_eq!(config.uid_range_len(), 2);
+    }
+
+    #[test]
+    fn passwd_entries_presized() {
+        let config = multi_range_config();
+        let entries = config.passwd_entries();
+        assert_eq!(entries.len(), config.uid_range_len());
+        assert_eq!(entries.capacity(), entries.len());
+        assert_eq!(entries.first().map(|p| p.uid), Some(1000));
+        assert_eq!(entries.last().map(|p| p.uid), Some(65000));
+    }
+
+    #[test]
+    fn collect_for_uids_skips_none() {
+        let config = Config {
+            ranges: vec![uids(1000..=1009)],
+            ..base_config()
+        };
+        let entries = config.collect_for_uids(|uid| (uid.0 % 2 == 0).then_some(uid.0));
+        assert_eq!(entries, vec![1000, 1002, 1004, 1006, 1008]);
+        assert!(config.collect_for_uids(|_| None::<Passwd>).is_empty());
+    }
+
+    #[test]
+    fn collect_for_uids_capped() {
+        // bypasses validation, which would reject the range
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            max_range_size: 10,
+            ..base_config()
+        };
+        let entries = config.passwd_entries();
+        assert_eq!(entries.len(), 10);
+        assert_eq!(entries.last().map(|p| p.uid), Some(1009));
+        assert!(entries.capacity() <= 10);
+
+        let config = Config {
+            max_range_size: 0,
+            ..config
+        };
+        assert_eq!(config.passwd_entries().len(), 1000);
+    }
+
+    #[test]
+    fn chunked_enumeration() {
+        let configs = [
+            base_config(),
+            Config {
+                exclude_uids: vec![Uid(1001), Uid(60000)].into(),
+                nobody: Some(NobodyConfig {
+                    uid: Uid(65534),
+                    gid: Gid(65534),
+                    name: "nobody".into(),
+                    dir: "/nonexistent".into(),
+                    shell: "/usr/sbin/nologin".into(),
+                }),
+                ..multi_range_config()
+            },
+            Config {
+                user_group: Some(Gid(2500)),
+                enumerate_per_user_groups: true,
+                ranges: vec![uids(1000..=1999), uids(3000..=3999)],
+                ..base_config()
+            },
+            // truncated by the limit, which validation would reject
+            Config {
+                max_range_size: 10,
+                ..base_config()
+            },
+        ];
+        let configs = configs.into_iter().flat_map(|config| {
+            [Order::Ascending, Order::Descending].map(|order| Config {
+                enumeration_order: order,
+                ..config.clone()
+            })
+        });
+        for config in configs {
+            let passwd = |entries: Vec<Passwd>| -> Vec<_> {
+                entries.iter().map(PasswdSnapshot::from).collect()
+            };
+            let group = |entries: Vec<Group>| -> Vec<_> {
+                entries.iter().map(GroupSnapshot::from).collect()
+            };
+            let expected_passwd = passwd(config.passwd_entries());
+            let expected_group = group(config.group_entries());
+            for size in [None, Some(1), Some(7), Some(1000), Some(usize::MAX)] {
+                let config = Config {
+                    enumeration_chunk_size: size,
+                    ..config.clone()
+                };
+                let chunks: Vec<_> = config.passwd_entry_chunks().collect();
+                if let Some(size) = size {
+                    assert!(chunks.iter().all(|chunk| chunk.len() <= size));
+                }
+                assert_eq!(passwd(chunks.concat()), expected_passwd, "{:?}", size);
+                let chunks: Vec<_> = config.group_entry_chunks().collect();
+                assert_eq!(group(chunks.concat()), expected_group, "{:?}", size);
+            }
+        }
+        assert!(Config {
+            enumeration_chunk_size: Some(0),
+            ..base_config()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_range_too_large() {
+        assert!(parse_config("ranges = [{ start = 1000, end = 4000000000 }]").is_err());
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 500999 }, { start = 600000, end = 1100000 }]
+            "#,
+        )
+        .is_err());
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 1999 }]
+            max_range_size = 999
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_config_range_at_limit() {
+        let config = parse_config("ranges = [{ start = 1000, end = 1000999 }]").unwrap();
+        assert_eq!(config.uid_range_len(), 1_000_000);
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 1999 }]
+            max_range_size = 1000
+            "#,
+        )
+        .is_ok());
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 4000000000 }]
+            max_range_size = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.max_range_size, 0);
+    }
+
+    #[test]
+    fn group_entries() {
+        let config = Config {
+            ranges: vec![uids(1000..=1009)],
+            ..base_config()
+        };
+        let groups = config.group_entries();
+        assert_eq!(groups.len(), 10);
+        assert_eq!(groups[0].name, "user-1000");
+        assert_eq!(groups[9].gid, 1009);
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..config
+        };
+        let groups = config.group_entries();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "users");
+        assert_eq!(groups[0].gid, 100);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn empty_range_entries() {
+        for ranges in [vec![], vec![uids(10..=9)]] {
+            for user_group in [None, Some(Gid(100))] {
+                let config = Config {
+                    ranges: ranges.clone(),
+                    user_group,
+                    populate_members: true,
+                    ..base_config()
+                };
+                let resolver = Resolver::from(config.clone());
+                assert!(matches!(
+                    with_resolver(Some(&resolver), |resolver| Response::Success(
+                        resolver.all_passwd()
+                    )),
+                    Response::Success(entries) if entries.is_empty()
+                ));
+                assert!(matches!(
+                    with_resolver(Some(&resolver), |resolver| Response::Success(
+                        resolver.all_group()
+                    )),
+                    Response::Success(entries) if entries.is_empty()
+                ));
+                assert!(config.shadow_entries().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn override_shell() {
+        let config = Config {
+            overrides: HashMap::from([(
+                Uid(1005),
+                PasswdOverride {
+                    shell: Some("/bin/zsh".into()),
+                    dir: Some("/home/alice".into()),
+                    ..Default::default()
+                },
+            )])
+            .into(),
+            ..base_config()
+        };
+        let passwd = config.uid_to_passwd(Uid(1005)).unwrap();
+        assert_eq!(passwd.name, "user-1005");
+        assert_eq!(passwd.shell, "/bin/zsh");
+        assert_eq!(passwd.dir, "/home/alice");
+        assert_eq!(passwd.gid, 1005);
+        assert_eq!(passwd.gecos, "");
+
+        // other UIDs are still generated
+        assert_eq!(config.uid_to_passwd(Uid(1006)).unwrap().shell, "/bin/bash");
+    }
+
+    #[test]
+    fn override_name() {
+        let config = Config {
+            overrides: HashMap::from([(
+                Uid(1005),
+                PasswdOverride {
+                    name: Some("alice".into()),
+                    gid: Some(Gid(100)),
+                    ..Default::default()
+                },
+            )])
+            .into(),
+            ..base_config()
+        };
+        let passwd = config.uid_to_passwd(Uid(1005)).unwrap();
+        assert_eq!(passwd.name, "alice");
+        assert_eq!(passwd.gid, 100);
+        assert_eq!(config.uid_from_name("alice"), Some(Uid(1005)));
+        assert_eq!(config.passwd_by_name("alice").unwrap().uid, 1005);
+        assert!(matches!(
+            option_to_response(config.passwd_by_name("alice")),
+            Response::Success(_)
+        ));
+
+        // the generated name no longer resolves
+        assert_eq!(config.uid_from_name("user-1005"), None);
+        assert!(config.passwd_by_name("user-1005").is_none());
+
+        // the per-user group follows the name
+        assert_eq!(config.name_for_gid(Gid(1005)).as_deref(), Some("alice"));
+        assert_eq!(config.gid_from_name("alice"), Some(Gid(1005)));
+    }
+
+    #[test]
+    fn parse_overrides() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            [overrides.1005]
+            name = "alice"
+            shell = "/bin/zsh"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.overrides[&Uid(1005)],
+            PasswdOverride {
+                name: Some("alice".into()),
+                shell: Some("/bin/zsh".into()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn validate_overrides() {
+        let with_overrides = |overrides: &[(libc::uid_t, &str)]| Config {
+            overrides: overrides
+                .iter()
+                .map(|&(uid, name)| {
+                    (
+                        Uid(uid),
+                        PasswdOverride {
+                            name: Some(name.into()),
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect(),
+            ..base_config()
+        };
+        assert!(with_overrides(&[(1005, "alice"), (1006, "bob")])
+            .validate()
+            .is_ok());
+        assert!(with_overrides(&[(1005, "user-1005")]).validate().is_ok());
+        assert!(with_overrides(&[(10, "alice")]).validate().is_err());
+        assert!(with_overrides(&[(1005, "user-1006")]).validate().is_err());
+        assert!(with_overrides(&[(1005, "alice"), (1006, "alice")])
+            .validate()
+            .is_err());
+        assert!(with_overrides(&[(1005, "ali:ce")]).validate().is_err());
+    }
+
+    #[test]
+    fn aliases() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            [aliases]
+            deploy = 1005
+            backup = 1005
+            "#,
+        )
+        .unwrap();
+        for name in ["deploy", "backup", "user-1005"] {
+            let passwd = config.passwd_by_name(name).unwrap();
+            assert_eq!((passwd.uid, passwd.name.as_str()), (1005, "user-1005"));
+        }
+        assert_eq!(config.shadow_by_name("deploy").unwrap().name, "user-1005");
+        assert_eq!(config.name_for_uid(Uid(1005)).as_deref(), Some("user-1005"));
+        assert!(config.passwd_by_name("Deploy").is_none());
+        assert!(config
+            .passwd_entries()
+            .iter()
+            .all(|passwd| passwd.name.starts_with("user-")));
+
+        let with_aliases = |aliases: &[(&str, libc::uid_t)]| Config {
+            aliases: aliases
+                .iter()
+                .map(|&(name, uid)| (name.to_owned(), Uid(uid)))
+                .collect(),
+            exclude_uids: vec![Uid(1500)].into(),
+            ..base_config()
+        };
+        assert!(with_aliases(&[("deploy", 10)]).validate().is_err());
+        assert!(with_aliases(&[("deploy", 1500)]).validate().is_err());
+        assert!(with_aliases(&[("user-1006", 1005)]).validate().is_err());
+        assert!(with_aliases(&[("dep:loy", 1005)]).validate().is_err());
+        let config = Config {
+            case_insensitive_names: true,
+            ..with_aliases(&[("deploy", 1005), ("Deploy", 1006)])
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn duplicate_alias() {
+        let err = parse_config_as(
+            r#"{
+                "ranges": [{ "start": 1000, "end": 9999 }],
+                "aliases": { "deploy": 1005, "deploy": 1006 }
+            }"#,
+            ConfigFormat::Json,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("duplicate alias"),
+            "{:#}",
+            err
+        );
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            [aliases]
+            deploy = 1005
+            deploy = 1006
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn override_name_index() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            case_insensitive_names = true
+            [overrides.5678]
+            name = "Alice"
+            [overrides.5679]
+            shell = "/bin/zsh"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.uid_from_name("Alice"), Some(Uid(5678)));
+        assert_eq!(config.uid_from_name("alice"), Some(Uid(5678)));
+        assert_eq!(config.passwd_by_name("ALICE").unwrap().uid, 5678);
+        assert_eq!(config.uid_from_name("user-5678"), None);
+        assert_eq!(config.uid_from_name("user-5679"), Some(Uid(5679)));
+
+        let err = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            [overrides.5678]
+            name = "user-1234"
+            "#,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("collides with a synthesized username"));
+    }
+
+    #[test]
+    fn describe_config() {
+        assert_eq!(
+            base_config().describe(),
+            "9000 UIDs in 1000..=9999, named user-1000 to user-9999, each with their own primary group"
+        );
+
+        let config = Config {
+            user_group: Some(Gid(100)),
+            shared_group_name: Some("staff".into()),
+            exclude_uids: vec![Uid(1000), Uid(65000)].into(),
+            ..multi_range_config()
+        };
+        let description = config.describe();
+        assert!(description.starts_with("5999 UIDs in 1000..=1999 and 60000..=65000,"));
+        assert!(
+            description.contains("user-1001 to user-64999"),
+            "{}",
+            description
+        );
+        assert!(
+            description.contains("primary group staff (100)"),
+            "{}",
+            description
+        );
+
+        let config = Config {
+            gid_offset: -500,
+            ..base_config()
+        };
+        assert!(config.describe().ends_with("(GID = UID - 500)"));
+
+        let config = Config {
+            ranges: vec![],
+            ..base_config()
+        };
+        assert_eq!(config.describe(), "no UIDs in no ranges");
+    }
+
+    #[test]
+    fn resolver_lookups() {
+        let resolver = Resolver::from(Config {
+            ranges: vec![uids(2000..=2009)],
+            user_group: Some(Gid(100)),
+            supplementary_gids: vec![Gid(27)],
+            ..base_config()
+        });
+        assert_eq!(resolver.config().ranges, vec![uids(2000..=2009)]);
+
+        let passwd = resolver.passwd_by_uid(Uid(2003)).unwrap();
+        assert_eq!(passwd.name, "user-2003");
+        assert_eq!(passwd.gid, 100);
+        assert!(resolver.passwd_by_uid(Uid(1000)).is_none());
+        assert_eq!(resolver.passwd_by_name("user-2009").unwrap().uid, 2009);
+        assert!(resolver.passwd_by_name("user-2010").is_none());
+        assert_eq!(resolver.all_passwd().len(), 10);
+
+        assert_eq!(resolver.group_by_gid(Gid(100)).unwrap().name, "users");
+        assert!(resolver.group_by_gid(Gid(2003)).is_none());
+        assert_eq!(resolver.group_by_name("users").unwrap().gid, 100);
+        assert!(resolver.group_by_name("user-2003").is_none());
+        assert_eq!(resolver.all_group().len(), 1);
+
+        let groups = resolver.groups_for_user("user-2003");
+        assert_eq!(groups.iter().map(|g| g.gid).collect::<Vec<_>>(), vec![27]);
+        assert!(resolver.groups_for_user("root").is_empty());
+
+        assert_eq!(resolver.shadow_by_name("user-2003").unwrap().passwd, "*");
+        assert_eq!(resolver.all_shadow().len(), 10);
+    }
+
+    #[test]
+    fn resolver_per_user_groups() {
+        let resolver = Resolver::new(Arc::new(base_config()));
+        assert_eq!(resolver.group_by_gid(Gid(1000)).unwrap().name, "user-1000");
+        assert_eq!(resolver.group_by_name("user-9999").unwrap().gid, 9999);
+        assert_eq!(resolver.all_group().len(), 9000);
+    }
+
+    #[test]
+    fn resolver_not_enumerable() {
+        let resolver = Resolver::from(
+            parse_config(
+                r#"
+                ranges = [{ start = 1000, end = 9999 }]
+                enumerable = false
+                "#,
+            )
+            .unwrap(),
+        );
+        assert!(resolver.all_passwd().is_empty());
+        assert!(resolver.all_group().is_empty());
+        assert!(resolver.all_shadow().is_empty());
+        assert!(resolver.all_gshadow().is_empty());
+
+        assert_eq!(resolver.passwd_by_uid(Uid(1234)).unwrap().name, "user-1234");
+        assert_eq!(resolver.passwd_by_name("user-1234").unwrap().uid, 1234);
+        assert_eq!(resolver.group_by_gid(Gid(1234)).unwrap().name, "user-1234");
+        assert_eq!(resolver.group_by_name("user-1234").unwrap().gid, 1234);
+        assert!(resolver.shadow_by_name("user-1234").is_some());
+        assert!(resolver.gshadow_by_name("user-1234").is_some());
+    }
+
+    #[test]
+    fn resolver_enumeration_order() {
+        let config = Config {
+            ranges: vec![uids(1000..=1004)],
+            ..base_config()
+        };
+        for (order, first, last) in [
+            (Order::Ascending, 1000, 1004),
+            (Order::Descending, 1004, 1000),
+        ] {
+            let resolver = Resolver::from(Config {
+                enumeration_order: order,
+                ..config.clone()
+            });
+            let uids: Vec<_> = resolver.all_passwd().iter().map(|p| p.uid).collect();
+            assert_eq!((uids[0], uids[4]), (first, last), "{:?}", order);
+            let gids: Vec<_> = resolver.all_group().iter().map(|g| g.gid).collect();
+            assert_eq!((gids[0], gids[4]), (first, last), "{:?}", order);
+            let names: Vec<_> = resolver.all_shadow().into_iter().map(|s| s.name).collect();
+            assert_eq!(names[0], format!("user-{}", first));
+        }
+
+        // nobody counts as the last UID and the shared group keeps its place
+        let resolver = Resolver::from(Config {
+            enumeration_order: Order::Descending,
+            user_group: Some(Gid(1002)),
+            enumerate_per_user_groups: true,
+            ranges: vec![uids(1000..=1001), uids(1003..=1004)],
+            ..nobody_config()
+        });
+        let uids: Vec<_> = resolver.all_passwd().iter().map(|p| p.uid).collect();
+        assert_eq!(uids, [65534, 1004, 1003, 1001, 1000]);
+        let gids: Vec<_> = resolver.all_group().iter().map(|g| g.gid).collect();
+        assert_eq!(gids, [1004, 1003, 1002, 1001, 1000]);
+
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            enumeration_order = "descending"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.enumeration_order, Order::Descending);
+    }
+
+    /// A passwd database with a fixed set of users, failing for UID 0
+    struct FakePasswdDb(Vec<(libc::uid_t, &'static str)>);
+
+    impl PasswdDb for FakePasswdDb {
+        fn name_for_uid(&self, uid: Uid) -> io::Result<Option<String>> {
+            if uid == Uid(0) {
+                return Err(io::Error::from_raw_os_error(libc::EIO));
+            }
+            Ok(self
+                .0
+                .iter()
+                .find(|&&(u, _)| u == uid.0)
+                .map(|&(_, n)| n.into()))
+        }
+
+        fn uid_for_name(&self, name: &str) -> io::Result<Option<Uid>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|&&(_, n)| n == name)
+                .map(|&(u, _)| Uid(u)))
+        }
+    }
+
+    #[test]
+    fn resolver_defer_to_real() {
+        let config = Config {
+            ranges: vec![uids(0..=1999)],
+            forbid_reserved: false,
+            supplementary_gids: vec![Gid(27)],
+            defer_to_real: true,
+            ..base_config()
+        };
+        let real = Arc::new(FakePasswdDb(vec![(1005, "alice"), (1500, "user-1006")]));
+        let resolver = Resolver::from(config.clone()).with_real_db(real.clone());
+
+        // the UID exists
+        assert!(resolver.passwd_by_uid(Uid(1005)).is_none());
+        assert!(resolver.passwd_by_name("user-1005").is_none());
+        assert!(resolver.shadow_by_name("user-1005").is_none());
+        assert!(resolver.groups_for_user("user-1005").is_empty());
+        // the name exists, with another UID
+        assert!(resolver.passwd_by_uid(Uid(1006)).is_none());
+        assert!(resolver.passwd_by_name("user-1006").is_none());
+        // the real database failed to answer
+        assert!(resolver.passwd_by_uid(Uid(0)).is_none());
+
+        // free UIDs are synthesized
+        assert_eq!(resolver.passwd_by_uid(Uid(1007)).unwrap().name, "user-1007");
+        assert_eq!(resolver.passwd_by_name("user-1007").unwrap().uid, 1007);
+        assert!(resolver.shadow_by_name("user-1007").is_some());
+        assert_eq!(resolver.groups_for_user("user-1007").len(), 1);
+
+        // nothing is deferred unless configured
+        let resolver = Resolver::from(Config {
+            defer_to_real: false,
+            ..config
+        })
+        .with_real_db(real);
+        assert_eq!(resolver.passwd_by_uid(Uid(1005)).unwrap().name, "user-1005");
+        assert!(resolver.passwd_by_uid(Uid(0)).is_some());
+    }
+
+    #[test]
+    fn resolver_cached_lookups() {
+        let uncached = Resolver::new(Arc::new(base_config()));
+        let cache = Arc::new(cache::LookupCache::new(4));
+        let cached = uncached.clone().with_cache(cache.clone());
+        let passwd_fields = |p: Option<Passwd>| p.map(|p| (p.name, p.uid, p.gid, p.dir));
+        let group_fields = |g: Option<Group>| g.map(|g| (g.name, g.gid, g.members));
+
+        // the second round is answered from the cache
+        for _ in 0..2 {
+            for uid in [1000, 1234, 999, 10000] {
+                assert_eq!(
+                    passwd_fields(cached.passwd_by_uid(Uid(uid))),
+                    passwd_fields(uncached.passwd_by_uid(Uid(uid)))
+                );
+                assert_eq!(
+                    group_fields(cached.group_by_gid(Gid(uid))),
+                    group_fields(uncached.group_by_gid(Gid(uid)))
+                );
+            }
+            for name in ["user-1000", "user-01000", "root"] {
+                assert_eq!(
+                    passwd_fields(cached.passwd_by_name(name)),
+                    passwd_fields(uncached.passwd_by_name(name))
+                );
+                assert_eq!(
+                    group_fields(cached.group_by_name(name)),
+                    group_fields(uncached.group_by_name(name))
+                );
+            }
+        }
+        // at most 4 passwd and 4 group lookups are kept
+        assert_eq!(cache.len(), 8);
+    }
+
+    #[test]
+    fn resolver_reload_concurrently() {
+        let config = |name: &str, ranges| Config {
+            ranges: vec![ranges],
+            shell: format!("/bin/{}", name),
+            home_template: format!("/home/{}/{{name}}", name),
+            ..base_config()
+        };
+        let (a, b) = (
+            config("a", uids(1000..=1999)),
+            config("b", uids(2000..=2999)),
+        );
+        let resolver = Resolver::from(a.clone()).with_cache(Arc::new(cache::LookupCache::new(16)));
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let resolver = resolver.clone();
+                let done = &done;
+                scope.spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        let current = resolver.current();
+                        let name = current.shell.trim_start_matches("/bin/");
+                        assert!(current
+                            .home_template
+                            .starts_with(&format!("/home/{}/", name)));
+                        for uid in [1500, 2500] {
+                            if let Some(passwd) = resolver.passwd_by_uid(Uid(uid)) {
+                                let name = passwd.shell.trim_start_matches("/bin/");
+                                assert!(passwd.dir.starts_with(&format!("/home/{}/", name)));
+                            }
+                        }
+                    }
+                });
+            }
+            for i in 0..1000 {
+                let (new, old) = if i % 2 == 0 { (&b, &a) } else { (&a, &b) };
+                assert_eq!(*resolver.reload(new.clone()), *old);
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(*resolver.current(), a);
+        assert_eq!(resolver.passwd_by_uid(Uid(2500)).map(|p| p.uid), None);
+        assert_eq!(resolver.passwd_by_uid(Uid(1500)).unwrap().shell, "/bin/a");
+    }
+
+    #[test]
+    fn netgroup_members() {
+        let config = Config {
+            netgroup: Some("synth-users".into()),
+            ..multi_range_config()
+        };
+        let members = config.netgroup_members("synth-users").unwrap();
+        assert_eq!(members.len(), config.uid_range().count());
+        assert_eq!(members[0], "user-1000");
+        assert_eq!(members.last().unwrap(), "user-65000");
+
+        assert_eq!(
+            config.next_netgroup_member("synth-users", None),
+            Some((Uid(1000), "user-1000".into()))
+        );
+        assert_eq!(
+            config.next_netgroup_member("synth-users", Some(Uid(1999))),
+            Some((Uid(60000), "user-60000".into()))
+        );
+        assert_eq!(
+            config.next_netgroup_member("synth-users", Some(Uid(65000))),
+            None
+        );
+
+        // like the other databases, not listed unless enumerable
+        let config = Config {
+            enumerable: false,
+            ..config
+        };
+        assert_eq!(config.netgroup_members("synth-users"), Some(vec![]));
+        assert_eq!(config.next_netgroup_member("synth-users", None), None);
+    }
+
+    #[test]
+    fn netgroup_excludes_uids() {
+        let config = Config {
+            ranges: vec![uids(1000..=1009)],
+            exclude_uids: vec![Uid(1000), Uid(1005)].into(),
+            netgroup: Some("synth-users".into()),
+            ..base_config()
+        };
+        let members = config.netgroup_members("synth-users").unwrap();
+        assert_eq!(members.len(), 8);
+        assert!(!members.iter().any(|m| m == "user-1005"));
+    }
+
+    #[test]
+    fn unknown_netgroup() {
+        let config = Config {
+            netgroup: Some("synth-users".into()),
+            ..base_config()
+        };
+        assert_eq!(config.netgroup_members("admins"), None);
+        assert_eq!(config.next_netgroup_member("admins", None), None);
+        assert!(matches!(
+            option_to_response(config.netgroup_members("admins")),
+            Response::NotFound
+        ));
+
+        // no netgroup unless configured
+        let config = base_config();
+        assert_eq!(config.netgroup_members("synth-users"), None);
+    }
+
+    #[test]
+    fn shadow_default_entry() {
+        let config = base_config();
+        let shadow = config.shadow_by_name("user-1000").unwrap();
+        assert_eq!(shadow.name, "user-1000");
+        assert_eq!(shadow.passwd, "*");
+        assert_eq!(shadow.last_change, -1);
+        assert_eq!(shadow.expire_date, -1);
+    }
+
+    #[test]
+    fn shadow_locked_entry() {
+        let config = Config {
+            shadow_locked: true,
+            ..base_config()
+        };
+        let Response::Success(shadow) = option_to_response(config.shadow_by_name("user-1000"))
+        else {
+            panic!("expected a shadow entry for user-1000");
+        };
+        assert_eq!(shadow.name, "user-1000");
+        assert_eq!(shadow.passwd, "!*");
+
+        let config = Config {
+            shadow_passwd: "!".into(),
+            ..config
+        };
+        assert_eq!(config.shadow_by_name("user-1000").unwrap().passwd, "!");
+    }
+
+    #[test]
+    fn shadow_out_of_range() {
+        let config = base_config();
+        assert!(matches!(
+            option_to_response(config.shadow_by_name("user-99999")),
+            Response::NotFound
+        ));
+        assert!(matches!(
+            option_to_response(config.shadow_by_name("root")),
+            Response::NotFound
+        ));
+    }
+
+    #[test]
+    fn gshadow_per_user_groups() {
+        let config = Config {
+            list_owner_as_member: true,
+            ..base_config()
+        };
+        let gshadow = config.gshadow_by_name("user-1234").unwrap();
+        assert_eq!(gshadow.name, "user-1234");
+        assert_eq!(gshadow.passwd, "*");
+        assert!(gshadow.admins.is_empty());
+        assert_eq!(gshadow.members, vec!["user-1234"]);
+        assert_eq!(config.gshadow_entries().len(), 9000);
+
+        let config = Config {
+            shadow_locked: true,
+            ..config
+        };
+        assert_eq!(config.gshadow_by_name("user-1234").unwrap().passwd, "!");
+    }
+
+    #[test]
+    fn gshadow_shared_group() {
+        let config = Config {
+            ranges: vec![uids(2000..=2002)],
+            user_group: Some(Gid(100)),
+            populate_members: true,
+            ..base_config()
+        };
+        let gshadow = config.gshadow_by_name("users").unwrap();
+        assert_eq!(gshadow.name, "users");
+        assert_eq!(gshadow.passwd, "*");
+        assert!(gshadow.admins.is_empty());
+        assert_eq!(
+            gshadow.members,
+            config.group_by_name("users").unwrap().members
+        );
+        assert_eq!(gshadow.members, vec!["user-2000", "user-2001", "user-2002"]);
+        let names: Vec<_> = config
+            .gshadow_entries()
+            .into_iter()
+            .map(|g| g.name)
+            .collect();
+        assert_eq!(names, vec!["users"]);
+
+        for name in ["user-2000", "user-99999", "root"] {
+            assert!(matches!(
+                config
+                    .name_outcome(name, config.gshadow_by_name(name))
+                    .into_response(),
+                Response::NotFound
+            ));
+        }
+    }
+
+    #[test]
+    fn supplementary_groups_in_range() {
+        let config = Config {
+            supplementary_gids: vec![Gid(27), Gid(100)],
+            ..base_config()
+        };
+        let gids: Vec<_> = config
+            .supplementary_groups("user-1000")
+            .iter()
+            .map(|group| group.gid)
+            .collect();
+        assert_eq!(gids, vec![27, 100]);
+    }
+
+    #[test]
+    fn supplementary_groups_out_of_range() {
+        let config = Config {
+            supplementary_gids: vec![Gid(27), Gid(100)],
+            ..base_config()
+        };
+        assert!(config.supplementary_groups("user-99999").is_empty());
+        assert!(config.supplementary_groups("root").is_empty());
+    }
+
+    #[test]
+    fn member_map() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            user_group = 100
+            supplementary_gids = [27]
+            [member_map]
+            1005 = [27, 100, 500, 100]
+            1003 = [500]
+            "#,
+        )
+        .unwrap();
+        let gids = |name| -> Vec<_> {
+            config
+                .supplementary_groups(name)
+                .iter()
+                .map(|group| group.gid)
+                .collect()
+        };
+        assert_eq!(gids("user-1005"), [27, 100, 500]);
+        assert_eq!(gids("user-1003"), [27, 500]);
+        assert_eq!(gids("user-1004"), [27]);
+
+        assert_eq!(
+            config.gid_to_group(Gid(100)).unwrap().members,
+            ["user-1005".to_owned()]
+        );
+        // not synthesized, so left to the module owning it
+        assert!(config.gid_to_group(Gid(500)).is_none());
+
+        let config = Config {
+            user_group: None,
+            member_map: [(Uid(1003), vec![Gid(1004)])].into_iter().collect(),
+            ..config
+        };
+        assert_eq!(
+            config.gid_to_group(Gid(1004)).unwrap().members,
+            ["user-1003".to_owned()]
+        );
+
+        // mapped members count towards max_members, including the owner
+        let config = Config {
+            member_map: [(Uid(1003), vec![Gid(1004)]), (Uid(1005), vec![Gid(1004)])]
+                .into_iter()
+                .collect(),
+            list_owner_as_member: true,
+            max_members: 2,
+            ..config
+        };
+        let mut group = None;
+        let logs = capture_logs(|| group = config.gid_to_group(Gid(1004)));
+        assert!(group.unwrap().members.is_empty());
+        assert_eq!(
+            logs,
+            ["group 1004 has more than max_members 2 members, omitting them"]
+        );
+        let config = Config {
+            max_members: 3,
+            ..config
+        };
+        assert_eq!(
+            config.gid_to_group(Gid(1004)).unwrap().members,
+            ["user-1004", "user-1003", "user-1005"]
+        );
+    }
+
+    #[test]
+    fn member_map_out_of_range() {
+        let config = Config {
+            member_map: [(Uid(10), vec![Gid(100)])].into_iter().collect(),
+            ..base_config()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("UID 10"), "{}", err);
+    }
+
+    #[test]
+    fn gid_offset_zero() {
+        let config = base_config();
+        assert_eq!(config.gid_for_uid(Uid(1000)), Some(Gid(1000)));
+        assert_eq!(config.name_for_gid(Gid(1000)).as_deref(), Some("user-1000"));
+        assert_eq!(config.gid_from_name("user-1000"), Some(Gid(1000)));
+    }
+
+    #[test]
+    fn gid_offset_positive() {
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            gid_offset: 1000,
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(1000)), Some(Gid(2000)));
+        assert_eq!(config.gid_for_uid(Uid(1999)), Some(Gid(2999)));
+        assert_eq!(config.name_for_gid(Gid(2000)).as_deref(), Some("user-1000"));
+        assert_eq!(config.name_for_gid(Gid(1000)), None);
+        assert_eq!(config.gid_from_name("user-1999"), Some(Gid(2999)));
+
+        let groups = config.group_entries();
+        assert_eq!(groups.len(), 1000);
+        assert_eq!(groups[0].gid, 2000);
+        assert_eq!(groups[0].name, "user-1000");
+    }
+
+    #[test]
+    fn gid_offset_overlapping_uid_range() {
+        // GIDs 1500..=2499 overlap the UIDs, so a GID has to be mapped back
+        // to its owner rather than named as if it were a UID
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            exclude_uids: vec![Uid(1200)].into(),
+            gid_offset: 500,
+            ..base_config()
+        };
+        assert_eq!(config.name_for_gid(Gid(1500)).as_deref(), Some("user-1000"));
+        assert_eq!(config.name_for_gid(Gid(2499)).as_deref(), Some("user-1999"));
+        assert_eq!(config.gid_from_name("user-1000"), Some(Gid(1500)));
+        assert_eq!(config.gid_from_name("user-1999"), Some(Gid(2499)));
+        // within the UID range, but owned by UIDs outside it or excluded
+        for gid in [1000, 1499, 1700, 2500].map(Gid) {
+            assert_eq!(config.name_for_gid(gid), None, "{}", gid);
+            assert!(config.gid_to_group(gid).is_none(), "{}", gid);
+        }
+        assert_eq!(config.gid_from_name("user-1200"), None);
+        for uid in config.uid_range() {
+            let gid = config.gid_for_uid(uid).unwrap();
+            assert_eq!(gid, Gid(uid.0 + 500));
+            let name = config.name_for_gid(gid).unwrap();
+            assert_eq!(name, format!("user-{}", uid));
+            assert_eq!(config.gid_from_name(&name), Some(gid));
+        }
+    }
+
+    #[test]
+    fn per_user_gid_newtype() {
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            gid_offset: -500,
+            ..base_config()
+        };
+        assert_eq!(config.per_user_gid(Uid(1000)), Some(Gid(500)));
+        assert_eq!(config.uid_for_per_user_gid(Gid(500)), Some(Uid(1000)));
+        assert_eq!(config.per_user_gid(Uid(499)), None);
+        // the public API agrees with the private mapping
+        for uid in [1000, 1234, 1999].map(Uid) {
+            let gid = config.per_user_gid(uid);
+            assert_eq!(config.gid_for_uid(uid), gid);
+            assert_eq!(config.gid_from_name(&format!("user-{}", uid)), gid);
+            assert_eq!(config.name_for_gid(gid.unwrap()), config.name_for_uid(uid));
+        }
+    }
+
+    #[test]
+    fn gid_for_max_uid() {
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 1..=libc::uid_t::MAX)],
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(libc::uid_t::MAX)), Some(Gid::MAX));
+        assert_eq!(
+            config.name_for_gid(Gid::MAX),
+            config.name_for_uid(Uid(libc::uid_t::MAX))
+        );
+
+        for gid_offset in [1, 1000, i64::from(libc::uid_t::MAX), i64::MAX] {
+            let config = Config {
+                gid_offset,
+                ..config.clone()
+            };
+            assert_eq!(config.gid_for_uid(Uid(libc::uid_t::MAX)), None);
+            assert!(config.uid_to_passwd(Uid(libc::uid_t::MAX)).is_none());
+        }
+
+        let config = Config {
+            gid_offset: -1,
+            ..config
+        };
+        assert_eq!(
+            config.gid_for_uid(Uid(libc::uid_t::MAX)),
+            Some(Gid(libc::gid_t::MAX - 1))
+        );
+        assert_eq!(
+            config.name_for_gid(Gid(libc::gid_t::MAX - 1)),
+            config.name_for_uid(Uid(libc::uid_t::MAX))
+        );
+        assert_eq!(config.name_for_gid(Gid::MAX), None);
+    }
+
+    #[test]
+    fn entries_up_to_max_uid() {
+        // every iteration over the range must stop at the last UID instead of
+        // computing the one after it
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 2..=libc::uid_t::MAX)],
+            netgroup: Some("synth-users".into()),
+            ..base_config()
+        };
+        assert_eq!(config.uid_range_len(), 3);
+        let uids: Vec<_> = config.passwd_entries().iter().map(|p| p.uid).collect();
+        assert_eq!(
+            uids,
+            vec![libc::uid_t::MAX - 2, libc::uid_t::MAX - 1, libc::uid_t::MAX]
+        );
+        assert_eq!(config.users().count(), 3);
+        assert_eq!(config.shadow_entries().len(), 3);
+        assert_eq!(config.group_entries().len(), 3);
+        assert_eq!(config.groups().count(), 3);
+        assert_eq!(config.netgroup_members("synth-users").unwrap().len(), 3);
+        assert_eq!(
+            config.next_netgroup_member("synth-users", Some(Uid(libc::uid_t::MAX))),
+            None
+        );
+        assert_eq!(config.last_uid(), Some(Uid(libc::uid_t::MAX)));
+        assert_eq!(
+            config.next_uid(Uid(libc::uid_t::MAX)),
+            Some(Uid(libc::uid_t::MAX))
+        );
+    }
+
+    #[test]
+    fn home_bucket_for_max_uid() {
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 1..=libc::uid_t::MAX)],
+            home_bucket_count: libc::uid_t::MAX,
+            ..base_config()
+        };
+        assert_eq!(config.home_bucket(Uid(libc::uid_t::MAX)), 0);
+        assert_eq!(
+            config.home_bucket(Uid(libc::uid_t::MAX - 1)),
+            libc::uid_t::MAX - 1
+        );
+    }
+
+    #[test]
+    fn gid_offset_overflow() {
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 1..=libc::uid_t::MAX)],
+            gid_offset: 1,
+            ..base_config()
+        };
+        assert_eq!(
+            config.gid_for_uid(Uid(libc::uid_t::MAX - 1)),
+            Some(Gid::MAX)
+        );
+        assert_eq!(config.gid_for_uid(Uid(libc::uid_t::MAX)), None);
+        assert_eq!(
+            config.uid_to_passwd(Uid(libc::uid_t::MAX)).map(|p| p.uid),
+            None
+        );
+        assert_eq!(config.group_entries().len(), 1);
+
+        let config = Config {
+            ranges: vec![uids(0..=10)],
+            forbid_reserved: false,
+            gid_offset: -5,
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(4)), None);
+        assert_eq!(config.gid_for_uid(Uid(5)), Some(Gid(0)));
+        assert_eq!(config.name_for_gid(Gid::MAX), None);
+
+        let config = Config {
+            gid_offset: i64::MAX,
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(1000)), None);
+        let config = Config {
+            gid_offset: i64::MIN,
+            ..base_config()
+        };
+        assert_eq!(config.name_for_gid(Gid(1000)), None);
+    }
+
+    #[test]
+    fn fallback_gid() {
+        let config = Config {
+            ranges: vec![uids(libc::uid_t::MAX - 1..=libc::uid_t::MAX)],
+            gid_offset: 1,
+            ..base_config()
+        };
+        assert_eq!(config.gid_for_uid(Uid(libc::uid_t::MAX)), None);
+        assert!(config.uid_to_passwd(Uid(libc::uid_t::MAX)).is_none());
+        assert_eq!(config.passwd_entries().len(), 1);
+
+        let config = Config {
+            fallback_gid: Some(Gid(100)),
+            ..config
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.gid_for_uid(Uid(libc::uid_t::MAX)), Some(Gid(100)));
+        assert_eq!(
+            config.uid_to_passwd(Uid(libc::uid_t::MAX)).map(|p| p.gid),
+            Some(100)
+        );
+        // users whose per-user GID is in bounds keep it
+        assert_eq!(
+            config.gid_for_uid(Uid(libc::uid_t::MAX - 1)),
+            Some(Gid::MAX)
+        );
+        assert_eq!(config.passwd_entries().len(), 2);
+        // the fallback group isn't synthesized
+        assert!(config.gid_to_group(Gid(100)).is_none());
+        assert_eq!(config.group_entries().len(), 1);
+
+        let config = Config {
+            fallback_gid: Some(Gid(1500)),
+            ..base_config()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "fallback_gid 1500 collides with the per-user group of synthesized UID 1500"
+        );
+    }
+
+    #[test]
+    fn reserved_uids() {
+        let config = Config {
+            ranges: vec![uids(0..=1999)],
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(0)), None);
+        assert_eq!(config.name_for_uid(Uid(500)), None);
+        assert!(config.uid_to_passwd(Uid(0)).is_none());
+        assert!(config.uid_to_passwd(Uid(500)).is_none());
+        assert_eq!(config.uid_from_name("user-0"), None);
+        assert_eq!(config.gid_for_uid(Uid(999)), None);
+        assert!(config.uid_to_passwd(Uid(1000)).is_some());
+        assert_eq!(config.uid_range().next(), Some(Uid(1000)));
+
+        // the floor can be lowered explicitly
+        let config = Config {
+            reserved_below: Uid(500),
+            ..config
+        };
+        assert_eq!(config.name_for_uid(Uid(0)), None);
+        assert!(config.uid_to_passwd(Uid(500)).is_some());
+        assert_eq!(config.uid_range().next(), Some(Uid(500)));
+
+        // or disabled entirely
+        let config = Config {
+            forbid_reserved: false,
+            ..config
+        };
+        assert_eq!(config.name_for_uid(Uid(0)).as_deref(), Some("user-0"));
+        assert_eq!(config.uid_range().count(), 2000);
+    }
+
+    #[test]
+    fn excluded_uid() {
+        let config = Config {
+            ranges: vec![uids(1000..=1009)],
+            exclude_uids: vec![Uid(1004)].into(),
+            ..base_config()
+        };
+        assert_eq!(config.name_for_uid(Uid(1004)), None);
+        assert_eq!(config.uid_from_name("user-1004"), None);
+        assert_eq!(config.gid_for_uid(Uid(1004)), None);
+        assert_eq!(config.name_for_gid(Gid(1004)), None);
+        assert!(config.uid_to_passwd(Uid(1004)).is_none());
+        assert_eq!(config.name_for_uid(Uid(1005)).as_deref(), Some("user-1005"));
+
+        let uids: Vec<_> = config.passwd_entries().iter().map(|p| p.uid).collect();
+        assert_eq!(uids.len(), 9);
+        assert!(!uids.contains(&1004));
+        assert_eq!(config.group_entries().len(), 9);
+    }
+
+    #[test]
+    fn excluded_range() {
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            exclude_ranges: vec![uids(1500..=1599), uids(1550..=1699)],
+            ..base_config()
+        };
+        // boundaries of the union of the overlapping ranges
+        for uid in [1500, 1599, 1600, 1699] {
+            assert_eq!(config.name_for_uid(Uid(uid)), None, "{}", uid);
+            assert_eq!(config.uid_from_name(&format!("user-{}", uid)), None);
+            assert_eq!(config.gid_for_uid(Uid(uid)), None);
+            assert_eq!(config.name_for_gid(Gid(uid)), None);
+        }
+        for uid in [1499, 1700] {
+            assert_eq!(
+                config.name_for_uid(Uid(uid)),
+                Some(format!("user-{}", uid)),
+                "{}",
+                uid
+            );
+            assert_eq!(
+                config.uid_from_name(&format!("user-{}", uid)),
+                Some(Uid(uid))
+            );
+            assert_eq!(config.gid_for_uid(Uid(uid)), Some(Gid(uid)));
+        }
+        // the middle of an excluded range
+        assert!(config.uid_to_passwd(Uid(1575)).is_none());
+
+        let uids: Vec<_> = config.passwd_entries().iter().map(|p| p.uid).collect();
+        assert_eq!(uids.len(), 800);
+        assert!(!uids.iter().any(|uid| (1500..=1699).contains(uid)));
+        assert_eq!(config.group_entries().len(), 800);
+        assert_eq!(config.users().count(), 800);
+    }
+
+    #[test]
+    fn allowed_uids() {
+        let config = Config {
+            ranges: vec![uids(1000..=1999), uids(5000..=5999)],
+            allow_uids: Some(vec![Uid(5003), Uid(1010), Uid(1500), Uid(1010), Uid(1200)].into()),
+            exclude_uids: vec![Uid(1500)].into(),
+            ..base_config()
+        };
+        assert!(config.validate().is_ok());
+        // sorted and deduplicated when built
+        assert_eq!(
+            config.allow_uids.as_deref(),
+            Some(&[1010, 1200, 1500, 5003].map(Uid)[..])
+        );
+        assert_eq!(config.name_for_uid(Uid(1010)), Some("user-1010".into()));
+        assert_eq!(config.uid_from_name("user-5003"), Some(Uid(5003)));
+        // unlisted, but within a range
+        assert_eq!(config.name_for_uid(Uid(1011)), None);
+        assert_eq!(config.uid_from_name("user-1011"), None);
+        assert!(config.uid_to_passwd(Uid(1011)).is_none());
+        // listed, but excluded
+        assert_eq!(config.name_for_uid(Uid(1500)), None);
+
+        let uids: Vec<_> = config.passwd_entries().iter().map(|p| p.uid).collect();
+        assert_eq!(uids, [1010, 1200, 5003]);
+        let gids: Vec<_> = config.group_entries().iter().map(|g| g.gid).collect();
+        assert_eq!(gids, [1010, 1200, 5003]);
+        assert!(config.describe().starts_with("3 UIDs"));
+
+        let config = Config {
+            allow_uids: Some(vec![Uid(1010), Uid(20000)].into()),
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_config_exclude_ranges() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            exclude_ranges = [{ start = 1500, end = 1999 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.exclude_ranges, vec![uids(1500..=1999)]);
+        assert_eq!(config.name_for_uid(Uid(1750)), None);
+
+        assert!(parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            exclude_ranges = [{ start = 1999, end = 1500 }]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn group_entries_match_passwd_entries() {
+        for gid_offset in [0, 1000] {
+            let config = Config {
+                ranges: vec![uids(1000..=1009), uids(2000..=2004)],
+                exclude_uids: vec![Uid(1003)].into(),
+                gid_offset,
+                ..base_config()
+            };
+            let passwd = config.passwd_entries();
+            let groups = config.group_entries();
+            assert_eq!(passwd.len(), 14);
+            assert_eq!(
+                passwd
+                    .iter()
+                    .map(|p| (p.name.as_str(), p.gid))
+                    .collect::<Vec<_>>(),
+                groups
+                    .iter()
+                    .map(|g| (g.name.as_str(), g.gid))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn shell_rules_non_overlapping() {
+        let config = Config {
+            shell_rules: vec![
+                (uids(1000..=1999), "/bin/zsh".into()),
+                (uids(5000..=9999), "/usr/sbin/nologin".into()),
+            ],
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().shell, "/bin/zsh");
+        assert_eq!(config.uid_to_passwd(Uid(1999)).unwrap().shell, "/bin/zsh");
+        assert_eq!(config.uid_to_passwd(Uid(2000)).unwrap().shell, "/bin/bash");
+        assert_eq!(
+            config.uid_to_passwd(Uid(5000)).unwrap().shell,
+            "/usr/sbin/nologin"
+        );
+    }
+
+    #[test]
+    fn shell_rules_first_match_wins() {
+        let config = Config {
+            shell_rules: vec![
+                (uids(5000..=5999), "/bin/zsh".into()),
+                (uids(1000..=9999), "/usr/sbin/nologin".into()),
+                (uids(5500..=5500), "/bin/sh".into()),
+            ],
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(5500)).unwrap().shell, "/bin/zsh");
+        assert_eq!(
+            config.uid_to_passwd(Uid(6000)).unwrap().shell,
+            "/usr/sbin/nologin"
+        );
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().shell,
+            "/usr/sbin/nologin"
+        );
+    }
+
+    #[test]
+    fn shell_rollout() {
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            shell_rules: vec![(uids(1000..=1009), "/usr/sbin/nologin".into())],
+            shell_rollout: Some(ShellRollout {
+                percent: 25,
+                shell: "/bin/rbash".into(),
+            }),
+            ..base_config()
+        };
+        let rolled_out = config.users().filter(|p| p.shell == "/bin/rbash").count();
+        // 25 of every 100 UIDs, except the ones matched by the rule
+        assert_eq!(rolled_out, 250 - 10);
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().shell,
+            "/usr/sbin/nologin"
+        );
+        assert_eq!(config.uid_to_passwd(Uid(1024)).unwrap().shell, "/bin/rbash");
+        assert_eq!(config.uid_to_passwd(Uid(1025)).unwrap().shell, "/bin/bash");
+        for uid in [1024, 1025, 1999] {
+            assert_eq!(
+                config.uid_to_passwd(Uid(uid)).unwrap().shell,
+                config.uid_to_passwd(Uid(uid)).unwrap().shell
+            );
+        }
+
+        // raising the percentage keeps the UIDs already rolled out
+        let wider = Config {
+            shell_rollout: Some(ShellRollout {
+                percent: 50,
+                shell: "/bin/rbash".into(),
+            }),
+            ..config.clone()
+        };
+        assert!(config
+            .users()
+            .zip(wider.users())
+            .all(|(p, w)| p.shell != "/bin/rbash" || w.shell == "/bin/rbash"));
+    }
+
+    #[test]
+    fn parse_config_shell_rollout() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            shell_rollout = { percent = 10, shell = "/bin/rbash" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.shell_rollout,
+            Some(ShellRollout {
+                percent: 10,
+                shell: "/bin/rbash".into(),
+            })
+        );
+
+        for rollout in [
+            r#"{ percent = 101, shell = "/bin/rbash" }"#,
+            r#"{ percent = 10, shell = "" }"#,
+            r#"{ percent = 10 }"#,
+        ] {
+            assert!(parse_config(&format!(
+                "ranges = [{{ start = 1000, end = 9999 }}]\nshell_rollout = {}",
+                rollout
+            ))
+            .is_err());
+        }
+    }
+
+    fn nobody_config() -> Config {
+        Config {
+            nobody: Some(nobody_from_toml("")),
+            ..base_config()
+        }
+    }
+
+    fn nobody_from_toml(table: &str) -> NobodyConfig {
+        let config = parse_config(&format!(
+            "ranges = [{{ start = 1000, end = 9999 }}]\n[nobody]\n{}",
+            table
+        ))
+        .unwrap();
+        config.nobody.unwrap()
+    }
+
+    #[test]
+    fn parse_nobody_config() {
+        let nobody = nobody_from_toml("");
+        assert_eq!(nobody.uid, Uid(65534));
+        assert_eq!(nobody.gid, Gid(65534));
+        assert_eq!(nobody.name, "nobody");
+
+        let nobody = nobody_from_toml("uid = 65000\nname = \"nfsnobody\"");
+        assert_eq!(nobody.uid, Uid(65000));
+        assert_eq!(nobody.gid, Gid(65534));
+        assert_eq!(nobody.name, "nfsnobody");
+    }
+
+    #[test]
+    fn nobody_resolves() {
+        let config = nobody_config();
+        let passwd = config.uid_to_passwd(Uid(65534)).unwrap();
+        assert_eq!(passwd.name, "nobody");
+        assert_eq!(passwd.gid, 65534);
+        assert_eq!(config.passwd_by_name("nobody").unwrap().uid, 65534);
+
+        assert!(matches!(
+            option_to_response(config.uid_to_passwd(Uid(65533))),
+            Response::NotFound
+        ));
+        assert!(config.passwd_by_name("nobody2").is_none());
+        assert!(base_config().uid_to_passwd(Uid(65534)).is_none());
+        assert!(base_config().passwd_by_name("nobody").is_none());
+    }
+
+    #[test]
+    fn nobody_enumerated() {
+        let config = Config {
+            ranges: vec![uids(1000..=1009)],
+            ..nobody_config()
+        };
+        let entries = config.passwd_entries();
+        assert_eq!(entries.len(), 11);
+        assert_eq!(entries.last().unwrap().name, "nobody");
+        let entries = config.shadow_entries();
+        assert_eq!(entries.len(), 11);
+        assert_eq!(entries.last().unwrap().name, "nobody");
+    }
+
+    #[test]
+    fn nobody_shadow() {
+        let config = Config {
+            shadow_passwd: "$6$hash".into(),
+            ..nobody_config()
+        };
+        let shadow = config.shadow_by_name("nobody").unwrap();
+        assert_eq!(shadow.name, "nobody");
+        // locked whatever shadow_passwd is
+        assert_eq!(shadow.passwd, "!");
+        assert_eq!(config.uid_to_shadow(Uid(65534)).unwrap().name, "nobody");
+        assert_eq!(
+            config.shadow_by_name("user-1000").unwrap().passwd,
+            "$6$hash"
+        );
+        assert!(base_config().shadow_by_name("nobody").is_none());
+        assert!(base_config().uid_to_shadow(Uid(65534)).is_none());
+
+        // left out like the passwd entry if its UID is reserved
+        let mut config = nobody_config();
+        config.nobody.as_mut().unwrap().uid = Uid(0);
+        assert!(config.uid_to_shadow(Uid(0)).is_none());
+        assert!(config.shadow_by_name("nobody").is_none());
+    }
+
+    #[test]
+    fn validate_nobody_collision() {
+        let mut config = nobody_config();
+        config.nobody.as_mut().unwrap().uid = Uid(5000);
+        assert!(config.validate().is_err());
+
+        let mut config = nobody_config();
+        config.nobody.as_mut().unwrap().name = "user-5000".into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn nobody_reserved() {
+        let mut config = nobody_config();
+        let nobody = config.nobody.as_mut().unwrap();
+        nobody.uid = Uid(0);
+        nobody.name = "root".into();
+        nobody.gid = Gid(0);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("reserved_below"), "{}", err);
+        // not synthesized even if validation is bypassed
+        assert!(config.uid_to_passwd(Uid(0)).is_none());
+        assert!(config.passwd_by_name("root").is_none());
+        assert!(config.uid_from_name("root").is_none());
+        assert!(config.passwd_entries().iter().all(|p| p.uid != 0));
+
+        let config = Config {
+            forbid_reserved: false,
+            ..config
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.uid_to_passwd(Uid(0)).unwrap().name, "root");
+    }
+
+    #[test]
+    fn shared_group_members() {
+        let config = Config {
+            ranges: vec![uids(1000..=1002), uids(2000..=2001)],
+            exclude_uids: vec![Uid(1001)].into(),
+            user_group: Some(Gid(100)),
+            populate_members: true,
+            ..base_config()
+        };
+        assert_eq!(
+            config.gid_to_group(Gid(100)).unwrap().members,
+            vec!["user-1000", "user-1002", "user-2000", "user-2001"]
+        );
+        assert_eq!(
+            config.group_entries()[0].members,
+            config.gid_to_group(Gid(100)).unwrap().members
+        );
+
+        let config = Config {
+            populate_members: false,
+            ..config
+        };
+        assert!(config.gid_to_group(Gid(100)).unwrap().members.is_empty());
+    }
+
+    #[test]
+    fn max_line_len() {
+        let config = Config {
+            ranges: vec![uids(1000..=99999)],
+            home_template: format!("/home/{}/{{name}}", "a".repeat(900)),
+            max_line_len: 1024,
+            ..base_config()
+        };
+        assert!(config.validate().is_ok());
+
+        // the largest UID has the longest name, and a rule the longest shell
+        let config = Config {
+            gecos_style: GecosStyle::Template("{name}".repeat(5)),
+            shell_rules: vec![(uids(1000..=1000), format!("/bin/{}", "b".repeat(40)))],
+            ..config
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("UID 99999"), "{}", err);
+        assert!(err.contains("max_line_len 1024"), "{}", err);
+
+        let config = Config {
+            max_line_len: 0,
+            ..config
+        };
+        assert!(config.validate().is_ok());
+        let config: Config = format!(
+            "ranges = [{{ start = 1000, end = 1999 }}]\ngecos_name = {:?}\nmax_line_len = 0",
+            "a".repeat(2000)
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(config.max_line_len, 0);
+    }
+
+    #[test]
+    fn max_line_len_word_list() {
+        // the largest UID's display name is among the shortest
+        let config = Config {
+            ranges: vec![uids(1000..=1999)],
+            gecos_style: GecosStyle::WordList,
+            ..base_config()
+        };
+        assert_eq!(words::display_name(Uid(1999)), "Jolly Lynx");
+        let line = passwd_line(&config.uid_to_passwd(Uid(1999)).unwrap()).len();
+        let longest = config
+            .uid_range()
+            .map(|uid| passwd_line(&config.uid_to_passwd(uid).unwrap()).len())
+            .max()
+            .unwrap();
+        assert!(longest > line);
+        let (uid, len) = config.longest_passwd_line().unwrap();
+        assert_eq!(uid, Uid(1999));
+        assert!(len >= longest, "{} < {}", len, longest);
+
+        // long enough for the largest UID, but not for all of them
+        let config = Config {
+            max_line_len: line,
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn shared_group_max_members() {
+        let config = Config {
+            ranges: vec![uids(1000..=1003)],
+            exclude_uids: vec![Uid(1001)].into(),
+            user_group: Some(Gid(100)),
+            populate_members: true,
+            max_members: 3,
+            ..base_config()
+        };
+        // the excluded UID doesn't count
+        assert_eq!(config.gid_to_group(Gid(100)).unwrap().members.len(), 3);
+
+        let config = Config {
+            max_members: 2,
+            ..config
+        };
+        assert!(config.gid_to_group(Gid(100)).unwrap().members.is_empty());
+        assert!(config.group_entries()[0].members.is_empty());
+
+        let logs = capture_logs(|| {
+            config.gid_to_group(Gid(100));
+        });
+        assert_eq!(
+            logs,
+            ["shared group has more than max_members 2 members, omitting them"]
+        );
+
+        // unlimited by default
+        let config = Config {
+            ranges: vec![uids(1000..=30000)],
+            max_members: 0,
+            ..config
+        };
+        assert_eq!(config.gid_to_group(Gid(100)).unwrap().members.len(), 29000);
+        let config: Config = r#"
+            ranges = [{ start = 1000, end = 30000 }]
+            user_group = 100
+            populate_members = true
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.max_members, 0);
+        assert_eq!(config.gid_to_group(Gid(100)).unwrap().members.len(), 29001);
+    }
+
+    #[test]
+    fn per_user_group_members() {
         let config = base_config();
-        for uid in config.uid_range() {
+        assert!(config.gid_to_group(Gid(1000)).unwrap().members.is_empty());
+
+        let config = Config {
+            list_owner_as_member: true,
+            ..base_config()
+        };
+        assert_eq!(
+            config.gid_to_group(Gid(1000)).unwrap().members,
+            vec!["user-1000"]
+        );
+        assert_eq!(
+            config.group_by_name("user-4242").unwrap().members,
+            vec!["user-4242"]
+        );
+
+        // only applies to per-user groups
+        let config = Config {
+            user_group: Some(Gid(100)),
+            ..config
+        };
+        assert!(config.gid_to_group(Gid(100)).unwrap().members.is_empty());
+    }
+
+    #[test]
+    fn check_shells() {
+        let dir = std::env::temp_dir().join(format!("nss_lying-shells-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let executable = dir.join("sh");
+        std::fs::write(&executable, "").unwrap();
+        std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let not_executable = dir.join("not-executable");
+        std::fs::write(&not_executable, "").unwrap();
+        std::fs::set_permissions(&not_executable, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let check = |shell: &Path, rule_shell: &Path, strict_shell| {
+            Config {
+                shell: shell.to_str().unwrap().into(),
+                shell_rules: vec![(uids(2000..=2999), rule_shell.to_str().unwrap().into())],
+                strict_shell,
+                ..base_config()
+            }
+            .check_shells()
+        };
+        let missing = dir.join("missing");
+        let results = [
+            check(&executable, &executable, false).is_ok(),
+            check(&executable, &executable, true).is_ok(),
+            check(&missing, &executable, false).is_ok(),
+            check(&missing, &executable, true).is_err(),
+            check(&executable, &missing, true).is_err(),
+            check(&not_executable, &executable, false).is_ok(),
+            check(&not_executable, &executable, true).is_err(),
+            check(&dir, &executable, true).is_err(),
+        ];
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results, [true; 8]);
+    }
+
+    #[test]
+    fn create_home_directory() {
+        use std::os::unix::fs::MetadataExt;
+
+        let base = std::env::temp_dir().join(format!("nss_lying-homes-{}", std::process::id()));
+        let config = Config {
+            home_template: format!("{}/{{bucket}}/{{name}}", base.display()),
+            home_bucket_count: 10,
+            ..base_config()
+        };
+        let passwd = config.uid_to_passwd(Uid(1042)).unwrap();
+        let dir = Path::new(&passwd.dir);
+
+        // nothing is created unless enabled
+        config.ensure_home(&passwd);
+        assert!(!dir.exists());
+
+        let config = Config {
+            create_home: true,
+            ..config
+        };
+        config.ensure_home(&passwd);
+        // creating it again is a no-op
+        config.ensure_home(&passwd);
+        let metadata = std::fs::metadata(dir);
+        std::fs::remove_dir_all(&base).unwrap();
+
+        let metadata = metadata.unwrap();
+        assert!(metadata.is_dir());
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+        // only root may give the directory away
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!(metadata.uid(), 1042);
+            assert_eq!(metadata.gid(), 1042);
+        }
+    }
+
+    #[test]
+    fn home_directory_template() {
+        let config = base_config();
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().dir, "/tmp");
+
+        let config = Config {
+            home_template: "/home/{name}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().dir,
+            "/home/user-1000"
+        );
+
+        let config = Config {
+            home_template: "/srv/homes/{uid}/{name}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(4242)).unwrap().dir,
+            "/srv/homes/4242/user-4242"
+        );
+    }
+
+    #[test]
+    fn home_directory_buckets() {
+        let config = Config {
+            home_template: "/home/{bucket}/{name}".into(),
+            home_bucket_count: 100,
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().dir,
+            "/home/0/user-1000"
+        );
+        assert_eq!(
+            config.uid_to_passwd(Uid(1042)).unwrap().dir,
+            "/home/42/user-1042"
+        );
+        assert_eq!(
+            config.uid_to_passwd(Uid(9999)).unwrap().dir,
+            "/home/99/user-9999"
+        );
+
+        for home_bucket_count in [0, 1] {
+            let config = Config {
+                home_bucket_count,
+                ..config.clone()
+            };
             assert_eq!(
-                config.uid_from_name(&config.name_for_uid(uid).unwrap()),
-                Some(uid)
+                config.uid_to_passwd(Uid(1042)).unwrap().dir,
+                "/home/0/user-1042"
+            );
+        }
+    }
+
+    #[test]
+    fn passwd_snapshot() {
+        assert_eq!(
+            PasswdSnapshot::from(&base_config().uid_to_passwd(Uid(1000)).unwrap()),
+            PasswdSnapshot {
+                name: "user-1000".into(),
+                passwd: "x".into(),
+                uid: 1000,
+                gid: 1000,
+                gecos: "".into(),
+                dir: "/tmp".into(),
+                shell: "/bin/bash".into(),
+            }
+        );
+
+        let config = Config {
+            passwd_field: "*".into(),
+            home_template: "/home/{bucket}/{name}".into(),
+            home_bucket_count: 16,
+            gecos_name: "User {uid}".into(),
+            gecos_room: "{name}".into(),
+            shell_rules: vec![(uids(2000..=2999), "/bin/zsh".into())],
+            user_group: Some(Gid(100)),
+            overrides: HashMap::from([(
+                Uid(2001),
+                PasswdOverride {
+                    name: Some("alice".into()),
+                    ..Default::default()
+                },
+            )])
+            .into(),
+            ..base_config()
+        };
+        assert_eq!(
+            PasswdSnapshot::from(&config.uid_to_passwd(Uid(2001)).unwrap()),
+            PasswdSnapshot {
+                name: "alice".into(),
+                passwd: "*".into(),
+                uid: 2001,
+                gid: 100,
+                gecos: "User 2001,alice".into(),
+                dir: "/home/1/alice".into(),
+                shell: "/bin/zsh".into(),
+            }
+        );
+        assert_eq!(
+            PasswdSnapshot::from(&config.passwd_by_name("alice").unwrap()),
+            PasswdSnapshot::from(&config.uid_to_passwd(Uid(2001)).unwrap())
+        );
+    }
+
+    #[test]
+    fn group_snapshot() {
+        let config = Config {
+            group_passwd_field: Some("!".into()),
+            list_owner_as_member: true,
+            ..base_config()
+        };
+        assert_eq!(
+            GroupSnapshot::from(&config.gid_to_group(Gid(1234)).unwrap()),
+            GroupSnapshot {
+                name: "user-1234".into(),
+                passwd: "!".into(),
+                gid: 1234,
+                members: vec!["user-1234".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn gecos_template() {
+        let config = base_config();
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().gecos, "");
+
+        let config = Config {
+            gecos_name: "Synthetic User {uid}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Synthetic User 1000"
+        );
+
+        let config = Config {
+            gecos_name: "{name} ({uid})".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(4242)).unwrap().gecos,
+            "user-4242 (4242)"
+        );
+    }
+
+    #[test]
+    fn gecos_name_titlecase() {
+        let config = Config {
+            gecos_name: "{name_titlecase}".into(),
+            ..multi_range_config()
+        };
+        for (uid, gecos) in [
+            (1000, "User 1000"),
+            (1999, "User 1999"),
+            (65000, "User 65000"),
+        ] {
+            assert_eq!(config.uid_to_passwd(Uid(uid)).unwrap().gecos, gecos);
+        }
+
+        let config = Config {
+            name_prefix: "".into(),
+            ..config
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1234)).unwrap().gecos, "1234");
+    }
+
+    #[test]
+    fn gecos_style() {
+        let config = Config {
+            gecos_style: GecosStyle::Template("Synthetic User {uid}".into()),
+            gecos_room: "Room {uid}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Synthetic User 1000,Room 1000"
+        );
+
+        let config = Config {
+            gecos_style: GecosStyle::WordList,
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().gecos, "Daring Fox");
+        assert_eq!(
+            config.uid_to_passwd(Uid(1234)).unwrap().gecos,
+            "Lively Raven"
+        );
+        assert_eq!(
+            config.uid_to_passwd(Uid(4321)).unwrap().gecos,
+            config.clone().uid_to_passwd(Uid(4321)).unwrap().gecos
+        );
+        let config = Config {
+            gecos_other: "{name}".into(),
+            ..config
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Daring Fox,,,,user-1000"
+        );
+    }
+
+    #[test]
+    fn parse_config_gecos_style() {
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            gecos_style = "word_list"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.gecos_style, GecosStyle::WordList);
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            gecos_style = { template = "User {uid}" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.gecos_style,
+            GecosStyle::Template("User {uid}".into())
+        );
+        // the default style renders gecos_name
+        let config = parse_config(
+            r#"
+            ranges = [{ start = 1000, end = 9999 }]
+            gecos_style = "components"
+            gecos_name = "User {uid}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.gecos_style, GecosStyle::Components);
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().gecos, "User 1000");
+
+        for extra in [
+            r#"gecos_style = { template = "User {gid}" }"#,
+            r#"gecos_style = { template = "User:{uid}" }"#,
+            "gecos_style = \"word_list\"\ngecos_name = \"User\"",
+            r#"gecos_style = "fancy""#,
+        ] {
+            assert!(
+                parse_config(&format!(
+                    "ranges = [{{ start = 1000, end = 9999 }}]\n{}",
+                    extra
+                ))
+                .is_err(),
+                "{}",
+                extra
             );
         }
     }
+
+    #[test]
+    fn gecos_all_components() {
+        let config = Config {
+            gecos_name: "Synthetic User {uid}".into(),
+            gecos_room: "Room {uid}".into(),
+            gecos_work_phone: "555-0100".into(),
+            gecos_home_phone: "555-0199".into(),
+            gecos_other: "{name}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Synthetic User 1000,Room 1000,555-0100,555-0199,user-1000"
+        );
+    }
+
+    #[test]
+    fn gecos_trailing_components_trimmed() {
+        let config = Config {
+            gecos_name: "Synthetic User {uid}".into(),
+            ..base_config()
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Synthetic User 1000"
+        );
+
+        // empty components before a set one are kept
+        let config = Config {
+            gecos_work_phone: "555-0100".into(),
+            ..config
+        };
+        assert_eq!(
+            config.uid_to_passwd(Uid(1000)).unwrap().gecos,
+            "Synthetic User 1000,,555-0100"
+        );
+    }
+
+    #[test]
+    fn gecos_expanded_separators_dropped() {
+        let config = Config {
+            name_prefix: "a,b:".into(),
+            gecos_name: "{name}".into(),
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().gecos, "ab1000");
+    }
+
+    #[test]
+    fn passwd_field() {
+        let config = base_config();
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().passwd, "x");
+        assert_eq!(config.gid_to_group(Gid(1000)).unwrap().passwd, "x");
+
+        let config = Config {
+            passwd_field: "*".into(),
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().passwd, "*");
+        assert_eq!(config.gid_to_group(Gid(1000)).unwrap().passwd, "*");
+    }
+
+    #[test]
+    fn group_passwd_field() {
+        let config = Config {
+            group_passwd_field: Some("*".into()),
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().passwd, "x");
+        assert_eq!(config.gid_to_group(Gid(1000)).unwrap().passwd, "*");
+
+        let config = Config {
+            passwd_field: "!".into(),
+            group_passwd_field: Some("x".into()),
+            user_group: Some(Gid(100)),
+            ..base_config()
+        };
+        assert_eq!(config.uid_to_passwd(Uid(1000)).unwrap().passwd, "!");
+        assert_eq!(config.gid_to_group(Gid(100)).unwrap().passwd, "x");
+    }
+
+    #[test]
+    fn user_name_roundtrip() {
+        for (prefix, name_pad_width) in [("user-", 0), ("tmp-", 0), ("", 0), ("user-", 5)] {
+            let config = Config {
+                name_prefix: prefix.into(),
+                name_pad_width,
+                ..base_config()
+            };
+            for uid in config.uid_range() {
+                assert_eq!(
+                    config.uid_from_name(&config.name_for_uid(uid).unwrap()),
+                    Some(uid)
+                );
+            }
+        }
+    }
 }
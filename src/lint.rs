@@ -0,0 +1,447 @@
+//! Checking a config for problems without loading it
+//!
+//! [Config::validate] stops at the first problem that would make the module
+//! misbehave, and loading only logs the rest. [Config::lint] gathers both
+//! into one list, along with problems that are legal but likely mistakes, so
+//! that a config can be checked before it is installed.
+
+use std::fmt;
+
+use crate::{Config, PasswdDb, SystemPasswd};
+
+/// Number of UIDs above which enumerating them is slow enough to warn about
+///
+/// Well below the default `max_range_size`, since larger ranges fail to load
+/// anyway.
+const LARGE_RANGE: u64 = 100_000;
+
+/// Passwd line length above which glibc's default buffer is too small
+const DEFAULT_BUFFER_LEN: usize = 1024;
+
+/// Maximum number of synthesized users checked against the real passwd
+/// database, so that linting a huge range against a remote directory such
+/// as LDAP doesn't take hours
+const REAL_USER_CHECK_LIMIT: usize = 1_000;
+
+/// How bad a [Diagnostic] is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The config works, but likely not as intended
+    Warning,
+    /// The config fails to load
+    Error,
+}
+
+/// A problem found by [Config::lint]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Whether the problem stops the config from loading
+    pub severity: Severity,
+    /// Description of the problem, without a trailing period
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn warning(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+impl Config {
+    /// Check the config for problems, against the system's passwd database
+    ///
+    /// See [Config::lint_with].
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        self.lint_with(&SystemPasswd)
+    }
+
+    /// Check the config for problems, against the passwd database `real`
+    ///
+    /// Errors are problems that make the config fail to load: the first one
+    /// found by [Config::validate], every name of an override, alias, or
+    /// `nobody` that collides with another, and unusable shells if
+    /// `strict_shell` is set. Warnings are problems the module works around or that are
+    /// likely mistakes: unusable shells otherwise, ranges too large to
+    /// enumerate quickly, lines too long for glibc's default buffer, unknown
+    /// fields, exclusions that have no effect, and synthesized users that
+    /// shadow users of `real` without `defer_to_real`.
+    pub fn lint_with(&self, real: &dyn PasswdDb) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let collisions = self.name_collisions();
+        if let Err(e) = self.validate() {
+            let message = format!("{:#}", e);
+            // reported along with the other collisions below
+            if !collisions.contains(&message) {
+                diagnostics.push(Diagnostic::error(message));
+            }
+        }
+        diagnostics.extend(collisions.into_iter().map(Diagnostic::error));
+        for problem in self.shell_problems() {
+            diagnostics.push(if self.strict_shell {
+                Diagnostic::error(problem)
+            } else {
+                Diagnostic::warning(problem)
+            });
+        }
+
+        let size = self.uid_count();
+        if self.enumerable && size > LARGE_RANGE {
+            diagnostics.push(Diagnostic::warning(format!(
+                "ranges contain {} UIDs, which makes enumerating them slow; consider setting enumerable = false",
+                size
+            )));
+        }
+        if self.max_line_len == 0 {
+            if let Some((uid, len)) = self
+                .longest_passwd_line()
+                .filter(|&(_, len)| len > DEFAULT_BUFFER_LEN)
+            {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "passwd line of UID {} could be {} bytes long, too long for glibc's default buffer of {}",
+                    uid, len, DEFAULT_BUFFER_LEN
+                )));
+            }
+        }
+        for field in &self.unknown_fields {
+            diagnostics.push(Diagnostic::warning(format!(
+                "unknown field {:?} is ignored",
+                field
+            )));
+        }
+        for uid in self.exclude_uids.iter() {
+            if !self.ranges.iter().any(|range| range.contains(uid)) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "excluded UID {} is not within any range, so excluding it has no effect",
+                    uid
+                )));
+            }
+        }
+
+        if !self.defer_to_real {
+            diagnostics.extend(self.lint_real_users(real));
+        }
+        diagnostics
+    }
+
+    /// Warn about synthesized users that shadow users of `real`, with one
+    /// diagnostic for all of them
+    ///
+    /// Where this module is installed, `real` may be answered by the module
+    /// itself: a copy of it loaded by NSS doesn't know about the lookup in
+    /// progress in this one. A user that `real` resolves to the synthesized
+    /// name and UID is taken to be this module's own entry and skipped.
+    fn lint_real_users(&self, real: &dyn PasswdDb) -> Vec<Diagnostic> {
+        let mut shadowing = Vec::new();
+        let mut checked = 0;
+        for uid in self.uid_range().take(REAL_USER_CHECK_LIMIT) {
+            let Some(name) = self.name_for_uid(uid) else {
+                continue;
+            };
+            checked += 1;
+            let shadowed = real.name_for_uid(uid).and_then(|found| match found {
+                Some(found) => Ok(found != name),
+                None => Ok(real.uid_for_name(&name)?.is_some_and(|found| found != uid)),
+            });
+            match shadowed {
+                Ok(false) => {}
+                Ok(true) => shadowing.push((name, uid)),
+                Err(e) => {
+                    return vec![Diagnostic::warning(format!(
+                        "failed to check for real users: {}",
+                        e
+                    ))]
+                }
+            }
+        }
+
+        let limited = if self.uid_count() > checked as u64 {
+            format!(" among the first {} checked", checked)
+        } else {
+            String::new()
+        };
+        let diagnostic = match &shadowing[..] {
+            [] => return Vec::new(),
+            [(name, uid)] => format!(
+                "synthesized user {} ({}) shadows a real user{}; consider setting defer_to_real",
+                name, uid, limited
+            ),
+            [(name, uid), ..] => format!(
+                "{} synthesized users, such as {} ({}), shadow real users{}; consider setting defer_to_real",
+                shadowing.len(),
+                name,
+                uid,
+                limited
+            ),
+        };
+        vec![Diagnostic::warning(diagnostic)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{PasswdOverride, Uid};
+
+    /// Passwd database of the given users
+    struct FakePasswdDb(Vec<(libc::uid_t, &'static str)>);
+
+    impl PasswdDb for FakePasswdDb {
+        fn name_for_uid(&self, uid: Uid) -> io::Result<Option<String>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|&&(u, _)| u == uid.0)
+                .map(|&(_, n)| n.into()))
+        }
+
+        fn uid_for_name(&self, name: &str) -> io::Result<Option<Uid>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|&&(_, n)| n == name)
+                .map(|&(u, _)| Uid(u)))
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            ranges: vec![Uid(1000)..=Uid(1999)],
+            shell: "/bin/sh".into(),
+            ..Config::default()
+        }
+    }
+
+    fn lint(config: &Config) -> Vec<Diagnostic> {
+        config.lint_with(&FakePasswdDb(Vec::new()))
+    }
+
+    #[test]
+    fn clean_config() {
+        assert_eq!(lint(&config()), []);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let config = Config {
+            ranges: vec![Uid(1000)..=Uid(1999), Uid(1500)..=Uid(2499)],
+            ..config()
+        };
+        let diagnostics = lint(&config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert!(diagnostics[0].message.contains("overlap"));
+    }
+
+    #[test]
+    fn name_collisions() {
+        let name = |name: &str| PasswdOverride {
+            name: Some(name.into()),
+            ..Default::default()
+        };
+        let config = Config {
+            overrides: [
+                (Uid(1000), name("user-1001")),
+                (Uid(1005), name("alice")),
+                (Uid(1006), name("alice")),
+            ]
+            .into_iter()
+            .collect(),
+            aliases: [("user-1002".into(), Uid(1003)), ("carol".into(), Uid(1004))]
+                .into_iter()
+                .collect(),
+            ..config()
+        };
+        let diagnostics = lint(&config);
+        assert!(
+            diagnostics.iter().all(|d| d.severity == Severity::Error),
+            "{:?}",
+            diagnostics
+        );
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        // which of the two overrides named alice keeps the name is unspecified
+        let alice = |uid: libc::uid_t| {
+            format!(
+                "name \"alice\" in override for UID {} is used by another override",
+                uid
+            )
+        };
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert_eq!(
+            messages[0],
+            "name \"user-1001\" in override for UID 1000 collides with a synthesized username"
+        );
+        assert!(
+            messages[1] == alice(1005) || messages[1] == alice(1006),
+            "{:?}",
+            messages
+        );
+        assert_eq!(
+            messages[2],
+            "alias \"user-1002\" collides with a synthesized username"
+        );
+    }
+
+    #[test]
+    fn missing_shell() {
+        let config = Config {
+            shell: "/nonexistent/shell".into(),
+            ..config()
+        };
+        let diagnostics = lint(&config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].message.contains("/nonexistent/shell"));
+
+        let config = Config {
+            strict_shell: true,
+            ..config
+        };
+        assert_eq!(lint(&config)[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn large_range() {
+        let small = Config {
+            ranges: vec![Uid(1000)..=Uid(50_999)],
+            ..config()
+        };
+        assert_eq!(lint(&small), []);
+
+        // loads, but is slow to enumerate
+        let config = Config {
+            ranges: vec![Uid(1000)..=Uid(500_999)],
+            ..config()
+        };
+        let diagnostics = lint(&config);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].message.contains("enumerable"));
+
+        let config = Config {
+            enumerable: false,
+            ..config
+        };
+        assert!(!lint(&config)
+            .iter()
+            .any(|d| d.message.contains("enumerable")));
+    }
+
+    #[test]
+    fn oversized_lines() {
+        let config = Config {
+            home_template: format!("/home/{}", "a".repeat(2000)),
+            ..config()
+        };
+        // an error with the limit, and a warning without it
+        assert_eq!(lint(&config)[0].severity, Severity::Error);
+        let config = Config {
+            max_line_len: 0,
+            ..config
+        };
+        let diagnostics = lint(&config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].message.contains("UID 1999"));
+    }
+
+    #[test]
+    fn ineffective_exclusion() {
+        let config = Config {
+            exclude_uids: vec![Uid(1500), Uid(5000)].into(),
+            ..config()
+        };
+        assert_eq!(
+            lint(&config),
+            [Diagnostic::warning(
+                "excluded UID 5000 is not within any range, so excluding it has no effect"
+            )]
+        );
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let config = Config {
+            unknown_fields: vec!["shel".into()],
+            ..config()
+        };
+        assert_eq!(
+            lint(&config),
+            [Diagnostic::warning("unknown field \"shel\" is ignored")]
+        );
+    }
+
+    #[test]
+    fn shadowed_real_users() {
+        let real = FakePasswdDb(vec![(1005, "alice"), (1, "user-1010")]);
+        assert_eq!(
+            config().lint_with(&real),
+            [Diagnostic::warning(
+                "2 synthesized users, such as user-1005 (1005), shadow real users; consider setting defer_to_real"
+            )]
+        );
+        let real = FakePasswdDb(vec![(1005, "alice")]);
+        assert_eq!(
+            config().lint_with(&real),
+            [Diagnostic::warning(
+                "synthesized user user-1005 (1005) shadows a real user; consider setting defer_to_real"
+            )]
+        );
+
+        // the module answering for itself, as it does once installed
+        let real = FakePasswdDb(vec![(1000, "user-1000"), (1001, "user-1001")]);
+        assert_eq!(config().lint_with(&real), []);
+
+        // only the first users of a large range are checked
+        let large = Config {
+            ranges: vec![Uid(1000)..=Uid(9999)],
+            ..config()
+        };
+        let real = FakePasswdDb(vec![(1005, "alice"), (5000, "bob")]);
+        assert_eq!(
+            large.lint_with(&real),
+            [Diagnostic::warning(
+                "synthesized user user-1005 (1005) shadows a real user among the first 1000 checked; consider setting defer_to_real"
+            )]
+        );
+
+        let config = Config {
+            defer_to_real: true,
+            ..config()
+        };
+        assert_eq!(config.lint_with(&real), []);
+    }
+
+    #[test]
+    fn diagnostic_display() {
+        assert_eq!(
+            Diagnostic::error("ranges overlap").to_string(),
+            "error: ranges overlap"
+        );
+        assert_eq!(
+            Diagnostic::warning("shell is missing").to_string(),
+            "warning: shell is missing"
+        );
+    }
+}
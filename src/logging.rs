@@ -0,0 +1,33 @@
+//! Optional log backends for the module
+//!
+//! Lookups are always logged through the `log` facade, which is a no-op
+//! unless a logger is installed. Since the module runs inside arbitrary
+//! processes, it only installs a logger of its own when built with the
+//! `env_logger` or `syslog` feature; otherwise records go to whatever logger
+//! the host process installed, if any.
+
+/// Install the logger selected by the enabled features, if any
+///
+/// Does nothing if the host process already installed a logger. The
+/// `env_logger` filter is read from `NSS_LYING_LOG` with `lookup`, so that it
+/// is ignored in secure-execution mode like the other environment variables.
+#[cfg_attr(not(feature = "env_logger"), allow(unused_variables))]
+pub fn init(lookup: impl Fn(&str) -> Option<String>) {
+    #[cfg(feature = "env_logger")]
+    {
+        let mut builder = env_logger::Builder::new();
+        if let Some(filters) = lookup("NSS_LYING_LOG") {
+            builder.parse_filters(&filters);
+        }
+        let _ = builder.try_init();
+    }
+
+    #[cfg(all(feature = "syslog", not(feature = "env_logger")))]
+    {
+        let _ = syslog::init(
+            syslog::Facility::LOG_AUTH,
+            log::LevelFilter::Info,
+            Some("nss_lying"),
+        );
+    }
+}
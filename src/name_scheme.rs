@@ -0,0 +1,275 @@
+//! Mapping between UIDs and generated usernames
+//!
+//! [Config](crate::Config) generates names with a [PrefixScheme] built from
+//! its `name_*` fields, unless a custom [NameScheme] is set through
+//! [Config::name_scheme](crate::Config::name_scheme).
+
+use std::{borrow::Cow, fmt, sync::Arc};
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::Uid;
+
+/// A bijection between UIDs and the usernames generated for them
+///
+/// Schemes only do the formatting and parsing: which UIDs are synthesized,
+/// overrides, and case-insensitive lookups of overridden names are still
+/// handled by [Config](crate::Config). For names and IDs to stay in
+/// bijection, `uid` must accept exactly the names returned by `name`.
+pub trait NameScheme: Send + Sync {
+    /// Generate the name of a UID, or [None] if the scheme can't name it
+    fn name(&self, uid: Uid) -> Option<String>;
+
+    /// Parse a name generated by [NameScheme::name] back into its UID
+    fn uid(&self, name: &str) -> Option<Uid>;
+
+    /// Check whether a name has the form of a generated one, even if
+    /// [NameScheme::uid] rejects it, e.g. because of extra leading zeros
+    ///
+    /// Only used to tell lookups of malformed names from those of foreign
+    /// ones.
+    fn is_name_form(&self, name: &str) -> bool {
+        self.uid(name).is_some()
+    }
+}
+
+/// The default [NameScheme]: a prefix followed by the UID
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixScheme<'a> {
+    /// Prefix of every name, which may be empty
+    pub prefix: Cow<'a, str>,
+    /// Minimum width of the UID, padded with leading zeros
+    pub pad_width: usize,
+    /// Base of the UID: 8, 10, or 16. Names can't be parsed in any other base.
+    pub radix: u32,
+    /// Whether to also parse names with the prefix and digits in another case
+    pub case_insensitive: bool,
+}
+
+impl<'a> PrefixScheme<'a> {
+    /// Create a scheme of decimal UIDs after `prefix`, e.g. `user-1000`
+    pub fn new(prefix: impl Into<Cow<'a, str>>) -> Self {
+        PrefixScheme {
+            prefix: prefix.into(),
+            pad_width: 0,
+            radix: 10,
+            case_insensitive: false,
+        }
+    }
+
+    /// Strip the prefix from a name
+    fn strip_prefix<'n>(&self, name: &'n str) -> Option<&'n str> {
+        let len = self.prefix.len();
+        let prefix = name.get(..len)?;
+        let matches = if self.case_insensitive {
+            prefix.eq_ignore_ascii_case(&self.prefix)
+        } else {
+            prefix == self.prefix
+        };
+        matches.then(|| &name[len..])
+    }
+
+    /// Check whether the suffix of a name is made of digits in the radix
+    fn is_digits(&self, suffix: &str) -> bool {
+        // `from_str_radix` also accepts a leading `+` and uppercase digits,
+        // which would give several names for the same UID
+        let canonical_digit = |b: u8| {
+            char::from(b).is_digit(self.radix) && (!b.is_ascii_uppercase() || self.case_insensitive)
+        };
+        !suffix.is_empty() && suffix.bytes().all(canonical_digit)
+    }
+}
+
+impl NameScheme for PrefixScheme<'_> {
+    fn name(&self, uid: Uid) -> Option<String> {
+        let width = self.pad_width;
+        Some(match self.radix {
+            8 => format!("{}{:0width$o}", self.prefix, uid.0),
+            16 => format!("{}{:0width$x}", self.prefix, uid.0),
+            _ => format!("{}{:0width$}", self.prefix, uid.0),
+        })
+    }
+
+    fn uid(&self, name: &str) -> Option<Uid> {
+        let suffix = self.strip_prefix(name)?;
+        // other radixes are rejected by `Config::validate`, and would panic
+        // below
+        if ![8, 10, 16].contains(&self.radix) {
+            return None;
+        }
+        if !self.is_digits(suffix) {
+            return None;
+        }
+        let uid = libc::uid_t::from_str_radix(suffix, self.radix).ok()?;
+        // likewise, only accept leading zeros that pad to the configured
+        // width
+        let digits = uid.checked_ilog(self.radix).unwrap_or(0) as usize + 1;
+        if suffix.len() != digits.max(self.pad_width) {
+            return None;
+        }
+        Some(Uid(uid))
+    }
+
+    fn is_name_form(&self, name: &str) -> bool {
+        self.strip_prefix(name)
+            .is_some_and(|suffix| self.is_digits(suffix))
+    }
+}
+
+/// A custom [NameScheme] set on a [Config](crate::Config)
+///
+/// Two configs only compare equal if they share the same scheme instance.
+#[derive(Clone)]
+pub struct CustomNameScheme(pub Arc<dyn NameScheme>);
+
+impl CustomNameScheme {
+    /// Wrap a scheme to set it on a config
+    pub fn new(scheme: impl NameScheme + 'static) -> Self {
+        CustomNameScheme(Arc::new(scheme))
+    }
+}
+
+impl fmt::Debug for CustomNameScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomNameScheme(..)")
+    }
+}
+
+impl PartialEq for CustomNameScheme {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomNameScheme {}
+
+/// A regular expression that names must match as a whole, see
+/// [Config::name_pattern](crate::Config::name_pattern)
+///
+/// The expression is compiled when the pattern is created, so an invalid one
+/// fails the config load. Patterns compare equal if their sources are equal.
+#[derive(Clone, Debug)]
+pub struct NamePattern {
+    source: String,
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Compile a pattern, anchored at both ends
+    pub fn new(source: &str) -> Result<Self, regex::Error> {
+        Ok(NamePattern {
+            source: source.into(),
+            regex: Regex::new(&format!("^(?:{})$", source))?,
+        })
+    }
+
+    /// Get the pattern as written, without the anchors
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check whether a whole name matches the pattern
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for NamePattern {}
+
+impl<'de> Deserialize<'de> for NamePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        NamePattern::new(&source)
+            .map_err(|e| de::Error::custom(format!("invalid name_pattern: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_scheme_roundtrip() {
+        let scheme = PrefixScheme::new("user-");
+        assert_eq!(scheme.name(Uid(1000)).as_deref(), Some("user-1000"));
+        assert_eq!(scheme.uid("user-1000"), Some(Uid(1000)));
+        assert_eq!(scheme.uid("user-01000"), None);
+        assert_eq!(scheme.uid("USER-1000"), None);
+        assert!(scheme.is_name_form("user-01000"));
+        assert!(!scheme.is_name_form("alice"));
+
+        let scheme = PrefixScheme {
+            pad_width: 5,
+            radix: 16,
+            case_insensitive: true,
+            ..PrefixScheme::new("svc-")
+        };
+        assert_eq!(scheme.name(Uid(1000)).as_deref(), Some("svc-003e8"));
+        assert_eq!(scheme.uid("svc-003e8"), Some(Uid(1000)));
+        assert_eq!(scheme.uid("SVC-003E8"), Some(Uid(1000)));
+        assert_eq!(scheme.uid("svc-3e8"), None);
+    }
+
+    #[test]
+    fn prefix_of_real_names() {
+        // prefixes without a separator are the start of many real names, which
+        // must only be claimed if the whole rest is a canonical UID
+        for radix in [8, 10, 16] {
+            for case_insensitive in [false, true] {
+                for prefix in ["user", "svc"] {
+                    let scheme = PrefixScheme {
+                        radix,
+                        case_insensitive,
+                        ..PrefixScheme::new(prefix)
+                    };
+                    for name in [
+                        "user",
+                        "username",
+                        "users",
+                        "user123xyz",
+                        "user 123",
+                        "user-123",
+                        "user_1",
+                        "user+123",
+                        "user123\n",
+                        "svc",
+                        "service",
+                        "svcs",
+                        "svc-backup",
+                        "svc1.2",
+                    ] {
+                        assert_eq!(scheme.uid(name), None, "{} {} {}", prefix, radix, name);
+                    }
+                    // unless the rest happens to be hex digits of a UID
+                    let hex = format!("{}123abc", prefix);
+                    let uid = (radix == 16).then_some(Uid(0x123abc));
+                    assert_eq!(scheme.uid(&hex), uid, "{} {}", radix, hex);
+
+                    let name = scheme.name(Uid(123)).unwrap();
+                    assert_eq!(scheme.uid(&name), Some(Uid(123)));
+                    assert_eq!(scheme.uid(&format!("{}x", name)), None);
+                    assert_eq!(scheme.uid(&format!("x{}", name)), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn name_pattern_anchored() {
+        let pattern = NamePattern::new("user-[0-9]{4}").unwrap();
+        assert!(pattern.is_match("user-1000"));
+        assert!(!pattern.is_match("user-1000x"));
+        assert!(!pattern.is_match("xuser-1000"));
+        // alternations are anchored as a whole
+        let pattern = NamePattern::new("a|b").unwrap();
+        assert!(!pattern.is_match("ab"));
+        assert!(NamePattern::new("user-(").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Netgroup hooks, which libnss doesn't provide
+//!
+//! The configured [Config::netgroup] lists every synthesized user as a
+//! `(-, user, -)` triple, so that e.g. `@synth-users` can be used in
+//! `access.conf`. Members are returned one at a time in UID order, keeping the
+//! last returned UID in the state glibc passes between calls instead of
+//! building the whole list up front. Like the other databases, the netgroup
+//! has no members unless [Config::enumerable] is set.
+
+#[cfg(feature = "netgroup")]
+use std::ffi::CStr;
+
+#[cfg(feature = "netgroup")]
+use libc::{c_char, c_int, c_ulong, c_void, size_t};
+#[cfg(feature = "netgroup")]
+use libnss::interop::{CBuffer, NssStatus, Response, ToC};
+
+#[cfg(feature = "netgroup")]
+use crate::{logged, resolver, with_resolver};
+use crate::{Config, Uid};
+
+/// The `(host, user, domain)` value of a netgroup entry
+#[cfg(feature = "netgroup")]
+#[repr(C)]
+struct Triple {
+    host: *const c_char,
+    user: *const c_char,
+    domain: *const c_char,
+}
+
+/// `struct __netgrent` from glibc's `netgroup.h`
+///
+/// Only the type, the value, and the module's own state are touched; the rest
+/// is owned by glibc.
+#[cfg(feature = "netgroup")]
+#[repr(C)]
+pub struct Netgrent {
+    /// `triple_val` or `group_val`
+    kind: c_int,
+    /// Union of a [Triple] and a group name, which is the smaller of the two
+    val: Triple,
+    data: *mut c_char,
+    data_size: size_t,
+    /// Union of a cursor and a position, used as the last returned UID
+    position: c_ulong,
+    /// Whether no member has been returned yet
+    first: c_int,
+    known_groups: *mut c_void,
+    needed_groups: *mut c_void,
+    nip: *mut c_void,
+}
+
+/// `triple_val` of `enum { triple_val, group_val }`
+#[cfg(feature = "netgroup")]
+const TRIPLE_VAL: c_int = 0;
+
+/// A netgroup member, written out as a `(-, user, -)` triple
+#[cfg(feature = "netgroup")]
+struct Member(String);
+
+#[cfg(feature = "netgroup")]
+impl ToC<Netgrent> for Member {
+    unsafe fn to_c(&self, result: *mut Netgrent, buffer: &mut CBuffer) -> std::io::Result<()> {
+        (*result).kind = TRIPLE_VAL;
+        (*result).val = Triple {
+            host: buffer.write_str("-")?,
+            user: buffer.write_str(&self.0)?,
+            domain: buffer.write_str("-")?,
+        };
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Get the member of a netgroup following the one for UID `after`, or
+    /// the first member if `after` is [None]
+    ///
+    /// Returns the member's UID and username, or [None] when there are no
+    /// more members or `netgroup` is not the configured netgroup. There are
+    /// no members at all unless `enumerable` is set.
+    pub fn next_netgroup_member(
+        &self,
+        netgroup: &str,
+        after: Option<Uid>,
+    ) -> Option<(Uid, String)> {
+        if self.netgroup.as_deref() != Some(netgroup) || !self.enumerable {
+            return None;
+        }
+        let from = match after {
+            None => Uid(0),
+            Some(uid) => Uid(uid.0.checked_add(1)?),
+        };
+        let uid = self.next_uid(from)?;
+        Some((uid, self.name_for_uid(uid)?))
+    }
+
+    /// Get the usernames of every member of a netgroup, in UID order
+    ///
+    /// Returns [None] when `netgroup` is not the configured netgroup.
+    pub fn netgroup_members(&self, netgroup: &str) -> Option<Vec<String>> {
+        if self.netgroup.as_deref() != Some(netgroup) {
+            return None;
+        }
+        let mut members = Vec::with_capacity(self.uid_range_len());
+        let mut after = None;
+        while let Some((uid, name)) = self.next_netgroup_member(netgroup, after) {
+            members.push(name);
+            after = Some(uid);
+        }
+        Some(members)
+    }
+}
+
+/// Start enumerating a netgroup
+///
+/// # Safety
+///
+/// Called by glibc with a valid group name and state.
+#[cfg(feature = "netgroup")]
+#[no_mangle]
+pub unsafe extern "C" fn _nss_lying_setnetgrent(
+    group: *const c_char,
+    result: *mut Netgrent,
+) -> c_int {
+    if group.is_null() || result.is_null() {
+        return Response::<()>::NotFound.to_status() as c_int;
+    }
+    let group = CStr::from_ptr(group).to_string_lossy();
+    let response = logged(
+        "netgroup::setnetgrent",
+        &group,
+        with_resolver(resolver(), |resolver| {
+            if resolver.config().netgroup.as_deref() == Some(&*group) {
+                Response::Success(())
+            } else {
+                Response::NotFound
+            }
+        }),
+    );
+    if let Response::Success(()) = response {
+        (*result).first = 1;
+        (*result).position = 0;
+    }
+    response.to_status() as c_int
+}
+
+/// Get the next member of the netgroup started by [_nss_lying_setnetgrent]
+///
+/// # Safety
+///
+/// Called by glibc with a valid state and a buffer of `buflen` bytes.
+#[cfg(feature = "netgroup")]
+#[no_mangle]
+pub unsafe extern "C" fn _nss_lying_getnetgrent_r(
+    result: *mut Netgrent,
+    buffer: *mut c_char,
+    buflen: size_t,
+    errnop: *mut c_int,
+) -> c_int {
+    let after = match (*result).first {
+        0 => libc::uid_t::try_from((*result).position).ok().map(Uid),
+        _ => None,
+    };
+    let response = logged(
+        "netgroup::getnetgrent_r",
+        &(*result).position,
+        with_resolver(resolver(), |resolver| {
+            let config = resolver.config();
+            let netgroup = config.netgroup.as_deref().unwrap_or_default();
+            // `Return` marks the end of the netgroup
+            config
+                .next_netgroup_member(netgroup, after)
+                .map_or(Response::Return, Response::Success)
+        }),
+    );
+    let Response::Success((uid, name)) = response else {
+        return response.to_status() as c_int;
+    };
+    let status = Response::Success(Member(name)).to_c(result, buffer, buflen, errnop);
+    // only advance once the member was written, so that glibc can retry with
+    // a larger buffer on ERANGE
+    if status == NssStatus::Success {
+        (*result).first = 0;
+        (*result).position = c_ulong::from(uid.0);
+    }
+    status as c_int
+}
+
+/// Stop enumerating a netgroup
+///
+/// # Safety
+///
+/// Called by glibc with a valid state.
+#[cfg(feature = "netgroup")]
+#[no_mangle]
+pub unsafe extern "C" fn _nss_lying_endnetgrent(_result: *mut Netgrent) -> c_int {
+    Response::Success(()).to_status() as c_int
+}
@@ -0,0 +1,281 @@
+//! Lookups against a [Config], independent of NSS
+
+use std::{fmt, sync::Arc};
+
+use arc_swap::{ArcSwap, Guard};
+
+use libnss::{group::Group, passwd::Passwd, shadow::Shadow};
+use log::warn;
+
+use crate::{
+    cache::{Key, LookupCache},
+    Config, Gid, GroupShadow, PasswdDb, SystemPasswd, Uid,
+};
+
+/// Answers the lookups of the NSS hooks for a given [Config]
+///
+/// The hooks are thin adapters around a resolver for the global config; a
+/// resolver can just as well be built from any other config, e.g. to embed
+/// the mapping in another service or to test it.
+///
+/// ```
+/// use nss_lying::{Config, Resolver, Uid};
+///
+/// let resolver = Resolver::from(Config::default());
+/// assert_eq!(resolver.passwd_by_uid(Uid(1234)).unwrap().name, "user-1234");
+/// assert!(resolver.group_by_name("users").is_none());
+/// ```
+#[derive(Clone)]
+pub struct Resolver {
+    /// Shared with clones, so that a reload applies to all of them
+    config: Arc<ArcSwap<Config>>,
+    cache: Option<Arc<LookupCache>>,
+    /// Database checked for `defer_to_real`, or [None] for [SystemPasswd]
+    real: Option<Arc<dyn PasswdDb>>,
+    /// Whether the config may be replaced by a reload in progress
+    reloading: bool,
+}
+
+impl Resolver {
+    /// Create a resolver for a config
+    pub fn new(config: Arc<Config>) -> Self {
+        Resolver {
+            config: Arc::new(ArcSwap::new(config)),
+            cache: None,
+            real: None,
+            reloading: false,
+        }
+    }
+
+    /// Cache passwd and group lookups by UID, GID, and name in `cache`
+    ///
+    /// The cache must only be shared between resolvers for the same config.
+    pub fn with_cache(self, cache: Arc<LookupCache>) -> Self {
+        Resolver {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Check `real` instead of the system's passwd database for
+    /// `defer_to_real`
+    pub fn with_real_db(self, real: Arc<dyn PasswdDb>) -> Self {
+        Resolver {
+            real: Some(real),
+            ..self
+        }
+    }
+
+    /// Mark the config as possibly about to be replaced by a reload in
+    /// progress
+    ///
+    /// The hooks then answer misses with `TryAgain` rather than `NotFound`,
+    /// since the new config may synthesize the entry.
+    pub fn with_reloading(self, reloading: bool) -> Self {
+        Resolver { reloading, ..self }
+    }
+
+    /// Check whether the config may be replaced by a reload in progress
+    pub fn is_reloading(&self) -> bool {
+        self.reloading
+    }
+
+    /// Get the config used for lookups
+    ///
+    /// The guard holds on to the config, so it stays consistent even if the
+    /// resolver is reloaded meanwhile. Prefer [Resolver::current] to keep
+    /// the config around for long.
+    pub fn config(&self) -> Guard<Arc<Config>> {
+        self.config.load()
+    }
+
+    /// Get the config used for lookups, like [Resolver::config]
+    pub fn current(&self) -> Arc<Config> {
+        self.config.load_full()
+    }
+
+    /// Replace the config used for lookups, returning the previous one
+    ///
+    /// The swap is atomic: each lookup, including those already in progress
+    /// on other threads, uses either the previous config or the new one
+    /// throughout. The swap applies to every clone of this resolver, and
+    /// clears the cache, if any.
+    pub fn reload(&self, new: Config) -> Arc<Config> {
+        let capacity = new.cache_capacity;
+        let previous = self.config.swap(Arc::new(new));
+        if let Some(cache) = &self.cache {
+            cache.reset(capacity);
+        }
+        previous
+    }
+
+    /// Look up a passwd entry by UID
+    ///
+    /// Creates the user's home directory if `create_home` is set.
+    pub fn passwd_by_uid(&self, uid: Uid) -> Option<Passwd> {
+        let generation = self.generation();
+        let config = self.config();
+        let lookup = || config.uid_to_passwd(uid);
+        let passwd = match &self.cache {
+            Some(cache) => cache
+                .passwd(generation, Key::Id(uid.into()), lookup)
+                .map(Arc::unwrap_or_clone),
+            None => lookup(),
+        }
+        .filter(|passwd| !self.is_real(&config, passwd))?;
+        config.ensure_home(&passwd);
+        Some(passwd)
+    }
+
+    /// Look up a passwd entry by username
+    ///
+    /// Creates the user's home directory if `create_home` is set.
+    pub fn passwd_by_name(&self, name: &str) -> Option<Passwd> {
+        let generation = self.generation();
+        let config = self.config();
+        let lookup = || config.passwd_by_name(name);
+        let passwd = match &self.cache {
+            Some(cache) => cache
+                .passwd(generation, Key::Name(name.into()), lookup)
+                .map(Arc::unwrap_or_clone),
+            None => lookup(),
+        }
+        .filter(|passwd| !self.is_real(&config, passwd))?;
+        config.ensure_home(&passwd);
+        Some(passwd)
+    }
+
+    /// Get every passwd entry
+    ///
+    /// Like the other enumerations, this is empty unless `enumerable` is set.
+    pub fn all_passwd(&self) -> Vec<Passwd> {
+        self.enumerate(|config| config.passwd_entries())
+    }
+
+    /// Look up a group entry by GID
+    pub fn group_by_gid(&self, gid: Gid) -> Option<Group> {
+        let generation = self.generation();
+        let config = self.config();
+        let lookup = || config.gid_to_group(gid);
+        match &self.cache {
+            Some(cache) => cache
+                .group(generation, Key::Id(gid.into()), lookup)
+                .map(Arc::unwrap_or_clone),
+            None => lookup(),
+        }
+    }
+
+    /// Look up a group entry by group name
+    pub fn group_by_name(&self, name: &str) -> Option<Group> {
+        let generation = self.generation();
+        let config = self.config();
+        let lookup = || config.group_by_name(name);
+        match &self.cache {
+            Some(cache) => cache
+                .group(generation, Key::Name(name.into()), lookup)
+                .map(Arc::unwrap_or_clone),
+            None => lookup(),
+        }
+    }
+
+    /// Get every group entry
+    pub fn all_group(&self) -> Vec<Group> {
+        self.enumerate(|config| config.group_entries())
+    }
+
+    /// Look up a gshadow entry by group name
+    pub fn gshadow_by_name(&self, name: &str) -> Option<GroupShadow> {
+        self.config().gshadow_by_name(name)
+    }
+
+    /// Get every gshadow entry
+    pub fn all_gshadow(&self) -> Vec<GroupShadow> {
+        self.enumerate(|config| config.gshadow_entries())
+    }
+
+    /// Get the supplementary groups of a user, for `initgroups`
+    pub fn groups_for_user(&self, name: &str) -> Vec<Group> {
+        let config = self.config();
+        if self.is_real_name(&config, name) {
+            return Vec::new();
+        }
+        config.supplementary_groups(name)
+    }
+
+    /// Look up a shadow entry by username
+    pub fn shadow_by_name(&self, name: &str) -> Option<Shadow> {
+        let config = self.config();
+        if self.is_real_name(&config, name) {
+            return None;
+        }
+        config.shadow_by_name(name)
+    }
+
+    /// Get every shadow entry
+    pub fn all_shadow(&self) -> Vec<Shadow> {
+        self.enumerate(|config| config.shadow_entries())
+    }
+
+    /// Get the generation of the cache, taken before reading the config so
+    /// that lookups racing with [Resolver::reload] aren't cached
+    fn generation(&self) -> u64 {
+        self.cache.as_ref().map_or(0, |cache| cache.generation())
+    }
+
+    /// Check whether a synthesized user exists in the real passwd database,
+    /// with `defer_to_real`
+    ///
+    /// Users the real database fails to answer for are treated as existing,
+    /// since synthesizing them might shadow a real account.
+    fn is_real(&self, config: &Config, passwd: &Passwd) -> bool {
+        if !config.defer_to_real {
+            return false;
+        }
+        let real = self.real.as_deref().unwrap_or(&SystemPasswd);
+        let exists = real
+            .has_uid(Uid(passwd.uid))
+            .and_then(|exists| Ok(exists || real.has_name(&passwd.name)?));
+        exists.unwrap_or_else(|e| {
+            warn!(
+                "failed to check for a real user {}, not synthesizing it: {}",
+                passwd.name, e
+            );
+            true
+        })
+    }
+
+    /// Like [Resolver::is_real], for the synthesized user of a name
+    fn is_real_name(&self, config: &Config, name: &str) -> bool {
+        config.defer_to_real
+            && config
+                .passwd_by_name(name)
+                .is_some_and(|passwd| self.is_real(config, &passwd))
+    }
+
+    /// Enumerate entries with `entries`, unless enumeration is disabled
+    ///
+    /// `entries` already lists them in `enumeration_order`.
+    fn enumerate<T>(&self, entries: impl FnOnce(&Config) -> Vec<T>) -> Vec<T> {
+        let config = self.config();
+        if !config.enumerable {
+            return Vec::new();
+        }
+        entries(&config)
+    }
+}
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolver")
+            .field("config", &self.config())
+            .field("cache", &self.cache)
+            .field("reloading", &self.reloading)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<Config> for Resolver {
+    fn from(config: Config) -> Self {
+        Resolver::new(Arc::new(config))
+    }
+}
@@ -0,0 +1,54 @@
+//! Comparable copies of synthesized entries, for tests
+//!
+//! libnss's [Passwd] and [Group] implement neither `PartialEq` nor `Debug`,
+//! so tests would otherwise compare entries one field at a time, and tend to
+//! check only the fields they are about. Converting an entry into a snapshot
+//! lets a test assert all of it at once.
+
+use libnss::{group::Group, passwd::Passwd};
+
+/// All fields of a [Passwd]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PasswdSnapshot {
+    pub name: String,
+    pub passwd: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub gecos: String,
+    pub dir: String,
+    pub shell: String,
+}
+
+impl From<&Passwd> for PasswdSnapshot {
+    fn from(passwd: &Passwd) -> Self {
+        PasswdSnapshot {
+            name: passwd.name.clone(),
+            passwd: passwd.passwd.clone(),
+            uid: passwd.uid,
+            gid: passwd.gid,
+            gecos: passwd.gecos.clone(),
+            dir: passwd.dir.clone(),
+            shell: passwd.shell.clone(),
+        }
+    }
+}
+
+/// All fields of a [Group]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct GroupSnapshot {
+    pub name: String,
+    pub passwd: String,
+    pub gid: libc::gid_t,
+    pub members: Vec<String>,
+}
+
+impl From<&Group> for GroupSnapshot {
+    fn from(group: &Group) -> Self {
+        GroupSnapshot {
+            name: group.name.clone(),
+            passwd: group.passwd.clone(),
+            gid: group.gid,
+            members: group.members.clone(),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Counters of the lookups answered by the module
+//!
+//! The counters are process-wide, like the hooks themselves, and only count
+//! lookups made in the current process. Since NSS modules are loaded into
+//! every process that resolves a user, most of them short-lived, the counters
+//! are mainly useful in a long-lived process, such as a diagnostic harness
+//! that exports [Stats::to_prometheus] periodically.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use libnss::interop::Response;
+
+/// A kind of lookup that is counted, one for each hook of the module
+///
+/// The netgroup hooks are the only ones that aren't counted, since a netgroup
+/// is read one member at a time rather than found or missed by one lookup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    /// `getpwuid`
+    PasswdByUid,
+    /// `getpwnam`
+    PasswdByName,
+    /// `setpwent`, enumerating the passwd database
+    PasswdAll,
+    /// `getgrgid`
+    GroupByGid,
+    /// `getgrnam`
+    GroupByName,
+    /// `setgrent`, enumerating the group database
+    GroupAll,
+    /// `initgroups`, getting the groups of a user
+    InitgroupsByUser,
+    /// `getspnam`
+    ShadowByName,
+    /// `setspent`, enumerating the shadow database
+    ShadowAll,
+    /// `getsgnam`
+    GshadowByName,
+    /// `setsgent`, enumerating the gshadow database
+    GshadowAll,
+}
+
+impl Lookup {
+    const ALL: [Lookup; 11] = [
+        Lookup::PasswdByUid,
+        Lookup::PasswdByName,
+        Lookup::PasswdAll,
+        Lookup::GroupByGid,
+        Lookup::GroupByName,
+        Lookup::GroupAll,
+        Lookup::InitgroupsByUser,
+        Lookup::ShadowByName,
+        Lookup::ShadowAll,
+        Lookup::GshadowByName,
+        Lookup::GshadowAll,
+    ];
+
+    /// Get the database and kind of lookup, as labels for metrics
+    fn labels(self) -> (&'static str, &'static str) {
+        match self {
+            Lookup::PasswdByUid => ("passwd", "by_uid"),
+            Lookup::PasswdByName => ("passwd", "by_name"),
+            Lookup::PasswdAll => ("passwd", "all"),
+            Lookup::GroupByGid => ("group", "by_gid"),
+            Lookup::GroupByName => ("group", "by_name"),
+            Lookup::GroupAll => ("group", "all"),
+            Lookup::InitgroupsByUser => ("initgroups", "by_user"),
+            Lookup::ShadowByName => ("shadow", "by_name"),
+            Lookup::ShadowAll => ("shadow", "all"),
+            Lookup::GshadowByName => ("gshadow", "by_name"),
+            Lookup::GshadowAll => ("gshadow", "all"),
+        }
+    }
+}
+
+/// Number of lookups of one kind that found or didn't find an entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counter {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Snapshot of the lookup counters, see [stats]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub passwd_by_uid: Counter,
+    pub passwd_by_name: Counter,
+    pub passwd_all: Counter,
+    pub group_by_gid: Counter,
+    pub group_by_name: Counter,
+    pub group_all: Counter,
+    pub initgroups_by_user: Counter,
+    pub shadow_by_name: Counter,
+    pub shadow_all: Counter,
+    pub gshadow_by_name: Counter,
+    pub gshadow_all: Counter,
+}
+
+impl Stats {
+    /// Get the counter for a kind of lookup
+    pub fn get(&self, lookup: Lookup) -> Counter {
+        match lookup {
+            Lookup::PasswdByUid => self.passwd_by_uid,
+            Lookup::PasswdByName => self.passwd_by_name,
+            Lookup::PasswdAll => self.passwd_all,
+            Lookup::GroupByGid => self.group_by_gid,
+            Lookup::GroupByName => self.group_by_name,
+            Lookup::GroupAll => self.group_all,
+            Lookup::InitgroupsByUser => self.initgroups_by_user,
+            Lookup::ShadowByName => self.shadow_by_name,
+            Lookup::ShadowAll => self.shadow_all,
+            Lookup::GshadowByName => self.gshadow_by_name,
+            Lookup::GshadowAll => self.gshadow_all,
+        }
+    }
+
+    fn get_mut(&mut self, lookup: Lookup) -> &mut Counter {
+        match lookup {
+            Lookup::PasswdByUid => &mut self.passwd_by_uid,
+            Lookup::PasswdByName => &mut self.passwd_by_name,
+            Lookup::PasswdAll => &mut self.passwd_all,
+            Lookup::GroupByGid => &mut self.group_by_gid,
+            Lookup::GroupByName => &mut self.group_by_name,
+            Lookup::GroupAll => &mut self.group_all,
+            Lookup::InitgroupsByUser => &mut self.initgroups_by_user,
+            Lookup::ShadowByName => &mut self.shadow_by_name,
+            Lookup::ShadowAll => &mut self.shadow_all,
+            Lookup::GshadowByName => &mut self.gshadow_by_name,
+            Lookup::GshadowAll => &mut self.gshadow_all,
+        }
+    }
+
+    /// Render the counters in the Prometheus text exposition format, as the
+    /// `nss_lying_lookups_total` counter labeled by `db`, `kind`, and
+    /// `result`
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::from(
+            "# HELP nss_lying_lookups_total Lookups answered by the lying NSS module in this process.\n\
+             # TYPE nss_lying_lookups_total counter\n",
+        );
+        for lookup in Lookup::ALL {
+            let (db, kind) = lookup.labels();
+            let counter = self.get(lookup);
+            for (result, value) in [("hit", counter.hits), ("miss", counter.misses)] {
+                text += &format!(
+                    "nss_lying_lookups_total{{db=\"{}\",kind=\"{}\",result=\"{}\"}} {}\n",
+                    db, kind, result, value
+                );
+            }
+        }
+        text
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for lookup in Lookup::ALL {
+            let counter = self.get(lookup);
+            writeln!(
+                f,
+                "{:?}: {} hits, {} misses",
+                lookup, counter.hits, counter.misses
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Hit and miss counters, indexed by [Lookup]
+static COUNTERS: [[AtomicU64; 2]; Lookup::ALL.len()] =
+    [const { [const { AtomicU64::new(0) }; 2] }; Lookup::ALL.len()];
+
+/// Count a lookup that did or didn't find an entry
+///
+/// Lookups through the module are counted automatically; this is for callers
+/// using [Config](crate::Config) directly that want to be counted alongside.
+pub fn record(lookup: Lookup, hit: bool) {
+    COUNTERS[lookup as usize][usize::from(!hit)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a hook's lookup by its response, passing the response through
+#[cfg_attr(not(any(feature = "passwd", feature = "group")), allow(dead_code))]
+pub(crate) fn counted<T>(lookup: Lookup, response: Response<T>) -> Response<T> {
+    record(lookup, matches!(response, Response::Success(_)));
+    response
+}
+
+/// Get a snapshot of the lookup counters
+///
+/// Each counter is read individually, so a snapshot taken while lookups are
+/// running may be slightly inconsistent between counters.
+pub fn stats() -> Stats {
+    let mut stats = Stats::default();
+    for lookup in Lookup::ALL {
+        let [hits, misses] = &COUNTERS[lookup as usize];
+        *stats.get_mut(lookup) = Counter {
+            hits: hits.load(Ordering::Relaxed),
+            misses: misses.load(Ordering::Relaxed),
+        };
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prometheus_format() {
+        let stats = Stats {
+            passwd_by_uid: Counter {
+                hits: 42,
+                misses: 3,
+            },
+            group_all: Counter { hits: 1, misses: 0 },
+            ..Stats::default()
+        };
+        assert_eq!(
+            stats.to_prometheus(),
+            r#"# HELP nss_lying_lookups_total Lookups answered by the lying NSS module in this process.
+# TYPE nss_lying_lookups_total counter
+nss_lying_lookups_total{db="passwd",kind="by_uid",result="hit"} 42
+nss_lying_lookups_total{db="passwd",kind="by_uid",result="miss"} 3
+nss_lying_lookups_total{db="passwd",kind="by_name",result="hit"} 0
+nss_lying_lookups_total{db="passwd",kind="by_name",result="miss"} 0
+nss_lying_lookups_total{db="passwd",kind="all",result="hit"} 0
+nss_lying_lookups_total{db="passwd",kind="all",result="miss"} 0
+nss_lying_lookups_total{db="group",kind="by_gid",result="hit"} 0
+nss_lying_lookups_total{db="group",kind="by_gid",result="miss"} 0
+nss_lying_lookups_total{db="group",kind="by_name",result="hit"} 0
+nss_lying_lookups_total{db="group",kind="by_name",result="miss"} 0
+nss_lying_lookups_total{db="group",kind="all",result="hit"} 1
+nss_lying_lookups_total{db="group",kind="all",result="miss"} 0
+nss_lying_lookups_total{db="initgroups",kind="by_user",result="hit"} 0
+nss_lying_lookups_total{db="initgroups",kind="by_user",result="miss"} 0
+nss_lying_lookups_total{db="shadow",kind="by_name",result="hit"} 0
+nss_lying_lookups_total{db="shadow",kind="by_name",result="miss"} 0
+nss_lying_lookups_total{db="shadow",kind="all",result="hit"} 0
+nss_lying_lookups_total{db="shadow",kind="all",result="miss"} 0
+nss_lying_lookups_total{db="gshadow",kind="by_name",result="hit"} 0
+nss_lying_lookups_total{db="gshadow",kind="by_name",result="miss"} 0
+nss_lying_lookups_total{db="gshadow",kind="all",result="hit"} 0
+nss_lying_lookups_total{db="gshadow",kind="all",result="miss"} 0
+"#
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Lookups against the system's passwd and group databases, for config values
+//! given by name and for `defer_to_real`
+//!
+//! These go through NSS, and so possibly through this module itself, either
+//! while its config is being loaded or from inside one of its own hooks.
+//! [config](crate::config) is not reentrant, and a hook asking itself whether
+//! a user exists would recurse forever, so the module answers `Unavail` to
+//! every lookup made while a system lookup is in progress on the same thread.
+//! NSS then moves on to the next module, so the system lookup sees exactly
+//! the entries of every other source.
+
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString},
+    io,
+    mem::MaybeUninit,
+    ptr,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::Uid;
+
+use crate::Gid;
+
+thread_local! {
+    static IN_LOOKUP: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Check whether a system lookup is in progress on this thread
+pub fn in_lookup() -> bool {
+    IN_LOOKUP.with(Cell::get)
+}
+
+/// Marks a system lookup as in progress until dropped
+struct LookupGuard;
+
+impl LookupGuard {
+    fn new() -> Self {
+        IN_LOOKUP.with(|in_lookup| in_lookup.set(true));
+        LookupGuard
+    }
+}
+
+impl Drop for LookupGuard {
+    fn drop(&mut self) {
+        IN_LOOKUP.with(|in_lookup| in_lookup.set(false));
+    }
+}
+
+/// Resolve a group name to its GID with `getgrnam_r`
+pub fn gid_for_group_name(name: &str) -> Result<Gid> {
+    let c_name = CString::new(name).with_context(|| format!("invalid group name {:?}", name))?;
+    let _guard = LookupGuard::new();
+    let mut group = MaybeUninit::<libc::group>::uninit();
+    let mut result = ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: every pointer is valid for the duration of the call, and
+        // `buffer.len()` is the size of `buffer`
+        let err = unsafe {
+            libc::getgrnam_r(
+                c_name.as_ptr(),
+                group.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        match err {
+            0 if result.is_null() => bail!("group {:?} does not exist", name),
+            // SAFETY: `getgrnam_r` succeeded and pointed `result` at `group`
+            0 => return Ok(Gid(unsafe { group.assume_init() }.gr_gid)),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            err => {
+                return Err(std::io::Error::from_raw_os_error(err))
+                    .with_context(|| format!("failed to look up group {:?}", name))
+            }
+        }
+    }
+}
+
+/// The passwd entries of the other sources in `nsswitch.conf`, consulted by
+/// [Resolver](crate::Resolver) for `defer_to_real`
+pub trait PasswdDb: Send + Sync {
+    /// Get the name of the user with this UID, if one exists
+    fn name_for_uid(&self, uid: Uid) -> io::Result<Option<String>>;
+
+    /// Get the UID of the user with this name, if one exists
+    fn uid_for_name(&self, name: &str) -> io::Result<Option<Uid>>;
+
+    /// Check whether a user with this UID exists
+    fn has_uid(&self, uid: Uid) -> io::Result<bool> {
+        Ok(self.name_for_uid(uid)?.is_some())
+    }
+
+    /// Check whether a user with this name exists
+    fn has_name(&self, name: &str) -> io::Result<bool> {
+        Ok(self.uid_for_name(name)?.is_some())
+    }
+}
+
+/// The system's passwd database, looked up with `getpwuid_r` and
+/// `getpwnam_r` while this module answers `Unavail`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemPasswd;
+
+impl PasswdDb for SystemPasswd {
+    fn name_for_uid(&self, uid: Uid) -> io::Result<Option<String>> {
+        // SAFETY: the arguments are passed through from `passwd_lookup`
+        passwd_lookup(
+            |passwd, buffer, len, result| unsafe {
+                libc::getpwuid_r(uid.0, passwd, buffer, len, result)
+            },
+            // SAFETY: a found entry has a valid name
+            |passwd| {
+                unsafe { CStr::from_ptr(passwd.pw_name) }
+                    .to_string_lossy()
+                    .into_owned()
+            },
+        )
+    }
+
+    fn uid_for_name(&self, name: &str) -> io::Result<Option<Uid>> {
+        // names with a NUL can't exist
+        let Ok(c_name) = CString::new(name) else {
+            return Ok(None);
+        };
+        // SAFETY: as above, and `c_name` is a valid C string
+        passwd_lookup(
+            |passwd, buffer, len, result| unsafe {
+                libc::getpwnam_r(c_name.as_ptr(), passwd, buffer, len, result)
+            },
+            |passwd| Uid(passwd.pw_uid),
+        )
+    }
+}
+
+/// Run a `getpw*_r` lookup, growing the buffer as needed, and read the
+/// found user, if any, with `read`
+fn passwd_lookup<T>(
+    mut lookup: impl FnMut(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+    read: impl FnOnce(&libc::passwd) -> T,
+) -> io::Result<Option<T>> {
+    let _guard = LookupGuard::new();
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut result = ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        match lookup(
+            passwd.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        ) {
+            0 if result.is_null() => return Ok(None),
+            // SAFETY: the lookup succeeded and pointed `result` at `passwd`,
+            // whose strings live in `buffer`
+            0 => return Ok(Some(read(unsafe { passwd.assume_init_ref() }))),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_group() {
+        assert!(gid_for_group_name("nss-lying-no-such-group").is_err());
+        assert!(gid_for_group_name("nul\0group").is_err());
+        assert!(!in_lookup());
+    }
+
+    #[test]
+    fn root_group() {
+        // only meaningful where the system has a root group
+        if !std::fs::read_to_string("/etc/group").is_ok_and(|group| group.starts_with("root:")) {
+            return;
+        }
+        assert_eq!(gid_for_group_name("root").unwrap(), Gid(0));
+        assert!(!in_lookup());
+    }
+
+    #[test]
+    fn system_passwd() {
+        assert!(!SystemPasswd.has_name("nss-lying-no-such-user").unwrap());
+        assert!(!SystemPasswd.has_name("nul\0user").unwrap());
+        assert!(!in_lookup());
+        // only meaningful where the system has a root user
+        if !std::fs::read_to_string("/etc/passwd").is_ok_and(|passwd| passwd.starts_with("root:")) {
+            return;
+        }
+        assert!(SystemPasswd.has_uid(Uid(0)).unwrap());
+        assert!(SystemPasswd.has_name("root").unwrap());
+        assert_eq!(
+            SystemPasswd.name_for_uid(Uid(0)).unwrap().as_deref(),
+            Some("root")
+        );
+        assert_eq!(SystemPasswd.uid_for_name("root").unwrap(), Some(Uid(0)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Minimal `{placeholder}` templates used for synthesized fields
+
+use std::fmt::{Display, Write};
+
+use anyhow::{bail, Result};
+
+/// A piece of a parsed template
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split a template into literal text and placeholders
+///
+/// Returns [None] for a `{` without a matching `}`.
+fn segments(template: &str) -> impl Iterator<Item = Option<Segment<'_>>> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        match rest.find('{') {
+            Some(0) => {
+                let Some(end) = rest.find('}') else {
+                    rest = "";
+                    return Some(None);
+                };
+                let placeholder = &rest[1..end];
+                rest = &rest[end + 1..];
+                Some(Some(Segment::Placeholder(placeholder)))
+            }
+            Some(start) => {
+                let literal = &rest[..start];
+                rest = &rest[start..];
+                Some(Some(Segment::Literal(literal)))
+            }
+            None => {
+                let literal = rest;
+                rest = "";
+                Some(Some(Segment::Literal(literal)))
+            }
+        }
+    })
+}
+
+/// Check that `template` is well-formed and only uses `placeholders`
+pub fn validate(template: &str, placeholders: &[&str]) -> Result<()> {
+    for segment in segments(template) {
+        match segment {
+            None => bail!("unterminated placeholder in template {:?}", template),
+            Some(Segment::Placeholder(name)) if !placeholders.contains(&name) => {
+                bail!(
+                    "unknown placeholder {{{}}} in template {:?} (expected one of: {})",
+                    name,
+                    template,
+                    placeholders
+                        .iter()
+                        .map(|p| format!("{{{}}}", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Expand the placeholders in `template` with the given values
+///
+/// Placeholders without a value are left literally. Templates are expected to
+/// have been checked with [validate] when the config was loaded.
+pub fn expand(template: &str, values: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    for segment in segments(template) {
+        match segment {
+            Some(Segment::Literal(literal)) => out.push_str(literal),
+            Some(Segment::Placeholder(name)) => {
+                match values.iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => {
+                        // writing to a String cannot fail
+                        let _ = write!(out, "{}", value);
+                    }
+                    None => {
+                        out.push('{');
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_placeholders() {
+        assert_eq!(
+            expand(
+                "/home/{name}/{uid}",
+                &[("uid", &1000), ("name", &"user-1000")]
+            ),
+            "/home/user-1000/1000"
+        );
+        assert_eq!(expand("/tmp", &[("uid", &1000)]), "/tmp");
+        assert_eq!(expand("{uid}{uid}", &[("uid", &7)]), "77");
+    }
+
+    #[test]
+    fn expand_unknown_placeholder() {
+        assert_eq!(expand("/home/{foo}", &[("uid", &1000)]), "/home/{foo}");
+    }
+
+    #[test]
+    fn validate_templates() {
+        assert!(validate("/home/{name}", &["uid", "name"]).is_ok());
+        assert!(validate("/tmp", &["uid", "name"]).is_ok());
+        assert!(validate("/home/{foo}", &["uid", "name"]).is_err());
+        assert!(validate("/home/{name", &["uid", "name"]).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Word lists and formatting for human-looking synthesized display names
+//!
+//! The lists are embedded and must not change, not even by appending, since
+//! the same UID is expected to get the same name on every host and from every
+//! version. Their lengths are coprime, so consecutive UIDs cycle through
+//! every adjective and noun pair before repeating one.
+
+use std::fmt::{self, Write};
+
+use crate::Uid;
+
+/// Adjectives, the first word of a name
+const ADJECTIVES: &[&str] = &[
+    "Amber", "Bold", "Brave", "Bright", "Calm", "Clever", "Cosmic", "Curious", "Daring", "Eager",
+    "Fancy", "Gentle", "Golden", "Happy", "Humble", "Jolly", "Keen", "Kind", "Lively", "Lucky",
+    "Mellow", "Merry", "Nimble", "Noble", "Plucky", "Quiet", "Rapid", "Silver", "Steady", "Sunny",
+    "Swift", "Witty",
+];
+
+/// Nouns, the second word of a name
+const NOUNS: &[&str] = &[
+    "Badger", "Beaver", "Bison", "Crane", "Dolphin", "Eagle", "Falcon", "Ferret", "Fox", "Gecko",
+    "Heron", "Ibis", "Jackal", "Koala", "Lemur", "Lynx", "Marmot", "Moose", "Newt", "Ocelot",
+    "Otter", "Owl", "Panda", "Puffin", "Quokka", "Raven", "Seal", "Stoat", "Tapir", "Walrus",
+    "Yak",
+];
+
+/// Get the display name for a UID, e.g. `Daring Fox`
+///
+/// Names repeat every `ADJECTIVES.len() * NOUNS.len()` UIDs.
+pub fn display_name(uid: Uid) -> String {
+    let uid = uid.0 as usize;
+    format!(
+        "{} {}",
+        ADJECTIVES[uid % ADJECTIVES.len()],
+        NOUNS[uid % NOUNS.len()]
+    )
+}
+
+/// Get the length of the longest name [display_name] can return
+pub fn longest_display_name_len() -> usize {
+    let longest = |words: &[&str]| words.iter().map(|word| word.len()).max().unwrap_or(0);
+    longest(ADJECTIVES) + 1 + longest(NOUNS)
+}
+
+/// Characters that separate the words of a username for [TitleCase]
+const NAME_SEPARATORS: &[char] = &['-', '_', '.'];
+
+/// Displays a username as words, e.g. `user-1000` as `User 1000`
+///
+/// The name is split at `-`, `_`, and `.`, empty words are dropped, and each
+/// word gets an uppercase first letter and lowercase other letters (ASCII
+/// only). Displaying rather than returning a string avoids allocating for
+/// templates that don't use it.
+pub struct TitleCase<'a>(pub &'a str);
+
+impl fmt::Display for TitleCase<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = self
+            .0
+            .split(NAME_SEPARATORS)
+            .filter(|word| !word.is_empty());
+        for (i, word) in words.enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            for (j, c) in word.chars().enumerate() {
+                f.write_char(if j == 0 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_names() {
+        assert_eq!(display_name(Uid(0)), "Amber Badger");
+        assert_eq!(display_name(Uid(1000)), "Daring Fox");
+        assert_eq!(display_name(Uid(1000)), display_name(Uid(1000)));
+        assert_eq!(
+            display_name(Uid(1000)),
+            display_name(Uid(1000 + (ADJECTIVES.len() * NOUNS.len()) as libc::uid_t))
+        );
+    }
+
+    #[test]
+    fn longest_display_name() {
+        let cycle = (ADJECTIVES.len() * NOUNS.len()) as libc::uid_t;
+        let longest = (0..cycle).map(|uid| display_name(Uid(uid)).len()).max();
+        assert_eq!(longest, Some(longest_display_name_len()));
+    }
+
+    #[test]
+    fn title_case() {
+        let title_case = |name| TitleCase(name).to_string();
+        assert_eq!(title_case("user-1000"), "User 1000");
+        assert_eq!(title_case("user-4294967295"), "User 4294967295");
+        assert_eq!(title_case("user-7"), "User 7");
+        assert_eq!(title_case("1000"), "1000");
+        assert_eq!(title_case("SVC_build.bot-42"), "Svc Build Bot 42");
+        assert_eq!(title_case("user--3e8-"), "User 3e8");
+        assert_eq!(title_case("alice"), "Alice");
+        assert_eq!(title_case(""), "");
+    }
+
+    #[test]
+    fn words_are_gecos_safe() {
+        for word in ADJECTIVES.iter().chain(NOUNS) {
+            assert!(word.bytes().all(|b| b.is_ascii_alphabetic()), "{}", word);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Property tests that names and IDs stay in bijection across configs
+//!
+//! Each case generates a config, skipping those that fail validation, and
+//! checks both directions of the mapping for UIDs and GIDs near the range
+//! bounds and for names close to generated ones. proptest shrinks a failing
+//! case to a minimal config and records it under `proptest-regressions`, so
+//! that it is retried first on the next run.
+
+use std::ops::RangeInclusive;
+
+use nss_lying::{Config, Gid, Uid};
+use proptest::{collection::vec, prelude::*, sample::Index};
+
+/// Number of configs to generate
+const CASES: u32 = 500;
+
+/// Maximum number of IDs and names to check per config
+const SAMPLES: usize = 200;
+
+/// Patterns of names and name suffixes, built from separators and digits of
+/// every radix in both cases
+const NAME: &str = "[user\\-_.0-9a-fA-F+ ]{0,16}";
+const SUFFIX: &str = "[user\\-_.0-9a-fA-F+ ]{0,14}";
+
+fn range() -> impl Strategy<Value = RangeInclusive<Uid>> {
+    let start = prop_oneof![
+        2 => 0..2000u32,
+        1 => libc::uid_t::MAX - 5000..=libc::uid_t::MAX,
+        1 => any::<libc::uid_t>(),
+    ];
+    (start, 0..5000u32).prop_map(|(start, len)| Uid(start)..=Uid(start.saturating_add(len)))
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        vec(range(), 1..4),
+        vec(any::<libc::uid_t>().prop_map(Uid), 0..3),
+        any::<bool>(),
+        prop::sample::select(vec![0, 1000, -500, i64::from(libc::uid_t::MAX)]),
+        prop::sample::select(vec!["user-", "", "u", "u1", "USER_", "a.b-"]),
+        prop::sample::select(vec![0, 1, 5, 12]),
+        prop::sample::select(vec![8, 10, 16]),
+        any::<bool>(),
+    )
+        .prop_map(
+            |(
+                ranges,
+                exclude_uids,
+                forbid_reserved,
+                gid_offset,
+                name_prefix,
+                name_pad_width,
+                name_radix,
+                case_insensitive_names,
+            )| Config {
+                ranges,
+                exclude_uids: exclude_uids.into(),
+                forbid_reserved,
+                gid_offset,
+                name_prefix: name_prefix.into(),
+                name_pad_width,
+                name_radix,
+                case_insensitive_names,
+                max_range_size: 0,
+                ..Config::default()
+            },
+        )
+        .prop_filter("config must be valid", |config| config.validate().is_ok())
+}
+
+/// An ID near one of the interesting places of a config: its range bounds,
+/// zero, or the maximum
+#[derive(Clone, Debug)]
+struct IdSample {
+    anchor: Index,
+    delta: i64,
+}
+
+impl IdSample {
+    fn resolve(&self, config: &Config) -> libc::uid_t {
+        let mut anchors = vec![0, libc::uid_t::MAX];
+        for range in &config.ranges {
+            anchors.extend([range.start().0, range.end().0]);
+        }
+        let anchor = i64::from(*self.anchor.get(&anchors));
+        (anchor + self.delta).clamp(0, i64::from(libc::uid_t::MAX)) as libc::uid_t
+    }
+}
+
+fn id_sample() -> impl Strategy<Value = IdSample> {
+    (any::<Index>(), -32..32i64).prop_map(|(anchor, delta)| IdSample { anchor, delta })
+}
+
+/// A name likely to be close to a generated one
+#[derive(Clone, Debug)]
+enum NameSample {
+    /// The generated name of an ID, possibly in uppercase
+    Generated(IdSample, bool),
+    /// The prefix followed by arbitrary digits and separators
+    Prefixed(String),
+    /// Any name built from the same characters
+    Arbitrary(String),
+}
+
+impl NameSample {
+    fn resolve(&self, config: &Config) -> String {
+        match self {
+            NameSample::Generated(id, uppercase) => {
+                let uid = id.resolve(config);
+                let name = config
+                    .name_for_uid(Uid(uid))
+                    .unwrap_or_else(|| format!("{}{}", config.name_prefix, uid));
+                if *uppercase {
+                    name.to_ascii_uppercase()
+                } else {
+                    name
+                }
+            }
+            NameSample::Prefixed(suffix) => format!("{}{}", config.name_prefix, suffix),
+            NameSample::Arbitrary(name) => name.clone(),
+        }
+    }
+}
+
+fn name_sample() -> impl Strategy<Value = NameSample> {
+    prop_oneof![
+        2 => (id_sample(), any::<bool>())
+            .prop_map(|(id, uppercase)| NameSample::Generated(id, uppercase)),
+        1 => SUFFIX.prop_map(NameSample::Prefixed),
+        1 => NAME.prop_map(NameSample::Arbitrary),
+    ]
+}
+
+fn check_uid(config: &Config, uid: Uid) -> Result<(), TestCaseError> {
+    match config.name_for_uid(uid) {
+        Some(name) => prop_assert_eq!(
+            config.uid_from_name(&name),
+            Some(uid),
+            "name {:?} of UID {} doesn't map back",
+            name,
+            uid
+        ),
+        None => prop_assert!(!config.contains_uid(uid), "UID {}", uid),
+    }
+    Ok(())
+}
+
+fn check_gid(config: &Config, gid: Gid) -> Result<(), TestCaseError> {
+    if let Some(name) = config.name_for_gid(gid) {
+        prop_assert_eq!(
+            config.gid_from_name(&name),
+            Some(gid),
+            "name {:?} of GID {} doesn't map back",
+            name,
+            gid
+        );
+    }
+    Ok(())
+}
+
+fn check_name(config: &Config, name: &str) -> Result<(), TestCaseError> {
+    if let Some(uid) = config.uid_from_name(name) {
+        let canonical = config.name_for_uid(uid);
+        let matches = canonical.as_deref().is_some_and(|canonical| {
+            if config.case_insensitive_names {
+                canonical.eq_ignore_ascii_case(name)
+            } else {
+                canonical == name
+            }
+        });
+        prop_assert!(
+            matches,
+            "name {:?} maps to UID {} named {:?}",
+            name,
+            uid,
+            canonical
+        );
+    }
+    if let Some(gid) = config.gid_from_name(name) {
+        prop_assert!(
+            config.name_for_gid(gid).is_some(),
+            "name {:?} maps to unnamed GID {}",
+            name,
+            gid
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn names_and_ids_in_bijection(
+        config in config(),
+        ids in vec(id_sample(), 1..SAMPLES),
+        names in vec(name_sample(), 1..SAMPLES),
+    ) {
+        for id in &ids {
+            let id = id.resolve(&config);
+            check_uid(&config, Uid(id))?;
+            check_gid(&config, Gid(id))?;
+        }
+        for name in &names {
+            check_name(&config, &name.resolve(&config))?;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Load the built module with `dlopen` and call its hooks the way glibc does
+//!
+//! This checks the exported symbols and the conversion of entries into C
+//! structs, which the other tests bypass by calling into the crate directly.
+//! Building the module takes a while, so the test only runs with the
+//! `dlopen` feature:
+//!
+//! ```text
+//! cargo test --features dlopen --test dlopen
+//! ```
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    fs::File,
+    io::Write,
+    os::fd::IntoRawFd,
+    path::PathBuf,
+    process::Command,
+};
+
+use libc::{c_char, c_int, size_t};
+use nss_lying::{Config, Gid, Resolver};
+
+const CONFIG: &str = r#"
+ranges = [{ start = 1000, end = 1999 }]
+shell = "/bin/sh"
+home_template = "/home/{name}"
+gecos_style = { template = "User {uid}" }
+"#;
+
+/// `NSS_STATUS_SUCCESS`, `NSS_STATUS_NOTFOUND`, and `NSS_STATUS_TRYAGAIN`
+const SUCCESS: c_int = 1;
+const NOT_FOUND: c_int = 0;
+const TRY_AGAIN: c_int = -2;
+
+type GetPwNam = unsafe extern "C" fn(
+    *const c_char,
+    *mut libc::passwd,
+    *mut c_char,
+    size_t,
+    *mut c_int,
+) -> c_int;
+type GetPwUid =
+    unsafe extern "C" fn(libc::uid_t, *mut libc::passwd, *mut c_char, size_t, *mut c_int) -> c_int;
+type GetGrGid =
+    unsafe extern "C" fn(libc::gid_t, *mut libc::group, *mut c_char, size_t, *mut c_int) -> c_int;
+
+/// Build the module with the default features, returning its path
+fn build_module() -> PathBuf {
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dlopen");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib"])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "building the module failed");
+    target_dir.join("debug/libnss_lying.so")
+}
+
+/// Hand [CONFIG] to the module through `NSS_LYING_CONFIG_FD`, before it is
+/// first used
+fn pass_config() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dlopen.toml");
+    File::create(&path)
+        .unwrap()
+        .write_all(CONFIG.as_bytes())
+        .unwrap();
+    // left open for the module to read, and reread on reloads
+    let fd = File::open(&path).unwrap().into_raw_fd();
+    std::env::set_var("NSS_LYING_CONFIG_FD", fd.to_string());
+}
+
+/// Look up a symbol of the module, panicking if it isn't exported
+unsafe fn symbol(module: *mut c_void, name: &str) -> *mut c_void {
+    let name = CString::new(name).unwrap();
+    let symbol = libc::dlsym(module, name.as_ptr());
+    assert!(!symbol.is_null(), "{:?} is not exported", name);
+    symbol
+}
+
+unsafe fn string(ptr: *const c_char) -> String {
+    CStr::from_ptr(ptr).to_str().unwrap().to_owned()
+}
+
+/// Read a null-terminated array of strings, such as `gr_mem`
+unsafe fn strings(mut ptr: *const *mut c_char) -> Vec<String> {
+    let mut strings = Vec::new();
+    // libnss packs the array into the buffer without aligning it
+    while !ptr.read_unaligned().is_null() {
+        strings.push(string(ptr.read_unaligned()));
+        ptr = ptr.add(1);
+    }
+    strings
+}
+
+#[test]
+#[cfg_attr(not(feature = "dlopen"), ignore)]
+fn hooks_match_resolver() {
+    let path = CString::new(build_module().into_os_string().into_encoded_bytes()).unwrap();
+    pass_config();
+    let resolver = Resolver::from(CONFIG.parse::<Config>().unwrap());
+
+    unsafe {
+        let module = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        assert!(!module.is_null(), "{}", string(libc::dlerror()));
+        let getpwnam: GetPwNam = std::mem::transmute(symbol(module, "_nss_lying_getpwnam_r"));
+        let getpwuid: GetPwUid = std::mem::transmute(symbol(module, "_nss_lying_getpwuid_r"));
+        let getgrgid: GetGrGid = std::mem::transmute(symbol(module, "_nss_lying_getgrgid_r"));
+        for name in [
+            "_nss_lying_getgrnam_r",
+            "_nss_lying_initgroups_dyn",
+            "_nss_lying_getspnam_r",
+        ] {
+            symbol(module, name);
+        }
+
+        let mut buffer = vec![0 as c_char; 1024];
+        let mut errno = 0;
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let name = CString::new("user-1234").unwrap();
+        let status = getpwnam(
+            name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut errno,
+        );
+        assert_eq!(status, SUCCESS);
+        let expected = resolver.passwd_by_name("user-1234").unwrap();
+        assert_eq!(string(passwd.pw_name), expected.name);
+        assert_eq!(string(passwd.pw_passwd), expected.passwd);
+        assert_eq!(passwd.pw_uid, expected.uid);
+        assert_eq!(passwd.pw_gid, expected.gid);
+        assert_eq!(string(passwd.pw_gecos), expected.gecos);
+        assert_eq!(string(passwd.pw_dir), expected.dir);
+        assert_eq!(string(passwd.pw_shell), expected.shell);
+
+        let status = getpwuid(
+            1999,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut errno,
+        );
+        assert_eq!(status, SUCCESS);
+        assert_eq!(string(passwd.pw_name), "user-1999");
+        assert_eq!(string(passwd.pw_gecos), "User 1999");
+        let status = getpwuid(
+            2000,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut errno,
+        );
+        assert_eq!(status, NOT_FOUND);
+
+        // glibc retries with a larger buffer on ERANGE
+        let status = getpwuid(1000, &mut passwd, buffer.as_mut_ptr(), 8, &mut errno);
+        assert_eq!((status, errno), (TRY_AGAIN, libc::ERANGE));
+
+        let mut group: libc::group = std::mem::zeroed();
+        let status = getgrgid(
+            1234,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut errno,
+        );
+        assert_eq!(status, SUCCESS);
+        let expected = resolver.group_by_gid(Gid(1234)).unwrap();
+        assert_eq!(string(group.gr_name), expected.name);
+        assert_eq!(string(group.gr_passwd), expected.passwd);
+        assert_eq!(group.gr_gid, expected.gid);
+        assert_eq!(strings(group.gr_mem), expected.members);
+        let status = getgrgid(0, &mut group, buffer.as_mut_ptr(), buffer.len(), &mut errno);
+        assert_eq!(status, NOT_FOUND);
+    }
+}
@@ -0,0 +1,208 @@
+use std::{path::PathBuf, process::Command};
+
+fn dump(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_nss-lying-dump"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn dump_default_config() {
+    let (success, stdout) = dump(&["--config", "/nonexistent/nss_lying.toml"]);
+    assert!(success);
+    assert!(stdout
+        .lines()
+        .any(|line| line == "user-1000:x:1000:1000::/tmp:/bin/bash"));
+    assert!(stdout.lines().any(|line| line == "user-9999:x:9999:"));
+}
+
+#[test]
+fn dump_lookups() {
+    let config = write_config(
+        "dump_lookups.toml",
+        r#"
+        ranges = [{ start = 2000, end = 2009 }]
+        user_group = 100
+        home_template = "/home/{name}"
+        "#,
+    );
+    let config = config.to_str().unwrap();
+
+    let (success, stdout) = dump(&["--config", config, "passwd", "user-2003"]);
+    assert!(success);
+    assert_eq!(stdout, "user-2003:x:2003:100::/home/user-2003:/bin/bash\n");
+
+    let (success, stdout) = dump(&["--config", config, "uid", "2009"]);
+    assert!(success);
+    assert_eq!(stdout, "user-2009:x:2009:100::/home/user-2009:/bin/bash\n");
+
+    let (success, stdout) = dump(&["--config", config, "group"]);
+    assert!(success);
+    assert_eq!(stdout, "users:x:100:\n");
+
+    let (success, _) = dump(&["--config", config, "uid", "1000"]);
+    assert!(!success);
+
+    let (success, stdout) = dump(&["--config", config, "describe"]);
+    assert!(success);
+    assert_eq!(
+        stdout,
+        "10 UIDs in 2000..=2009, named user-2000 to user-2009, all with primary group users (100)\n"
+    );
+}
+
+#[test]
+fn dump_not_enumerable() {
+    let config = write_config(
+        "dump_not_enumerable.toml",
+        r#"
+        ranges = [{ start = 2000, end = 2009 }]
+        enumerable = false
+        "#,
+    );
+    let config = config.to_str().unwrap();
+
+    for command in ["all", "passwd", "group"] {
+        let (success, stdout) = dump(&["--config", config, command]);
+        assert!(success);
+        assert!(stdout.is_empty(), "{}: {}", command, stdout);
+    }
+
+    // single entries still resolve
+    let (success, stdout) = dump(&["--config", config, "uid", "2003"]);
+    assert!(success);
+    assert_eq!(stdout, "user-2003:x:2003:2003::/tmp:/bin/bash\n");
+}
+
+#[test]
+fn dump_descending() {
+    let config = write_config(
+        "dump_descending.toml",
+        r#"
+        ranges = [{ start = 2000, end = 2002 }]
+        enumeration_order = "descending"
+        "#,
+    );
+    let (success, stdout) = dump(&["--config", config.to_str().unwrap(), "passwd"]);
+    assert!(success);
+    assert_eq!(
+        stdout,
+        "user-2002:x:2002:2002::/tmp:/bin/bash\n\
+         user-2001:x:2001:2001::/tmp:/bin/bash\n\
+         user-2000:x:2000:2000::/tmp:/bin/bash\n"
+    );
+}
+
+#[test]
+fn dump_stats() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nss-lying-dump"))
+        .args([
+            "--config",
+            "/nonexistent/nss_lying.toml",
+            "--stats",
+            "uid",
+            "1000",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("PasswdByUid: 1 hits, 0 misses"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("GroupByGid: 0 hits, 0 misses"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn dump_metrics() {
+    let (success, stdout) = dump(&["--config", "/nonexistent/nss_lying.toml", "metrics"]);
+    assert!(success);
+    assert!(
+        stdout.starts_with("# HELP nss_lying_lookups_total "),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout
+            .contains("nss_lying_lookups_total{db=\"passwd\",kind=\"by_uid\",result=\"hit\"} 0\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn dump_invalid_config() {
+    let config = write_config("dump_invalid_config.toml", "ranges = ");
+    let (success, stdout) = dump(&["--config", config.to_str().unwrap()]);
+    assert!(!success);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn check_config() {
+    // the other checks depend on how the system is set up, so only the
+    // config check is looked at
+    let config = write_config(
+        "check_config.toml",
+        "ranges = [{ start = 2000, end = 2009 }]",
+    );
+    let (_, stdout) = dump(&["--config", config.to_str().unwrap(), "check"]);
+    assert!(
+        stdout.lines().any(|line| line
+            == "ok   config: 10 UIDs in 2000..=2009, named user-2000 to user-2009, each with their own primary group"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.lines().any(|line| line.contains(" nsswitch: ")));
+    assert!(stdout.lines().any(|line| line.contains(" module: ")));
+
+    let config = write_config(
+        "check_lint_config.toml",
+        "ranges = [{ start = 2000, end = 2009 }]\nexclude_uids = [5000]",
+    );
+    let (_, stdout) = dump(&["--config", config.to_str().unwrap(), "check"]);
+    assert!(
+        stdout.lines().any(|line| {
+            line
+            == "warn lint: excluded UID 5000 is not within any range, so excluding it has no effect"
+        }),
+        "{}",
+        stdout
+    );
+
+    let config = write_config("check_invalid_config.toml", "ranges = ");
+    let (success, stdout) = dump(&["--config", config.to_str().unwrap(), "check"]);
+    assert!(!success);
+    assert!(
+        stdout.lines().any(|line| line.starts_with("FAIL config: ")),
+        "{}",
+        stdout
+    );
+    // the lookup is made against the installed config, not the given one
+    assert!(stdout.lines().any(|line| line.contains(" lookup: ")));
+}
+
+/// Only meaningful once the module is installed and listed in nsswitch.conf
+#[test]
+#[cfg_attr(not(feature = "installed"), ignore)]
+fn check_installed() {
+    let (success, stdout) = dump(&["check"]);
+    assert!(success, "{}", stdout);
+}
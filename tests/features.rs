@@ -0,0 +1,49 @@
+use std::{path::PathBuf, process::Command};
+
+/// Build the module with only the given features, returning its contents
+fn build_module(features: &str) -> Vec<u8> {
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("features");
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            features,
+        ])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "build with features {:?} failed",
+        features
+    );
+    std::fs::read(target_dir.join("debug/libnss_lying.so")).unwrap()
+}
+
+/// Check whether the module contains a NUL-terminated symbol name, which is
+/// only the case if the hook was compiled in
+fn exports(module: &[u8], symbol: &str) -> bool {
+    module
+        .windows(symbol.len() + 1)
+        .any(|window| window[..symbol.len()] == *symbol.as_bytes() && window[symbol.len()] == 0)
+}
+
+#[test]
+fn build_single_database() {
+    let module = build_module("group");
+    assert!(exports(&module, "_nss_lying_getgrgid_r"));
+    assert!(exports(&module, "_nss_lying_initgroups_dyn"));
+    assert!(!exports(&module, "_nss_lying_getpwuid_r"));
+    assert!(!exports(&module, "_nss_lying_getspnam_r"));
+
+    let module = build_module("passwd");
+    assert!(exports(&module, "_nss_lying_getpwuid_r"));
+    assert!(exports(&module, "_nss_lying_getspnam_r"));
+    assert!(!exports(&module, "_nss_lying_getgrgid_r"));
+    assert!(!exports(&module, "_nss_lying_initgroups_dyn"));
+}